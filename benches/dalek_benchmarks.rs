@@ -16,12 +16,15 @@ use criterion::Criterion;
 
 use rand::rngs::OsRng;
 
-use frost_dalek::compute_message_hash;
-use frost_dalek::generate_commitment_share_lists;
-use frost_dalek::DistributedKeyGeneration;
-use frost_dalek::Parameters;
-use frost_dalek::Participant;
-use frost_dalek::SignatureAggregator;
+use frost_secp256k1::compute_message_hash;
+use frost_secp256k1::generate_commitment_share_lists;
+use frost_secp256k1::DistributedKeyGeneration;
+use frost_secp256k1::Parameters;
+use frost_secp256k1::Participant;
+use frost_secp256k1::SignatureAggregator;
+use frost_secp256k1::ThresholdSignature;
+use frost_secp256k1::GroupKey;
+use frost_secp256k1::verify_batch;
 
 mod dkg_benches {
     use super::*;
@@ -155,7 +158,7 @@ mod dkg_benches {
         c.bench_function("Finish", move |b| {
             let pk = p1.public_key().unwrap();
 
-            b.iter(|| p1_state.clone().finish(pk));
+            b.iter(|| p1_state.clone().finish(&pk));
         });
     }
 
@@ -248,11 +251,11 @@ mod sign_benches {
         let p4_state = p4_state.to_round_two(p4_my_secret_shares).unwrap();
         let p5_state = p5_state.to_round_two(p5_my_secret_shares).unwrap();
 
-        let (group_key, p1_sk) = p1_state.finish(p1.public_key().unwrap()).unwrap();
-        let (_, _) = p2_state.finish(p2.public_key().unwrap()).unwrap();
-        let (_, p3_sk) = p3_state.finish(p3.public_key().unwrap()).unwrap();
-        let (_, p4_sk) = p4_state.finish(p4.public_key().unwrap()).unwrap();
-        let (_, _) = p5_state.finish(p5.public_key().unwrap()).unwrap();
+        let (group_key, p1_sk) = p1_state.finish(&p1.public_key().unwrap()).unwrap();
+        let (_, _) = p2_state.finish(&p2.public_key().unwrap()).unwrap();
+        let (_, p3_sk) = p3_state.finish(&p3.public_key().unwrap()).unwrap();
+        let (_, p4_sk) = p4_state.finish(&p4.public_key().unwrap()).unwrap();
+        let (_, _) = p5_state.finish(&p5.public_key().unwrap()).unwrap();
 
         let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
         let message = b"This is a test of the tsunami alert system. This is only a test.";
@@ -260,11 +263,11 @@ mod sign_benches {
         let (p3_public_comshares, _p3_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 3, 1);
         let (p4_public_comshares, _p4_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 4, 1);
 
-        let mut aggregator = SignatureAggregator::new(params, group_key, &context[..], &message[..]);
+        let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-        aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into());
-        aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into()).unwrap();
+        aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
@@ -349,11 +352,11 @@ mod sign_benches {
         let p4_state = p4_state.to_round_two(p4_my_secret_shares).unwrap();
         let p5_state = p5_state.to_round_two(p5_my_secret_shares).unwrap();
 
-        let (group_key, p1_sk) = p1_state.finish(p1.public_key().unwrap()).unwrap();
-        let (_, _) = p2_state.finish(p2.public_key().unwrap()).unwrap();
-        let (_, p3_sk) = p3_state.finish(p3.public_key().unwrap()).unwrap();
-        let (_, p4_sk) = p4_state.finish(p4.public_key().unwrap()).unwrap();
-        let (_, _) = p5_state.finish(p5.public_key().unwrap()).unwrap();
+        let (group_key, p1_sk) = p1_state.finish(&p1.public_key().unwrap()).unwrap();
+        let (_, _) = p2_state.finish(&p2.public_key().unwrap()).unwrap();
+        let (_, p3_sk) = p3_state.finish(&p3.public_key().unwrap()).unwrap();
+        let (_, p4_sk) = p4_state.finish(&p4.public_key().unwrap()).unwrap();
+        let (_, _) = p5_state.finish(&p5.public_key().unwrap()).unwrap();
 
         let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
         let message = b"This is a test of the tsunami alert system. This is only a test.";
@@ -361,11 +364,11 @@ mod sign_benches {
         let (p3_public_comshares, mut p3_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 3, 1);
         let (p4_public_comshares, mut p4_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 4, 1);
 
-        let mut aggregator = SignatureAggregator::new(params, group_key, &context[..], &message[..]);
+        let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-        aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into());
-        aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into()).unwrap();
+        aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
@@ -374,9 +377,9 @@ mod sign_benches {
         let p3_partial = p3_sk.sign(&message_hash, &group_key, &mut p3_secret_comshares, 0, signers).unwrap();
         let p4_partial = p4_sk.sign(&message_hash, &group_key, &mut p4_secret_comshares, 0, signers).unwrap();
 
-        aggregator.include_partial_signature(p1_partial);
-        aggregator.include_partial_signature(p3_partial);
-        aggregator.include_partial_signature(p4_partial);
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p3_partial).unwrap();
+        aggregator.include_partial_signature(p4_partial).unwrap();
 
         let aggregator = aggregator.finalize().unwrap();
 
@@ -460,11 +463,11 @@ mod sign_benches {
         let p4_state = p4_state.to_round_two(p4_my_secret_shares).unwrap();
         let p5_state = p5_state.to_round_two(p5_my_secret_shares).unwrap();
 
-        let (group_key, p1_sk) = p1_state.finish(p1.public_key().unwrap()).unwrap();
-        let (_, _) = p2_state.finish(p2.public_key().unwrap()).unwrap();
-        let (_, p3_sk) = p3_state.finish(p3.public_key().unwrap()).unwrap();
-        let (_, p4_sk) = p4_state.finish(p4.public_key().unwrap()).unwrap();
-        let (_, _) = p5_state.finish(p5.public_key().unwrap()).unwrap();
+        let (group_key, p1_sk) = p1_state.finish(&p1.public_key().unwrap()).unwrap();
+        let (_, _) = p2_state.finish(&p2.public_key().unwrap()).unwrap();
+        let (_, p3_sk) = p3_state.finish(&p3.public_key().unwrap()).unwrap();
+        let (_, p4_sk) = p4_state.finish(&p4.public_key().unwrap()).unwrap();
+        let (_, _) = p5_state.finish(&p5.public_key().unwrap()).unwrap();
 
         let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
         let message = b"This is a test of the tsunami alert system. This is only a test.";
@@ -472,11 +475,11 @@ mod sign_benches {
         let (p3_public_comshares, mut p3_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 3, 1);
         let (p4_public_comshares, mut p4_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 4, 1);
 
-        let mut aggregator = SignatureAggregator::new(params, group_key, &context[..], &message[..]);
+        let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-        aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into());
-        aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into()).unwrap();
+        aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
@@ -485,9 +488,9 @@ mod sign_benches {
         let p3_partial = p3_sk.sign(&message_hash, &group_key, &mut p3_secret_comshares, 0, signers).unwrap();
         let p4_partial = p4_sk.sign(&message_hash, &group_key, &mut p4_secret_comshares, 0, signers).unwrap();
 
-        aggregator.include_partial_signature(p1_partial);
-        aggregator.include_partial_signature(p3_partial);
-        aggregator.include_partial_signature(p4_partial);
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p3_partial).unwrap();
+        aggregator.include_partial_signature(p4_partial).unwrap();
 
         let aggregator = aggregator.finalize().unwrap();
         let threshold_signature = aggregator.aggregate().unwrap();
@@ -507,7 +510,75 @@ mod sign_benches {
     }
 }
 
+mod batch_benches {
+    use super::*;
+
+    use criterion::BenchmarkId;
+
+    const BATCH_SIZES: [usize; 4] = [8, 64, 256, 1024];
+
+    /// Have a single signer make `count` signatures on different messages.
+    fn make_signatures(count: usize) -> (GroupKey, Vec<(ThresholdSignature, [u8; 32])>) {
+        let params = Parameters { n: 1, t: 1 };
+
+        let (p1, p1coeffs) = Participant::new(&params, 1);
+        let p1_state = DistributedKeyGeneration::<_>::new(&params, &p1.index, &p1coeffs, &mut Vec::new()).unwrap();
+        let p1_state = p1_state.to_round_two(Vec::new()).unwrap();
+        let (group_key, p1_sk) = p1_state.finish(&p1.public_key().unwrap()).unwrap();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let mut signatures = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let message = format!("Bridge transfer number {}", i).into_bytes();
+            let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+
+            let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.clone());
+
+            aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+
+            let signers = aggregator.get_signers();
+            let message_hash = compute_message_hash(&context[..], &message[..]);
+            let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
+
+            aggregator.include_partial_signature(p1_partial).unwrap();
+
+            let aggregator = aggregator.finalize().unwrap();
+
+            signatures.push((aggregator.aggregate().unwrap(), message_hash));
+        }
+        (group_key, signatures)
+    }
+
+    fn verify_many(c: &mut Criterion) {
+        let mut group = c.benchmark_group("Signature verification of many signatures");
+        let (group_key, signatures) = make_signatures(*BATCH_SIZES.iter().max().unwrap());
+
+        for size in BATCH_SIZES.iter() {
+            let batch: Vec<(&ThresholdSignature, &[u8; 32], &GroupKey)> = signatures[..*size].iter()
+                .map(|(signature, message_hash)| (signature, message_hash, &group_key))
+                .collect();
+
+            group.bench_with_input(BenchmarkId::new("Individual", size), &batch, |b, batch| {
+                b.iter(|| batch.iter().all(|(signature, message_hash, group_key)| signature.verify(group_key, message_hash).is_ok()));
+            });
+            group.bench_with_input(BenchmarkId::new("Batch", size), &batch, |b, batch| {
+                b.iter(|| verify_batch(&mut OsRng, batch));
+            });
+        }
+        group.finish();
+    }
+
+    criterion_group! {
+        name = batch_benches;
+        config = Criterion::default().sample_size(10);
+        targets =
+            verify_many,
+    }
+}
+
 criterion_main!(
     dkg_benches::dkg_benches,
     sign_benches::sign_benches,
+    batch_benches::batch_benches,
 );
@@ -0,0 +1,421 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Solidity ABI encoding of bridge payloads.
+//!
+//! A contract which checks a threshold signature usually recomputes the
+//! signed digest as `keccak256(abi.encode(...))` or
+//! `keccak256(abi.encodePacked(...))` of the payload's fields.  Unless the
+//! signers hashed exactly the same bytes, their perfectly valid
+//! [`ThresholdSignature`] is rejected, so the payload should be built from
+//! typed [`Token`]s and hashed with [`encode_hash`] or [`encode_packed_hash`],
+//! and the digest signed as is with [`SignatureAggregator::new_prehashed`].
+//!
+//! See the [Solidity ABI specification] for the encodings.
+//!
+//! [`ThresholdSignature`]: crate::signature::ThresholdSignature
+//! [`SignatureAggregator::new_prehashed`]: crate::signature::SignatureAggregator::new_prehashed
+//! [Solidity ABI specification]: https://docs.soliditylang.org/en/latest/abi-spec.html
+
+use std::string::String;
+use std::string::ToString;
+use std::vec::Vec;
+
+use sha3::Digest;
+use sha3::Keccak256;
+
+/// A typed Solidity value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Token {
+    /// An `address`.
+    Address([u8; 20]),
+    /// A `uint<M>` of the given number of bits, as a big-endian 32-byte word.
+    Uint(usize, [u8; 32]),
+    /// An `int<M>` of the given number of bits, as a big-endian two's
+    /// complement 32-byte word.
+    Int(usize, [u8; 32]),
+    /// A `bool`.
+    Bool(bool),
+    /// A `bytes<M>`, for \\(0 < M \le 32\\).
+    FixedBytes(Vec<u8>),
+    /// A dynamic `bytes`.
+    Bytes(Vec<u8>),
+    /// A `string`.
+    String(String),
+    /// A fixed size array `T[k]`.
+    FixedArray(Vec<Token>),
+    /// A dynamic size array `T[]`.
+    Array(Vec<Token>),
+}
+
+impl Token {
+    /// A `uint<bits>`, e.g. a `uint256` with `bits` = 256.
+    pub fn uint(bits: usize, value: u128) -> Token {
+        let mut word = [0u8; 32];
+
+        word[16..].copy_from_slice(&value.to_be_bytes());
+        Token::Uint(bits, word)
+    }
+
+    /// An `int<bits>`, e.g. an `int16` with `bits` = 16.
+    pub fn int(bits: usize, value: i128) -> Token {
+        let mut word = if value < 0 { [0xffu8; 32] } else { [0u8; 32] };
+
+        word[16..].copy_from_slice(&value.to_be_bytes());
+        Token::Int(bits, word)
+    }
+
+    /// A `bytes32`.
+    pub fn bytes32(value: [u8; 32]) -> Token {
+        Token::FixedBytes(value.to_vec())
+    }
+
+    /// A `string`.
+    pub fn string(value: &str) -> Token {
+        Token::String(value.to_string())
+    }
+
+    /// The Solidity name of this token's type, e.g. `uint256` or `bytes32[]`.
+    ///
+    /// The element type of an empty array is unknown, and given as `()`.
+    pub fn type_name(&self) -> String {
+        let element_type = |elements: &Vec<Token>| elements.first().map_or("()".to_string(), |e| e.type_name());
+
+        match self {
+            Token::Address(_) => "address".to_string(),
+            Token::Uint(bits, _) => format!("uint{}", bits),
+            Token::Int(bits, _) => format!("int{}", bits),
+            Token::Bool(_) => "bool".to_string(),
+            Token::FixedBytes(bytes) => format!("bytes{}", bytes.len()),
+            Token::Bytes(_) => "bytes".to_string(),
+            Token::String(_) => "string".to_string(),
+            Token::FixedArray(elements) => format!("{}[{}]", element_type(elements), elements.len()),
+            Token::Array(elements) => format!("{}[]", element_type(elements)),
+        }
+    }
+
+    /// Whether this token is of a dynamic type, which is encoded out-of-place.
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Token::Bytes(_) | Token::String(_) | Token::Array(_) => true,
+            Token::FixedArray(elements) => elements.iter().any(|e| e.is_dynamic()),
+            _ => false,
+        }
+    }
+
+    /// Check that this token is a well-formed value of its type.
+    fn check(&self) -> Result<(), &'static str> {
+        match self {
+            Token::Uint(bits, word) | Token::Int(bits, word) => {
+                if *bits == 0 || *bits > 256 || bits % 8 != 0 {
+                    return Err("Invalid integer size");
+                }
+
+                // Everything above the value's bits must be the extension of
+                // its top bit, which is always zero for an unsigned value.
+                let sign = match self {
+                    Token::Int(..) if word[32 - bits / 8] & 0x80 != 0 => 0xff,
+                    _ => 0x00,
+                };
+
+                if word[..32 - bits / 8].iter().any(|b| *b != sign) {
+                    return Err("Integer out of range");
+                }
+                Ok(())
+            },
+            Token::FixedBytes(bytes) if bytes.is_empty() || bytes.len() > 32 => Err("Invalid fixed bytes size"),
+            Token::FixedArray(elements) | Token::Array(elements) => {
+                for element in elements.iter() {
+                    element.check()?;
+
+                    if element.type_name() != elements[0].type_name() {
+                        return Err("Array elements must have the same type");
+                    }
+                }
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Append the standard encoding of this token to `out`.
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Token::Address(address) => {
+                out.extend_from_slice(&[0u8; 12]);
+                out.extend_from_slice(address);
+            },
+            Token::Uint(_, word) | Token::Int(_, word) => out.extend_from_slice(word),
+            Token::Bool(value) => out.extend_from_slice(&word_from_usize(*value as usize)),
+            Token::FixedBytes(bytes) => extend_padded(out, bytes),
+            Token::Bytes(bytes) => {
+                out.extend_from_slice(&word_from_usize(bytes.len()));
+                extend_padded(out, bytes);
+            },
+            Token::String(string) => {
+                out.extend_from_slice(&word_from_usize(string.len()));
+                extend_padded(out, string.as_bytes());
+            },
+            Token::FixedArray(elements) => encode_sequence_into(elements, out),
+            Token::Array(elements) => {
+                out.extend_from_slice(&word_from_usize(elements.len()));
+                encode_sequence_into(elements, out);
+            },
+        }
+    }
+
+    /// Append the non-standard packed encoding of this token to `out`.
+    fn encode_packed_into(&self, out: &mut Vec<u8>) -> Result<(), &'static str> {
+        match self {
+            Token::Address(address) => out.extend_from_slice(address),
+            Token::Uint(bits, word) | Token::Int(bits, word) => out.extend_from_slice(&word[32 - bits / 8..]),
+            Token::Bool(value) => out.push(*value as u8),
+            Token::FixedBytes(bytes) | Token::Bytes(bytes) => out.extend_from_slice(bytes),
+            Token::String(string) => out.extend_from_slice(string.as_bytes()),
+            // The elements of an array are padded, as in the standard encoding.
+            Token::FixedArray(elements) | Token::Array(elements) => {
+                for element in elements.iter() {
+                    if element.is_dynamic() {
+                        return Err("Arrays of dynamic types cannot be packed");
+                    }
+                    element.encode_into(out);
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Encode a `usize` as a big-endian 32-byte word.
+fn word_from_usize(value: usize) -> [u8; 32] {
+    let mut word = [0u8; 32];
+
+    word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+    word
+}
+
+/// Append `bytes` to `out`, right-padded with zeroes to a multiple of 32 bytes.
+fn extend_padded(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes);
+    out.resize(out.len() + (32 - bytes.len() % 32) % 32, 0);
+}
+
+/// Append the standard encoding of a sequence of `tokens`, i.e. the heads of
+/// all of them followed by the tails of the dynamic ones, to `out`.
+fn encode_sequence_into(tokens: &[Token], out: &mut Vec<u8>) {
+    let mut heads: Vec<u8> = Vec::new();
+    let mut tails: Vec<u8> = Vec::new();
+    let heads_len: usize = tokens.iter().map(|t| match t.is_dynamic() {
+        true => 32,
+        false => {
+            let mut encoded = Vec::new();
+
+            t.encode_into(&mut encoded);
+            encoded.len()
+        },
+    }).sum();
+
+    for token in tokens.iter() {
+        if token.is_dynamic() {
+            heads.extend_from_slice(&word_from_usize(heads_len + tails.len()));
+            token.encode_into(&mut tails);
+        } else {
+            token.encode_into(&mut heads);
+        }
+    }
+
+    out.extend_from_slice(&heads);
+    out.extend_from_slice(&tails);
+}
+
+/// Compute `abi.encode(tokens...)`.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is the encoding, otherwise a string describing
+/// which token is malformed.
+pub fn encode(tokens: &[Token]) -> Result<Vec<u8>, &'static str> {
+    let mut encoded = Vec::new();
+
+    for token in tokens.iter() {
+        token.check()?;
+    }
+    encode_sequence_into(tokens, &mut encoded);
+
+    Ok(encoded)
+}
+
+/// Compute `abi.encodePacked(tokens...)`.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is the encoding, otherwise a string describing
+/// which token is malformed or cannot be packed.
+pub fn encode_packed(tokens: &[Token]) -> Result<Vec<u8>, &'static str> {
+    let mut encoded = Vec::new();
+
+    for token in tokens.iter() {
+        token.check()?;
+        token.encode_packed_into(&mut encoded)?;
+    }
+
+    Ok(encoded)
+}
+
+/// Compute `keccak256(abi.encode(tokens...))`, e.g. to be signed with
+/// [`SignatureAggregator::new_prehashed`].
+///
+/// [`SignatureAggregator::new_prehashed`]: crate::signature::SignatureAggregator::new_prehashed
+pub fn encode_hash(tokens: &[Token]) -> Result<[u8; 32], &'static str> {
+    Ok(Keccak256::digest(encode(tokens)?).into())
+}
+
+/// Compute `keccak256(abi.encodePacked(tokens...))`, e.g. to be signed with
+/// [`SignatureAggregator::new_prehashed`].
+///
+/// [`SignatureAggregator::new_prehashed`]: crate::signature::SignatureAggregator::new_prehashed
+pub fn encode_packed_hash(tokens: &[Token]) -> Result<[u8; 32], &'static str> {
+    Ok(Keccak256::digest(encode_packed(tokens)?).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The examples from the Solidity ABI specification.
+
+    #[test]
+    fn encode_static_and_dynamic_arguments() {
+        // f(uint256,uint32[],bytes10,bytes) with
+        // (0x123, [0x456, 0x789], "1234567890", "Hello, world!")
+        let tokens = [
+            Token::uint(256, 0x123),
+            Token::Array(vec![Token::uint(32, 0x456), Token::uint(32, 0x789)]),
+            Token::FixedBytes(b"1234567890".to_vec()),
+            Token::Bytes(b"Hello, world!".to_vec()),
+        ];
+
+        assert_eq!(hex::encode(encode(&tokens).unwrap()), concat!(
+            "0000000000000000000000000000000000000000000000000000000000000123",
+            "0000000000000000000000000000000000000000000000000000000000000080",
+            "3132333435363738393000000000000000000000000000000000000000000000",
+            "00000000000000000000000000000000000000000000000000000000000000e0",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000456",
+            "0000000000000000000000000000000000000000000000000000000000000789",
+            "000000000000000000000000000000000000000000000000000000000000000d",
+            "48656c6c6f2c20776f726c642100000000000000000000000000000000000000",
+        ));
+    }
+
+    #[test]
+    fn encode_dynamic_bytes_and_arrays() {
+        // sam(bytes,bool,uint256[]) with ("dave", true, [1, 2, 3])
+        let tokens = [
+            Token::Bytes(b"dave".to_vec()),
+            Token::Bool(true),
+            Token::Array(vec![Token::uint(256, 1), Token::uint(256, 2), Token::uint(256, 3)]),
+        ];
+
+        assert_eq!(hex::encode(encode(&tokens).unwrap()), concat!(
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "0000000000000000000000000000000000000000000000000000000000000004",
+            "6461766500000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+        ));
+    }
+
+    #[test]
+    fn encode_nested_dynamic_arrays() {
+        // g(uint256[][],string[]) with ([[1, 2], [3]], ["one", "two", "three"])
+        let tokens = [
+            Token::Array(vec![
+                Token::Array(vec![Token::uint(256, 1), Token::uint(256, 2)]),
+                Token::Array(vec![Token::uint(256, 3)]),
+            ]),
+            Token::Array(vec![Token::string("one"), Token::string("two"), Token::string("three")]),
+        ];
+
+        assert_eq!(hex::encode(encode(&tokens).unwrap()), concat!(
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "0000000000000000000000000000000000000000000000000000000000000140",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "00000000000000000000000000000000000000000000000000000000000000e0",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "6f6e650000000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "74776f0000000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000005",
+            "7468726565000000000000000000000000000000000000000000000000000000",
+        ));
+    }
+
+    #[test]
+    fn encode_packed_example() {
+        // abi.encodePacked(int16(-1), bytes1(0x42), uint16(0x03), string("Hello, world!"))
+        let tokens = [
+            Token::int(16, -1),
+            Token::FixedBytes(vec![0x42]),
+            Token::uint(16, 0x03),
+            Token::string("Hello, world!"),
+        ];
+
+        assert_eq!(hex::encode(encode_packed(&tokens).unwrap()),
+                   "ffff42000348656c6c6f2c20776f726c6421");
+    }
+
+    #[test]
+    fn encode_packed_bridge_payload() {
+        let recipient = [0x11u8; 20];
+        let tokens = [
+            Token::Address(recipient),
+            Token::uint(256, 1_000_000),
+            Token::bytes32([0x22u8; 32]),
+            Token::Array(vec![Token::uint(8, 1), Token::uint(8, 2)]),
+        ];
+        let packed = encode_packed(&tokens).unwrap();
+
+        // Addresses and fixed-size values are unpadded, but array elements
+        // are padded to 32 bytes each.
+        assert_eq!(packed.len(), 20 + 32 + 32 + 2 * 32);
+        assert_eq!(&packed[..20], &recipient);
+        assert_eq!(packed[20 + 32 + 32 + 31], 1);
+        assert_eq!(encode_packed_hash(&tokens).unwrap(), <[u8; 32]>::from(Keccak256::digest(&packed)));
+        assert_eq!(encode_hash(&tokens).unwrap(), <[u8; 32]>::from(Keccak256::digest(encode(&tokens).unwrap())));
+    }
+
+    #[test]
+    fn malformed_tokens() {
+        assert_eq!(encode(&[Token::uint(7, 1)]), Err("Invalid integer size"));
+        assert_eq!(encode(&[Token::uint(8, 256)]), Err("Integer out of range"));
+        assert_eq!(encode(&[Token::int(8, -129)]), Err("Integer out of range"));
+        assert!(encode(&[Token::int(8, -128)]).is_ok());
+        assert_eq!(encode(&[Token::FixedBytes(vec![0u8; 33])]), Err("Invalid fixed bytes size"));
+        assert_eq!(encode(&[Token::Array(vec![Token::uint(8, 1), Token::uint(16, 1)])]),
+                   Err("Array elements must have the same type"));
+        assert_eq!(encode_packed(&[Token::Array(vec![Token::string("one")])]),
+                   Err("Arrays of dynamic types cannot be packed"));
+        assert_eq!(Token::Array(vec![Token::bytes32([0u8; 32])]).type_name(), "bytes32[]");
+    }
+}
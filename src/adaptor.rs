@@ -0,0 +1,679 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Threshold adaptor signatures, for atomic swaps.
+//!
+//! Given an adaptor point \\(T = tG\\), the signers make an
+//! [`AdaptorSignature`] (a "pre-signature") on a message, which anyone can
+//! check with [`AdaptorSignature::verify`], but which only becomes a valid
+//! signature once it is [adapted](AdaptorSignature::adapt) with the secret
+//! \\(t\\).  Conversely, anyone who sees both the pre-signature and the
+//! completed signature can [extract](AdaptorSignature::extract) \\(t\\).
+//!
+//! This works by signing with the group commitment \\(R' = R + T\\) in place
+//! of \\(R\\) in the challenge, so that the pre-signature \\(\hat{z}\\) is
+//! missing exactly \\(t\\), i.e. \\(z = \hat{z} + t\\).  For
+//! [`AdaptorScheme::Bip340`], \\(R'\\) is normalised to an even y-coordinate
+//! rather than \\(R\\), so the secret is added to or subtracted from
+//! \\(\hat{z}\\) according to the parity of \\(R'\\).
+//!
+//! An aggregator is set up for an adaptor signature with
+//! [`SignatureAggregator::set_adaptor`] before any partial signatures are
+//! included, after which it only takes partial signatures from
+//! [`SecretKey::sign_adaptor`] and only aggregates with
+//! [`SignatureAggregator::aggregate_adaptor`].
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::elliptic_curve::ops::LinearCombination;
+
+use serde::{Deserialize, Serialize};
+
+use zeroize::Zeroize;
+
+use crate::bip340::bip340_partial_signature_is_valid;
+use crate::bip340::compute_bip340_challenge;
+use crate::bip340::has_even_y;
+use crate::bip340::normalising_sign;
+use crate::bip340::Bip340Signature;
+use crate::keygen::GroupKey;
+use crate::keygen::IndividualPublicKey;
+use crate::keygen::SecretKey;
+use crate::precomputation::SecretCommitmentShareList;
+use crate::signature::calculate_lagrange_coefficients;
+use crate::signature::compute_binding_factors_and_group_commitment;
+use crate::signature::compute_challenge;
+use crate::signature::partial_signature_is_valid;
+use crate::signature::Finalized;
+use crate::signature::Initial;
+use crate::signature::PartialThresholdSignature;
+use crate::signature::SignatureAggregator;
+use crate::signature::Signer;
+use crate::signature::SignerRs;
+use crate::signature::ThresholdSignature;
+
+/// Which kind of signature an [`AdaptorSignature`] completes to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AdaptorScheme {
+    /// A [`ThresholdSignature`], for the EthSchnorr verifier.
+    EthSchnorr,
+    /// A [`Bip340Signature`].
+    Bip340,
+}
+
+/// Compute each signer's commitment \\(R\_i\\), the adapted group commitment
+/// \\(R' = R + T\\), and the challenge on \\(R'\\) for the given `scheme`.
+#[allow(clippy::type_complexity)]
+fn adapted_commitment_and_challenge(
+    scheme: AdaptorScheme,
+    message_hash: &[u8; 32],
+    group_key: &GroupKey,
+    adaptor_point: &AffinePoint,
+    signers: &[Signer],
+) -> Result<(HashMap<u32, Scalar>, SignerRs, AffinePoint, Scalar), &'static str>
+{
+    if *adaptor_point == AffinePoint::IDENTITY {
+        return Err("Adaptor point is the identity");
+    }
+
+    let (binding_factors, Rs) = compute_binding_factors_and_group_commitment(message_hash, signers);
+    let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+    let R_adapted = (R + adaptor_point).to_affine();
+
+    if R_adapted == AffinePoint::IDENTITY {
+        return Err("Adapted group commitment is the identity");
+    }
+
+    let c = match scheme {
+        AdaptorScheme::EthSchnorr => compute_challenge(message_hash, group_key, &R_adapted),
+        AdaptorScheme::Bip340 => compute_bip340_challenge(message_hash, &group_key.0, &R_adapted),
+    };
+
+    Ok((binding_factors, Rs, R_adapted, c))
+}
+
+/// Check a partial adaptor signature \\(z\_i\\) against the signer's public
+/// key share \\(Y\_i\\) and commitment \\(R\_i\\).
+#[allow(clippy::too_many_arguments)]
+fn adaptor_partial_signature_is_valid(
+    scheme: AdaptorScheme,
+    z: &Scalar,
+    Y_i: &AffinePoint,
+    R_i: &AffinePoint,
+    challenge: &Scalar,
+    lambda: &Scalar,
+    R_adapted: &AffinePoint,
+    group_key: &GroupKey,
+) -> bool
+{
+    match scheme {
+        AdaptorScheme::EthSchnorr => partial_signature_is_valid(z, Y_i, R_i, challenge, lambda),
+        AdaptorScheme::Bip340 => bip340_partial_signature_is_valid(z, Y_i, R_i, challenge, lambda,
+                                                                   &normalising_sign(R_adapted),
+                                                                   &normalising_sign(&group_key.0)),
+    }
+}
+
+/// A threshold pre-signature, which becomes a complete signature once adapted
+/// with the discrete logarithm of its adaptor point.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdaptorSignature {
+    /// The kind of signature this completes to.
+    pub(crate) scheme: AdaptorScheme,
+    /// The adaptor point \\(T\\).
+    pub(crate) adaptor_point: AffinePoint,
+    /// The adapted group commitment \\(R' = R + T\\), not normalised.
+    pub(crate) R: AffinePoint,
+    /// The pre-signature \\(\hat{z}\\).
+    pub(crate) z: Scalar,
+}
+
+impl AdaptorSignature {
+    /// The kind of signature this completes to.
+    pub fn scheme(&self) -> AdaptorScheme {
+        self.scheme
+    }
+
+    /// The adaptor point \\(T\\).
+    pub fn adaptor_point(&self) -> &AffinePoint {
+        &self.adaptor_point
+    }
+
+    /// The sign, \\(\pm 1\\), with which the secret \\(t\\) enters the
+    /// completed signature.
+    fn secret_sign(&self) -> Scalar {
+        match self.scheme {
+            AdaptorScheme::EthSchnorr => Scalar::ONE,
+            AdaptorScheme::Bip340 => normalising_sign(&self.R),
+        }
+    }
+
+    /// Verify this pre-signature on a `message_hash` under the `group_key`,
+    /// i.e. that adapting it with the discrete logarithm of its adaptor point
+    /// gives a valid signature.
+    ///
+    /// For [`AdaptorScheme::EthSchnorr`] this checks that
+    /// \\(\hat{z} G + c Y = R' - T\\), and for [`AdaptorScheme::Bip340`] that
+    /// \\(\hat{z} G = \pm (R' - T) + c Y\\), with the sign given by the parity
+    /// of \\(R'\\) and \\(Y\\) taken in its even-Y form.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the pre-signature is
+    /// valid, otherwise a string describing why it is not.
+    pub fn verify(&self, group_key: &GroupKey, message_hash: &[u8; 32]) -> Result<(), &'static str> {
+        if self.adaptor_point == AffinePoint::IDENTITY {
+            return Err("Adaptor point is the identity");
+        }
+
+        let R = ProjectivePoint::from(self.R) - self.adaptor_point;
+        let valid = match self.scheme {
+            AdaptorScheme::EthSchnorr => {
+                let c = compute_challenge(message_hash, group_key, &self.R);
+                let check = ProjectivePoint::lincomb(&ProjectivePoint::GENERATOR, &self.z, &group_key.0.into(), &c);
+
+                check.to_affine() == R.to_affine()
+            },
+            AdaptorScheme::Bip340 => {
+                let Y = match has_even_y(&group_key.0) {
+                    true => group_key.0,
+                    false => -group_key.0,
+                };
+                let c = compute_bip340_challenge(message_hash, &Y, &self.R);
+                let check = ProjectivePoint::lincomb(&R, &self.secret_sign(), &Y.into(), &c);
+
+                (ProjectivePoint::GENERATOR * self.z).to_affine() == check.to_affine()
+            },
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err("Invalid adaptor signature"),
+        }
+    }
+
+    /// Compute the completed \\(z = \hat{z} \pm t\\), after checking that the
+    /// `secret` \\(t\\) is the discrete logarithm of the adaptor point.
+    fn adapted_z(&self, scheme: AdaptorScheme, secret: &Scalar) -> Result<Scalar, &'static str> {
+        if self.scheme != scheme {
+            return Err("Adaptor signature is for a different scheme");
+        }
+        if (ProjectivePoint::GENERATOR * secret).to_affine() != self.adaptor_point {
+            return Err("Secret does not match the adaptor point");
+        }
+
+        Ok(self.z + (self.secret_sign() * secret))
+    }
+
+    /// Complete an [`AdaptorScheme::EthSchnorr`] pre-signature into a
+    /// [`ThresholdSignature`] with the discrete logarithm `secret` of the
+    /// adaptor point.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the [`ThresholdSignature`], which is
+    /// valid if this pre-signature was, otherwise a string describing the error.
+    pub fn adapt(&self, secret: &Scalar) -> Result<ThresholdSignature, &'static str> {
+        let z = self.adapted_z(AdaptorScheme::EthSchnorr, secret)?;
+
+        Ok(ThresholdSignature { R: self.R, z })
+    }
+
+    /// Complete an [`AdaptorScheme::Bip340`] pre-signature into a
+    /// [`Bip340Signature`] with the discrete logarithm `secret` of the
+    /// adaptor point.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the [`Bip340Signature`], which is valid if
+    /// this pre-signature was, otherwise a string describing the error.
+    pub fn adapt_bip340(&self, secret: &Scalar) -> Result<Bip340Signature, &'static str> {
+        let z = self.adapted_z(AdaptorScheme::Bip340, secret)?;
+        let R = match has_even_y(&self.R) {
+            true => self.R,
+            false => -self.R,
+        };
+
+        Ok(Bip340Signature { R, z })
+    }
+
+    /// Check the recovered `secret`, and the pre-signature's scheme, before
+    /// returning it.
+    fn extracted(&self, scheme: AdaptorScheme, secret: Scalar) -> Result<Scalar, &'static str> {
+        if self.scheme != scheme {
+            return Err("Adaptor signature is for a different scheme");
+        }
+        match (ProjectivePoint::GENERATOR * secret).to_affine() == self.adaptor_point {
+            true => Ok(secret),
+            false => Err("Signature was not adapted from this adaptor signature"),
+        }
+    }
+
+    /// Extract the discrete logarithm of the adaptor point from a
+    /// [`ThresholdSignature`] which was adapted from this pre-signature.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the secret \\(t\\), otherwise a string
+    /// describing the error.
+    pub fn extract(&self, signature: &ThresholdSignature) -> Result<Scalar, &'static str> {
+        if signature.R != self.R {
+            return Err("Signature was not adapted from this adaptor signature");
+        }
+        self.extracted(AdaptorScheme::EthSchnorr, signature.z - self.z)
+    }
+
+    /// Extract the discrete logarithm of the adaptor point from a
+    /// [`Bip340Signature`] which was adapted from this pre-signature.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the secret \\(t\\), otherwise a string
+    /// describing the error.
+    pub fn extract_bip340(&self, signature: &Bip340Signature) -> Result<Scalar, &'static str> {
+        self.extracted(AdaptorScheme::Bip340, self.secret_sign() * (signature.z - self.z))
+    }
+}
+
+impl SecretKey {
+    /// Compute an individual signer's [`PartialThresholdSignature`]
+    /// contribution to an [`AdaptorSignature`] on a `message_hash`, for the
+    /// given `adaptor_point`.
+    ///
+    /// The remaining inputs are the same as for [`SecretKey::sign`], and the
+    /// same warnings apply.  The resulting partial signature must be given to
+    /// [`SignatureAggregator::include_adaptor_partial_signature`] and
+    /// [`SignatureAggregator::aggregate_adaptor`].
+    ///
+    /// # Returns
+    ///
+    /// A Result whose `Ok` value contains a [`PartialThresholdSignature`], which
+    /// should be sent to the [`SignatureAggregator`].  Otherwise, its `Err` value contains
+    /// a string describing the error which occurred.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sign_adaptor(
+        &self,
+        scheme: AdaptorScheme,
+        message_hash: &[u8; 32],
+        adaptor_point: &AffinePoint,
+        group_key: &GroupKey,
+        my_secret_commitment_share_list: &mut SecretCommitmentShareList,
+        my_commitment_share_index: usize,
+        signers: &[Signer],
+    ) -> Result<PartialThresholdSignature, &'static str>
+    {
+        if my_commitment_share_index + 1 > my_secret_commitment_share_list.commitments.len() {
+            return Err("Commitment share index out of bounds");
+        }
+
+        let (binding_factors, _, R_adapted, challenge) =
+            adapted_commitment_and_challenge(scheme, message_hash, group_key, adaptor_point, signers)?;
+        let my_binding_factor = binding_factors.get(&self.index).ok_or("Could not compute our blinding factor")?;
+        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda: Scalar = calculate_lagrange_coefficients(&self.index, &all_participant_indices)?;
+        let my_commitment_share = my_secret_commitment_share_list.commitments[my_commitment_share_index].clone();
+
+        let mut nonce = my_commitment_share.hiding.nonce + (my_commitment_share.binding.nonce * my_binding_factor);
+        let mut key = self.key;
+
+        let z = match scheme {
+            AdaptorScheme::EthSchnorr => nonce - (lambda * key * challenge),
+            AdaptorScheme::Bip340 => {
+                // As in SecretKey::sign_bip340(), but normalising R + T rather than R.
+                if !has_even_y(&R_adapted) {
+                    nonce = -nonce;
+                }
+                if !has_even_y(&group_key.0) {
+                    key = -key;
+                }
+                nonce + (lambda * key * challenge)
+            },
+        };
+
+        nonce.zeroize();
+        key.zeroize();
+
+        // Zero out our secrets from memory to prevent nonce reuse.
+        my_secret_commitment_share_list.drop_share(my_commitment_share);
+
+        Ok(PartialThresholdSignature { index: self.index, z })
+    }
+}
+
+impl PartialThresholdSignature {
+    /// Verify this [`PartialThresholdSignature`], which was created with
+    /// [`SecretKey::sign_adaptor`], independently of any [`SignatureAggregator`].
+    ///
+    /// The inputs are the same as for [`PartialThresholdSignature::verify`],
+    /// with the `scheme` and `adaptor_point` which were signed for.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the partial signature
+    /// is valid, otherwise a string describing why it is not.
+    pub fn verify_adaptor(
+        &self,
+        scheme: AdaptorScheme,
+        group_key: &GroupKey,
+        message_hash: &[u8; 32],
+        adaptor_point: &AffinePoint,
+        signers: &[Signer],
+        public_key: &IndividualPublicKey,
+    ) -> Result<(), &'static str>
+    {
+        if public_key.index != self.index {
+            return Err("Public key does not belong to the signer of this partial signature");
+        }
+
+        let (_, Rs, R_adapted, c) =
+            adapted_commitment_and_challenge(scheme, message_hash, group_key, adaptor_point, signers)?;
+        let R_i = Rs.get(&self.index).ok_or("Partial signature is not from one of the signers")?;
+        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda = calculate_lagrange_coefficients(&self.index, &all_participant_indices)?;
+
+        match adaptor_partial_signature_is_valid(scheme, &self.z, &public_key.share, R_i, &c, &lambda,
+                                                 &R_adapted, group_key) {
+            true => Ok(()),
+            false => Err("Incorrect partial signature"),
+        }
+    }
+}
+
+impl SignatureAggregator<Initial> {
+    /// Set this aggregator up to make an [`AdaptorSignature`] for the `scheme`
+    /// and `adaptor_point`, rather than an ordinary signature.
+    ///
+    /// This must be done before any partial signatures are included, and
+    /// can't be undone.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the aggregator was set
+    /// up, otherwise a string describing the error.
+    pub fn set_adaptor(&mut self, scheme: AdaptorScheme, adaptor_point: AffinePoint) -> Result<(), &'static str> {
+        if adaptor_point == AffinePoint::IDENTITY {
+            return Err("Adaptor point is the identity");
+        }
+        if self.state.adaptor.is_some() {
+            return Err("Adaptor was already set");
+        }
+        if !self.state.partial_signatures.0.is_empty() {
+            return Err("Cannot set the adaptor after partial signatures");
+        }
+
+        self.state.adaptor = Some((scheme, adaptor_point));
+
+        Ok(())
+    }
+
+    /// The scheme and adaptor point which this aggregator was set up for with
+    /// [`SignatureAggregator::set_adaptor`], if any.
+    pub fn adaptor(&self) -> Option<(AdaptorScheme, AffinePoint)> {
+        self.state.adaptor
+    }
+
+    /// Add a [`PartialThresholdSignature`], which was created with
+    /// [`SecretKey::sign_adaptor`] for the aggregator's scheme and adaptor
+    /// point, to be included in the aggregation.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the partial signature
+    /// was included, otherwise a string describing why it was rejected.
+    pub fn include_adaptor_partial_signature(
+        &mut self,
+        partial_signature: PartialThresholdSignature,
+    ) -> Result<(), &'static str>
+    {
+        let (scheme, adaptor_point) = self.state.adaptor.ok_or("Aggregator is not set up for an adaptor signature")?;
+        let public_key = self.expected_partial_signer(&partial_signature)?;
+        let message_hash = *self.message_hash();
+        let signers = self.get_signers().clone();
+
+        partial_signature.verify_adaptor(scheme, &self.state.group_key, &message_hash, &adaptor_point,
+                                         &signers, &public_key)?;
+
+        self.state.partial_signatures.insert(&partial_signature.index, partial_signature.z);
+
+        Ok(())
+    }
+}
+
+impl SignatureAggregator<Finalized> {
+    /// Aggregate a set of previously-collected partial signatures, which were
+    /// created with [`SecretKey::sign_adaptor`] for the aggregator's scheme
+    /// and adaptor point, into an [`AdaptorSignature`].
+    ///
+    /// # Returns
+    ///
+    /// A Result whose Ok() value is an [`AdaptorSignature`], otherwise a
+    /// `Hashmap<u32, &'static str>` containing the participant indices of the misbehaving
+    /// signers and a description of their misbehaviour.  As with
+    /// [`SignatureAggregator::aggregate`], a key of `0` blames the aggregator,
+    /// e.g. if it was not set up with [`SignatureAggregator::set_adaptor`], or
+    /// a group key or adaptor point which cannot be used.
+    pub fn aggregate_adaptor(&self) -> Result<AdaptorSignature, HashMap<u32, &'static str>> {
+        let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+        let (scheme, adaptor_point) = match self.state.adaptor {
+            Some(adaptor) => adaptor,
+            None => {
+                misbehaving_participants.insert(0, "Aggregator is not set up for an adaptor signature");
+                return Err(misbehaving_participants);
+            },
+        };
+        let message_hash = self.message_hash();
+        let group_key = &self.state.group_key;
+
+        let (_, Rs, R_adapted, c) =
+            match adapted_commitment_and_challenge(scheme, message_hash, group_key, &adaptor_point, &self.state.signers) {
+                Ok(x) => x,
+                Err(error) => {
+                    misbehaving_participants.insert(0, error);
+                    return Err(misbehaving_participants);
+                },
+            };
+        let all_participant_indices: Vec<u32> = self.state.signers.iter().map(|x| x.participant_index).collect();
+        let mut z = Scalar::ZERO;
+
+        for signer in self.state.signers.iter() {
+            // These unwrap()s cannot fail, for the same reasons as given in
+            // SignatureAggregator<Finalized>.aggregate().
+            let lambda = calculate_lagrange_coefficients(&signer.participant_index, &all_participant_indices).unwrap();
+            let partial_sig = self.state.partial_signatures.get(&signer.participant_index).unwrap();
+            let Y_i = self.state.public_keys.get(&signer.participant_index).unwrap();
+            let R_i = Rs.get(&signer.participant_index).unwrap();
+
+            if adaptor_partial_signature_is_valid(scheme, partial_sig, Y_i, R_i, &c, &lambda, &R_adapted, group_key) {
+                z += partial_sig;
+            } else {
+                misbehaving_participants.insert(signer.participant_index, "Incorrect partial signature");
+            }
+        }
+
+        if ! misbehaving_participants.is_empty() {
+            return Err(misbehaving_participants);
+        }
+
+        let signature = AdaptorSignature { scheme, adaptor_point, R: R_adapted, z };
+
+        if scheme == AdaptorScheme::EthSchnorr && !group_key.is_valid_for_ethschnorr() {
+            misbehaving_participants.insert(0, "Public-key x >= HALF_Q");
+        } else if let Err(error) = signature.verify(group_key, message_hash) {
+            misbehaving_participants.insert(0, error);
+        }
+
+        match ! misbehaving_participants.is_empty() {
+            true => Err(misbehaving_participants),
+            false => Ok(signature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::keygen::Participant;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::compute_message_hash;
+
+    use k256::elliptic_curve::Field;
+
+    use rand::rngs::OsRng;
+
+    /// Have three of five participants make an adaptor signature on a message.
+    fn presign(scheme: AdaptorScheme, adaptor_point: &AffinePoint) -> (GroupKey, [u8; 32], AdaptorSignature) {
+        let params = Parameters { n: 5, t: 3 };
+        let (participants, _) = match scheme {
+            AdaptorScheme::EthSchnorr => Participant::dealer_for_ethschnorr(&params),
+            AdaptorScheme::Bip340 => Participant::dealer(&params),
+        };
+        let group_key = GroupKey(participants[0].group_key);
+        let message_hash = compute_message_hash(b"ATOMIC SWAP", b"swap 1 BTC for 15 ETH");
+        let mut aggregator = SignatureAggregator::new_prehashed(params, group_key, message_hash);
+        let mut secret_comshares = Vec::new();
+
+        aggregator.set_adaptor(scheme, *adaptor_point).unwrap();
+
+        for p in participants[1..4].iter() {
+            let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, p.public_key.index, 1);
+
+            aggregator.include_signer(p.public_key.index, public_comshares.commitments[0], p.public_key.clone()).unwrap();
+            secret_comshares.push(secret);
+        }
+
+        let signers = aggregator.get_signers().clone();
+
+        for (p, secret) in participants[1..4].iter().zip(secret_comshares.iter_mut()) {
+            let sk = p.secret_key();
+            let partial = sk.sign_adaptor(scheme, &message_hash, adaptor_point, &group_key, secret, 0, &signers).unwrap();
+
+            // An ordinary partial signature would be rejected.
+            assert!(partial.verify(&group_key, &message_hash, &signers, &p.public_key).is_err());
+            assert_eq!(aggregator.include_partial_signature(partial.clone()),
+                       Err("Aggregator is set up for an adaptor signature"));
+            aggregator.include_adaptor_partial_signature(partial).unwrap();
+        }
+
+        assert_eq!(aggregator.set_adaptor(scheme, *adaptor_point), Err("Adaptor was already set"));
+
+        let aggregator = aggregator.finalize().unwrap();
+
+        // The partial signatures can only be aggregated into an adaptor signature.
+        assert_eq!(aggregator.aggregate().unwrap_err().get(&0), Some(&"Aggregator is set up for an adaptor signature"));
+        assert_eq!(aggregator.aggregate_bip340().unwrap_err().get(&0), Some(&"Aggregator is set up for an adaptor signature"));
+
+        let signature = aggregator.aggregate_adaptor().unwrap();
+        let signature: AdaptorSignature = bincode::deserialize(&bincode::serialize(&signature).unwrap()).unwrap();
+
+        (group_key, message_hash, signature)
+    }
+
+    #[test]
+    fn ethschnorr_adaptor_signature() {
+        let secret = Scalar::random(&mut OsRng);
+        let adaptor_point = (ProjectivePoint::GENERATOR * secret).to_affine();
+        let (group_key, message_hash, presignature) = presign(AdaptorScheme::EthSchnorr, &adaptor_point);
+
+        assert!(presignature.verify(&group_key, &message_hash).is_ok());
+        assert!(presignature.verify(&group_key, &[0u8; 32]).is_err());
+        assert_eq!(presignature.adapt(&(secret + Scalar::ONE)).unwrap_err(), "Secret does not match the adaptor point");
+        assert_eq!(presignature.adapt_bip340(&secret).unwrap_err(), "Adaptor signature is for a different scheme");
+
+        // The pre-signature alone is not a signature.
+        let unadapted = ThresholdSignature { R: presignature.R, z: presignature.z };
+
+        assert!(unadapted.verify(&group_key, &message_hash).is_err());
+
+        let signature = presignature.adapt(&secret).unwrap();
+
+        assert!(signature.verify(&group_key, &message_hash).is_ok());
+        assert!(signature.to_ethschnorr_calldata(&group_key, &message_hash).verify().is_ok());
+        assert_eq!(presignature.extract(&signature), Ok(secret));
+    }
+
+    #[test]
+    fn bip340_adaptor_signature() {
+        // Run a few times to cover both parities of R + T and of the group key.
+        for _ in 0..8 {
+            let secret = Scalar::random(&mut OsRng);
+            let adaptor_point = (ProjectivePoint::GENERATOR * secret).to_affine();
+            let (group_key, message_hash, presignature) = presign(AdaptorScheme::Bip340, &adaptor_point);
+
+            assert!(presignature.verify(&group_key, &message_hash).is_ok());
+            assert_eq!(presignature.adapt(&secret).unwrap_err(), "Adaptor signature is for a different scheme");
+
+            let signature = presignature.adapt_bip340(&secret).unwrap();
+
+            assert!(signature.verify(&group_key, &message_hash).is_ok());
+            assert_eq!(presignature.extract_bip340(&signature), Ok(secret));
+
+            let other = Bip340Signature { R: signature.R, z: signature.z + Scalar::ONE };
+
+            assert!(presignature.extract_bip340(&other).is_err());
+        }
+    }
+
+    #[test]
+    fn adaptor_point_must_not_be_the_identity() {
+        let params = Parameters { n: 2, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+        let (p1_public, mut p1_secret) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public, _) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+        let signers = [
+            Signer { participant_index: 1, published_commitment_share: p1_public.commitments[0] },
+            Signer { participant_index: 2, published_commitment_share: p2_public.commitments[0] },
+        ];
+        let sk = participants[0].secret_key();
+
+        assert_eq!(sk.sign_adaptor(AdaptorScheme::Bip340, &[1u8; 32], &AffinePoint::IDENTITY, &group_key,
+                                   &mut p1_secret, 0, &signers).unwrap_err(),
+                   "Adaptor point is the identity");
+
+        let mut aggregator = SignatureAggregator::new_prehashed(params, group_key, [1u8; 32]);
+
+        assert_eq!(aggregator.set_adaptor(AdaptorScheme::Bip340, AffinePoint::IDENTITY),
+                   Err("Adaptor point is the identity"));
+        assert_eq!(aggregator.adaptor(), None);
+    }
+
+    #[test]
+    fn adaptor_must_be_set_before_partial_signatures() {
+        let params = Parameters { n: 2, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+        let mut aggregator = SignatureAggregator::new_prehashed(params, group_key, [1u8; 32]);
+        let (p1_public, mut p1_secret) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public, _) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        aggregator.include_signer(1, p1_public.commitments[0], participants[0].public_key.clone()).unwrap();
+        aggregator.include_signer(2, p2_public.commitments[0], participants[1].public_key.clone()).unwrap();
+
+        let signers = aggregator.get_signers().clone();
+        let sk = participants[0].secret_key();
+        let partial = sk.sign(&[1u8; 32], &group_key, &mut p1_secret, 0, &signers).unwrap();
+
+        assert_eq!(aggregator.include_adaptor_partial_signature(partial.clone()),
+                   Err("Aggregator is not set up for an adaptor signature"));
+
+        aggregator.include_partial_signature(partial).unwrap();
+
+        let adaptor_point = (ProjectivePoint::GENERATOR * Scalar::random(&mut OsRng)).to_affine();
+
+        assert_eq!(aggregator.set_adaptor(AdaptorScheme::EthSchnorr, adaptor_point),
+                   Err("Cannot set the adaptor after partial signatures"));
+    }
+}
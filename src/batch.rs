@@ -0,0 +1,476 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Signing many messages in one session.
+//!
+//! A [`BatchSignatureAggregator`] runs one [`SignatureAggregator`] per message,
+//! with the same signers for every message, so that a whole batch of messages
+//! costs only a single round trip:
+//!
+//! 1. The aggregator includes each signer with one commitment share per
+//!    message, usually taken from a [`CommitmentPool`] with
+//!    [`BatchSignatureAggregator::include_signers`].
+//! 2. Every signer is sent the message hashes and the list of [`Signer`]s for
+//!    each message, and answers with one [`PartialThresholdSignature`] per
+//!    message from [`SecretKey::sign_batch`].
+//! 3. The aggregator produces one [`ThresholdSignature`] per message, each
+//!    with its own blame on failure, so that one bad message does not hold up
+//!    the others.
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::Scalar;
+use k256::elliptic_curve::group::GroupEncoding;
+
+use crate::keygen::GroupKey;
+use crate::keygen::PublicKeyPackage;
+use crate::keygen::SecretKey;
+use crate::pool::CommitmentPool;
+use crate::precomputation::SecretCommitmentShareList;
+use crate::signature::compute_message_hash;
+use crate::signature::Initial;
+use crate::signature::PartialThresholdSignature;
+use crate::signature::SignatureAggregator;
+use crate::signature::Signer;
+use crate::signature::ThresholdSignature;
+
+/// A signature aggregator for a batch of messages, which are all signed by the
+/// same signers at once.
+#[derive(Debug)]
+pub struct BatchSignatureAggregator {
+    /// One aggregator per message, in the order of the messages.
+    aggregators: Vec<SignatureAggregator<Initial>>,
+    /// The hashed context and message for signing, per message.
+    message_hashes: Vec<[u8; 32]>,
+}
+
+impl BatchSignatureAggregator {
+    /// Construct a new batch signature aggregator for the group described by
+    /// a verified [`PublicKeyPackage`].
+    ///
+    /// # Inputs
+    ///
+    /// * The [`PublicKeyPackage`] of the group,
+    /// * An optional `context` string for computing the message hashes,
+    /// * The `messages` to be signed.
+    ///
+    /// # Returns
+    ///
+    /// A new [`BatchSignatureAggregator`].
+    pub fn new(
+        public_key_package: PublicKeyPackage,
+        context: Vec<u8>,
+        messages: Vec<Vec<u8>>,
+    ) -> BatchSignatureAggregator {
+        let message_hashes = messages.iter().map(|message| compute_message_hash(&context, message)).collect();
+        let aggregators = messages.into_iter()
+            .map(|message| SignatureAggregator::from_public_key_package(public_key_package.clone(), context.clone(), message))
+            .collect();
+
+        BatchSignatureAggregator { aggregators, message_hashes }
+    }
+
+    /// Get the number of messages in this batch.
+    pub fn len(&self) -> usize {
+        self.aggregators.len()
+    }
+
+    /// Check whether this batch has no messages.
+    pub fn is_empty(&self) -> bool {
+        self.aggregators.is_empty()
+    }
+
+    /// Get the hashes of the messages to be signed, in order.
+    pub fn message_hashes(&self) -> &[[u8; 32]] {
+        &self.message_hashes
+    }
+
+    /// Include a signer for every message in the batch, with one of their
+    /// `published_commitment_shares` for each message, in order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the signer was
+    /// included, otherwise a string describing why they were not.
+    pub fn try_include_signer(
+        &mut self,
+        participant_index: u32,
+        published_commitment_shares: &[(AffinePoint, AffinePoint)],
+    ) -> Result<(), &'static str>
+    {
+        if published_commitment_shares.len() != self.len() {
+            return Err("Wrong number of commitment shares");
+        }
+        if self.aggregators.iter().any(|a| a.state.signers.iter().any(|s| s.participant_index == participant_index)) {
+            return Err("Signer was already included");
+        }
+
+        let mut published: Vec<[u8; 33]> = Vec::with_capacity(2 * self.len());
+
+        for (hiding, binding) in published_commitment_shares.iter() {
+            published.push(hiding.to_bytes().into());
+            published.push(binding.to_bytes().into());
+        }
+        published.sort_unstable();
+        published.dedup();
+
+        if published.len() != 2 * self.len() {
+            return Err("Commitment share used for more than one message");
+        }
+
+        for (aggregator, commitment_share) in self.aggregators.iter_mut().zip(published_commitment_shares) {
+            aggregator.try_include_signer(participant_index, *commitment_share)?;
+        }
+
+        Ok(())
+    }
+
+    /// Include the `participants` as signers for every message in the batch,
+    /// with one fresh commitment share from the `pool` per message.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple, otherwise a
+    /// `Hashmap<u32, &'static str>` containing the participant indices of the
+    /// signers who could not be included, and why.  Nothing is assigned from
+    /// the `pool`, and no message gets any signers, unless every participant
+    /// can be included for every message.
+    pub fn include_signers(
+        &mut self,
+        pool: &mut CommitmentPool,
+        participants: &[u32],
+    ) -> Result<(), HashMap<u32, &'static str>>
+    {
+        let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+        let mut participants = participants.to_vec();
+
+        participants.sort_unstable();
+        participants.dedup();
+
+        // Check every message first, so that a participant refused for a
+        // later message hasn't already used up commitment shares for earlier
+        // ones.
+        for index in participants.iter() {
+            if pool.available(*index) < self.len() {
+                misbehaving_participants.insert(*index, "Not enough commitments available");
+                continue;
+            }
+            for aggregator in self.aggregators.iter() {
+                if let Err(error) = aggregator.check_new_signer(*index) {
+                    misbehaving_participants.insert(*index, error);
+                    break;
+                }
+            }
+        }
+
+        if !misbehaving_participants.is_empty() {
+            return Err(misbehaving_participants);
+        }
+
+        for aggregator in self.aggregators.iter_mut() {
+            // This can't fail, since every participant was checked above.
+            pool.include_signers(aggregator, &participants).expect("includable signers");
+        }
+
+        Ok(())
+    }
+
+    /// Get the lists of participating signers, one per message.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` of the sorted signers of each message, which is to be sent to
+    /// every signer along with the [`BatchSignatureAggregator::message_hashes`].
+    pub fn get_signers(&mut self) -> Vec<Vec<Signer>> {
+        self.aggregators.iter_mut().map(|aggregator| aggregator.get_signers().clone()).collect()
+    }
+
+    /// Add a signer's response, of one [`PartialThresholdSignature`] per
+    /// message, to be included in the aggregation.
+    ///
+    /// Every partial signature is verified immediately, and the valid ones are
+    /// included even if others are not.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if every partial
+    /// signature was included.  Otherwise, a `Hashmap<usize, &'static str>`
+    /// containing the positions of the messages whose partial signatures were
+    /// rejected, and why.
+    pub fn include_partial_signatures(
+        &mut self,
+        partial_signatures: Vec<PartialThresholdSignature>,
+    ) -> Result<(), HashMap<usize, &'static str>>
+    {
+        let mut rejected: HashMap<usize, &'static str> = HashMap::new();
+
+        if partial_signatures.len() != self.len() {
+            for i in 0..self.len() {
+                rejected.insert(i, "Wrong number of partial signatures");
+            }
+            return Err(rejected);
+        }
+
+        for (i, (aggregator, partial_signature)) in self.aggregators.iter_mut().zip(partial_signatures).enumerate() {
+            if let Err(error) = aggregator.include_partial_signature(partial_signature) {
+                rejected.insert(i, error);
+            }
+        }
+
+        match rejected.is_empty() {
+            true => Ok(()),
+            false => Err(rejected),
+        }
+    }
+
+    /// Aggregate the partial signatures for every message.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` with one entry per message, in order, which is as returned by
+    /// [`SignatureAggregator::finalize`] or
+    /// [`SignatureAggregator::aggregate`]: either the message's
+    /// [`ThresholdSignature`], or a `Hashmap<u32, &'static str>` containing the
+    /// participant indices of the misbehaving signers for that message.
+    pub fn aggregate(self) -> Vec<Result<ThresholdSignature, HashMap<u32, &'static str>>> {
+        self.aggregators.into_iter()
+            .map(|aggregator| aggregator.finalize()?.aggregate())
+            .collect()
+    }
+}
+
+impl SecretKey {
+    /// Compute this signer's [`PartialThresholdSignature`]s for a batch of
+    /// messages, in a single response.
+    ///
+    /// # Inputs
+    ///
+    /// * The `message_hashes` to be signed, in order,
+    /// * The public [`GroupKey`] for this group of signing participants,
+    /// * This signer's [`SecretCommitmentShareList`], in which the commitment
+    ///   share picked for each message is looked up and then dropped, and
+    /// * The list of all the participating [`Signer`]s (including ourself) for
+    ///   each message.
+    ///
+    /// Nothing is signed unless every message has a commitment share of ours
+    /// which is still in our list, and no commitment share was picked for more
+    /// than one message, since signing two messages with the same nonces
+    /// reveals our secret key.
+    ///
+    /// # Returns
+    ///
+    /// A Result whose `Ok` value contains one [`PartialThresholdSignature`] per
+    /// message, which should be sent to the [`BatchSignatureAggregator`].
+    /// Otherwise, its `Err` value contains a string describing the error which
+    /// occurred.
+    pub fn sign_batch(
+        &self,
+        message_hashes: &[[u8; 32]],
+        group_key: &GroupKey,
+        my_secret_commitment_share_list: &mut SecretCommitmentShareList,
+        signers: &[Vec<Signer>],
+    ) -> Result<Vec<PartialThresholdSignature>, &'static str>
+    {
+        if message_hashes.len() != signers.len() {
+            return Err("Wrong number of signer lists");
+        }
+
+        let mut my_commitment_shares: Vec<(AffinePoint, AffinePoint)> = Vec::with_capacity(signers.len());
+        let mut coefficients: Vec<(Scalar, Scalar)> = Vec::with_capacity(signers.len());
+
+        // Check everything which could go wrong before signing anything, so
+        // that a bad signer list for one message doesn't use up the
+        // commitment shares for the messages before it.
+        for (message_hash, message_signers) in message_hashes.iter().zip(signers) {
+            let me = message_signers.iter().find(|s| s.participant_index == self.index)
+                .ok_or("We are not a signer of every message")?;
+            let share = me.published_commitment_share;
+
+            if my_secret_commitment_share_list.index_of(&share).is_none() {
+                return Err("Unknown or already used commitment share");
+            }
+            if my_commitment_shares.iter().any(|s| s.0.to_bytes() == share.0.to_bytes() ||
+                                                   s.1.to_bytes() == share.1.to_bytes()) {
+                return Err("Commitment share used for more than one message");
+            }
+            my_commitment_shares.push(share);
+            coefficients.push(self.signing_coefficients(message_hash, group_key, message_signers)?);
+        }
+
+        let mut partial_signatures: Vec<PartialThresholdSignature> = Vec::with_capacity(signers.len());
+
+        for (share, (my_binding_factor, lambda_challenge)) in my_commitment_shares.iter().zip(coefficients) {
+            // Look the index up again, since signing drops the used share.
+            let index = my_secret_commitment_share_list.index_of(share).ok_or("Unknown or already used commitment share")?;
+            let my_commitment_share = my_secret_commitment_share_list.commitments[index].clone();
+            let z = my_commitment_share.hiding.nonce +
+                (my_commitment_share.binding.nonce * my_binding_factor) -
+                (lambda_challenge * self.key);
+
+            my_secret_commitment_share_list.drop_share(my_commitment_share);
+            partial_signatures.push(PartialThresholdSignature { index: self.index, z });
+        }
+
+        Ok(partial_signatures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn batch_signing() {
+        let (participants, package) = dealt_group(Parameters { n: 3, t: 2 });
+        let p1_sk = participants[0].secret_key();
+        let p2_sk = participants[1].secret_key();
+        let group_key = *package.group_key();
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let messages: Vec<Vec<u8>> = (0..8).map(|i| format!("Bridge transfer number {}", i).into_bytes()).collect();
+
+        let mut pool = CommitmentPool::new(*package.parameters(), 8);
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 10);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 10);
+
+        pool.publish(&p1_public_comshares).unwrap();
+        pool.publish(&p2_public_comshares).unwrap();
+
+        let mut aggregator = BatchSignatureAggregator::new(package, context.to_vec(), messages.clone());
+
+        aggregator.include_signers(&mut pool, &[1, 2]).unwrap();
+
+        assert_eq!(pool.available(1), 2);
+
+        let signers = aggregator.get_signers();
+        let message_hashes = aggregator.message_hashes().to_vec();
+
+        let p1_partials = p1_sk.sign_batch(&message_hashes, &group_key, &mut p1_secret_comshares, &signers).unwrap();
+        let p2_partials = p2_sk.sign_batch(&message_hashes, &group_key, &mut p2_secret_comshares, &signers).unwrap();
+
+        assert_eq!(p1_secret_comshares.commitments.len(), 2);
+
+        aggregator.include_partial_signatures(p1_partials).unwrap();
+        aggregator.include_partial_signatures(p2_partials).unwrap();
+
+        let signatures = aggregator.aggregate();
+
+        assert_eq!(signatures.len(), messages.len());
+
+        for (signature, message) in signatures.into_iter().zip(messages) {
+            let message_hash = compute_message_hash(&context[..], &message);
+
+            assert!(signature.unwrap().verify(&group_key, &message_hash).is_ok());
+        }
+    }
+
+    #[test]
+    fn batch_signing_blames_per_message() {
+        let (participants, package) = dealt_group(Parameters { n: 3, t: 2 });
+        let p1_sk = participants[0].secret_key();
+        let p2_sk = participants[1].secret_key();
+        let group_key = *package.group_key();
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let messages: Vec<Vec<u8>> = (0..3).map(|i| format!("Bridge transfer number {}", i).into_bytes()).collect();
+
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 3);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 3);
+
+        let mut aggregator = BatchSignatureAggregator::new(package, context.to_vec(), messages);
+
+        assert_eq!(aggregator.try_include_signer(1, &p1_public_comshares.commitments[..2]),
+                   Err("Wrong number of commitment shares"));
+
+        let reused = [p1_public_comshares.commitments[0], p1_public_comshares.commitments[1], p1_public_comshares.commitments[0]];
+
+        assert_eq!(aggregator.try_include_signer(1, &reused), Err("Commitment share used for more than one message"));
+
+        aggregator.try_include_signer(1, &p1_public_comshares.commitments).unwrap();
+        aggregator.try_include_signer(2, &p2_public_comshares.commitments).unwrap();
+
+        let signers = aggregator.get_signers();
+        let message_hashes = aggregator.message_hashes().to_vec();
+
+        // A malicious aggregator can't trick a signer into reusing a nonce.
+        let mut tampered = signers.clone();
+
+        tampered[2] = tampered[0].clone();
+
+        assert_eq!(p1_sk.sign_batch(&message_hashes, &group_key, &mut p1_secret_comshares, &tampered).unwrap_err(),
+                   "Commitment share used for more than one message");
+        assert_eq!(p1_secret_comshares.commitments.len(), 3);
+
+        // Nor is anything used up when only the last signer list is malformed.
+        let mut tampered = signers.clone();
+        let duplicate = tampered[2][1];
+
+        tampered[2].push(duplicate);
+
+        assert_eq!(p1_sk.sign_batch(&message_hashes, &group_key, &mut p1_secret_comshares, &tampered).unwrap_err(),
+                   "Duplicate signers provided");
+        assert_eq!(p1_secret_comshares.commitments.len(), 3);
+
+        let p1_partials = p1_sk.sign_batch(&message_hashes, &group_key, &mut p1_secret_comshares, &signers).unwrap();
+        let mut p2_partials = p2_sk.sign_batch(&message_hashes, &group_key, &mut p2_secret_comshares, &signers).unwrap();
+
+        // Participant 2's second partial signature is garbage.
+        p2_partials[1].z += Scalar::ONE;
+
+        assert!(aggregator.include_partial_signatures(p1_partials).is_ok());
+
+        let rejected = aggregator.include_partial_signatures(p2_partials).unwrap_err();
+
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected.get(&1), Some(&"Incorrect partial signature"));
+
+        let signatures = aggregator.aggregate();
+
+        assert!(signatures[0].is_ok());
+        assert_eq!(signatures[1].as_ref().unwrap_err().get(&2), Some(&"Missing partial signature"));
+        assert!(signatures[2].is_ok());
+    }
+
+    #[test]
+    fn failed_batch_inclusion_uses_up_nothing() {
+        let (_, package) = dealt_group(Parameters { n: 3, t: 2 });
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let messages: Vec<Vec<u8>> = (0..3).map(|i| format!("Bridge transfer number {}", i).into_bytes()).collect();
+
+        let mut pool = CommitmentPool::new(*package.parameters(), 1);
+        let (p1_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 1, 4);
+        let (p2_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 2, 4);
+
+        pool.publish(&p1_public_comshares).unwrap();
+        pool.publish(&p2_public_comshares).unwrap();
+
+        let mut aggregator = BatchSignatureAggregator::new(package, context.to_vec(), messages);
+
+        // Participant 2 is already signing the last message only.
+        let (other_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        aggregator.aggregators[2].try_include_signer(2, other_public_comshares.commitments[0]).unwrap();
+
+        let pool_before = pool.to_bytes();
+        let misbehaving_participants = aggregator.include_signers(&mut pool, &[1, 2]).unwrap_err();
+
+        assert_eq!(misbehaving_participants.len(), 1);
+        assert_eq!(misbehaving_participants.get(&2), Some(&"Signer was already included"));
+        assert_eq!(pool.to_bytes(), pool_before);
+        assert_eq!(pool.available(1), 4);
+        assert_eq!(pool.available(2), 4);
+        assert!(aggregator.aggregators[..2].iter_mut().all(|a| a.get_signers().is_empty()));
+    }
+}
@@ -0,0 +1,428 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! [BIP340] Schnorr signatures, for Bitcoin and Taproot compatibility.
+//!
+//! The signatures produced by [`SecretKey::sign`] and
+//! [`SignatureAggregator::aggregate`] are made for the EthSchnorr verifier and
+//! are *not* valid BIP340 signatures.  This module provides an alternative
+//! signing mode, using the same keys and precomputed commitments, which differs
+//! in the following ways:
+//!
+//! * The [`GroupKey`] \\(Y\\) is used in its even-Y form.  If \\(Y\\) has an odd
+//!   y-coordinate, every signer negates their secret share, so that the shares
+//!   interpolate to the secret key for \\(-Y\\).
+//! * The group commitment \\(R\\) is normalised in the same way, by having every
+//!   signer negate their nonces whenever \\(R\\) has an odd y-coordinate.
+//! * The challenge is the tagged hash
+//!   \\(c = \mathcal{H}\_{\mathtt{BIP0340/challenge}}(R\_x || Y\_x || m)\\), and
+//!   the response is \\(z = k + c \cdot a\\) rather than \\(z = k - c \cdot a\\).
+//! * Public keys are encoded as 32-byte x-only keys and signatures as the 64
+//!   bytes \\(R\_x || z\\).
+//!
+//! [BIP340]: https://github.com/bitcoin/bips/blob/master/bip-0340.mediawiki
+
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::FieldBytes;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::U256;
+use k256::elliptic_curve::AffineXCoordinate;
+use k256::elliptic_curve::DecompactPoint;
+use k256::elliptic_curve::PrimeField;
+use k256::elliptic_curve::group::GroupEncoding;
+use k256::elliptic_curve::ops::LinearCombination;
+use k256::elliptic_curve::ops::Reduce;
+use sha2::Digest;
+use sha2::Sha256;
+
+#[cfg(feature = "std")]
+use zeroize::Zeroize;
+
+use crate::keygen::GroupKey;
+use crate::keygen::SecretKey;
+#[cfg(feature = "std")]
+use crate::precomputation::SecretCommitmentShareList;
+#[cfg(feature = "std")]
+use crate::keygen::IndividualPublicKey;
+#[cfg(feature = "std")]
+use crate::signature::Finalized;
+#[cfg(feature = "std")]
+use crate::signature::Initial;
+#[cfg(feature = "std")]
+use crate::signature::PartialThresholdSignature;
+#[cfg(feature = "std")]
+use crate::signature::SignatureAggregator;
+#[cfg(feature = "std")]
+use crate::signature::Signer;
+#[cfg(feature = "std")]
+use crate::signature::calculate_lagrange_coefficients;
+#[cfg(feature = "std")]
+use crate::signature::compute_binding_factors_and_group_commitment;
+
+/// Compute the BIP340 tagged hash \\(\mathrm{SHA256}(\mathrm{SHA256}(tag) || \mathrm{SHA256}(tag) || x)\\)
+/// of the concatenation of `chunks`.
+pub(crate) fn tagged_hash(tag: &[u8], chunks: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag);
+    let mut h = Sha256::new();
+
+    h.update(tag_hash);
+    h.update(tag_hash);
+
+    for chunk in chunks.iter() {
+        h.update(chunk);
+    }
+    h.finalize().into()
+}
+
+/// Determine whether a point has an even y-coordinate.
+pub(crate) fn has_even_y(point: &AffinePoint) -> bool {
+    point.to_bytes()[0] & 1 == 0
+}
+
+/// Compute the BIP340 challenge \\(\mathcal{H}\_{\mathtt{BIP0340/challenge}}(R\_x || Y\_x || m)\\).
+///
+/// Only the x-coordinates of `public_key` and `R` are used, so their parity
+/// does not matter here.
+pub(crate) fn compute_bip340_challenge(message_hash: &[u8; 32], public_key: &AffinePoint, R: &AffinePoint) -> Scalar {
+    let e = tagged_hash(b"BIP0340/challenge", &[&R.x()[..], &public_key.x()[..], &message_hash[..]]);
+
+    <Scalar as Reduce<U256>>::from_be_bytes_reduced(FieldBytes::from(e))
+}
+
+/// The sign, \\(\pm 1\\), by which signers multiply a point with an odd
+/// y-coordinate in order to normalise it.
+#[cfg(feature = "std")]
+pub(crate) fn normalising_sign(point: &AffinePoint) -> Scalar {
+    match has_even_y(point) {
+        true => Scalar::ONE,
+        false => -Scalar::ONE,
+    }
+}
+
+/// Check that a signer's BIP340 partial signature \\(z\_i\\) is consistent
+/// with their public key share \\(Y\_i\\) and their commitment \\(R\_i\\),
+/// taking into account the normalisation of the group commitment \\(R\\) and
+/// the group key \\(Y\\).
+#[cfg(feature = "std")]
+pub(crate) fn bip340_partial_signature_is_valid(
+    z: &Scalar,
+    Y_i: &AffinePoint,
+    R_i: &AffinePoint,
+    challenge: &Scalar,
+    lambda: &Scalar,
+    nonce_sign: &Scalar,
+    key_sign: &Scalar,
+) -> bool
+{
+    let check = ProjectivePoint::lincomb(&(*R_i).into(), nonce_sign, &(*Y_i).into(), &(*challenge * lambda * key_sign));
+
+    (AffinePoint::GENERATOR * z).to_affine() == check.to_affine()
+}
+
+/// A complete, aggregated threshold signature which is valid under [BIP340].
+///
+/// [BIP340]: https://github.com/bitcoin/bips/blob/master/bip-0340.mediawiki
+#[derive(Debug)]
+pub struct Bip340Signature {
+    /// The group commitment, always with an even y-coordinate.
+    pub(crate) R: AffinePoint,
+    pub(crate) z: Scalar,
+}
+
+impl Bip340Signature {
+    /// Serialize this signature to the 64 bytes \\(R\_x || z\\).
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+
+        bytes[..32].copy_from_slice(&self.R.x()[..]);
+        bytes[32..].copy_from_slice(&self.z.to_bytes()[..]);
+        bytes
+    }
+
+    /// Attempt to deserialize a signature from the 64 bytes \\(R\_x || z\\).
+    pub fn from_bytes(bytes: [u8; 64]) -> Option<Bip340Signature> {
+        let mut x = [0u8; 32];
+        let mut z = [0u8; 32];
+
+        x.copy_from_slice(&bytes[..32]);
+        z.copy_from_slice(&bytes[32..]);
+
+        let R = Option::from(AffinePoint::decompact(&FieldBytes::from(x)))?;
+        let z = Option::from(Scalar::from_repr(FieldBytes::from(z)))?;
+
+        Some(Bip340Signature { R, z })
+    }
+
+    /// Verify this [`Bip340Signature`] on a `message_hash` under the x-only
+    /// form of the `group_key`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the signature was
+    /// valid, otherwise an empty `Err`.
+    pub fn verify(&self, group_key: &GroupKey, message_hash: &[u8; 32]) -> Result<(), ()> {
+        let Y = match has_even_y(&group_key.0) {
+            true => group_key.0,
+            false => -group_key.0,
+        };
+        let c = compute_bip340_challenge(message_hash, &Y, &self.R);
+        let R_prime = ProjectivePoint::lincomb(&ProjectivePoint::GENERATOR, &self.z, &Y.into(), &-c).to_affine();
+
+        if R_prime == AffinePoint::IDENTITY || !has_even_y(&R_prime) {
+            return Err(());
+        }
+
+        match R_prime.x() == self.R.x() {
+            true => Ok(()),
+            false => Err(()),
+        }
+    }
+}
+
+impl GroupKey {
+    /// Serialise this group public key as a 32-byte BIP340 x-only public key.
+    pub fn to_bip340_bytes(&self) -> [u8; 32] {
+        self.0.x().into()
+    }
+
+    /// Deserialise a group public key from a 32-byte BIP340 x-only public key.
+    ///
+    /// The resulting key always has an even y-coordinate.
+    pub fn from_bip340_bytes(bytes: [u8; 32]) -> Option<GroupKey> {
+        Option::from(AffinePoint::decompact(&FieldBytes::from(bytes))).map(GroupKey)
+    }
+}
+
+impl SecretKey {
+    /// Compute an individual signer's [`PartialThresholdSignature`] contribution
+    /// to a [`Bip340Signature`] on a `message_hash`.
+    ///
+    /// The inputs are the same as for [`SecretKey::sign`], and the same
+    /// warnings apply.  The resulting partial signature must be given to
+    /// [`SignatureAggregator::include_bip340_partial_signature`] and
+    /// [`SignatureAggregator::aggregate_bip340`], *not*
+    /// [`SignatureAggregator::include_partial_signature`] and
+    /// [`SignatureAggregator::aggregate`].
+    ///
+    /// # Returns
+    ///
+    /// A Result whose `Ok` value contains a [`PartialThresholdSignature`], which
+    /// should be sent to the [`SignatureAggregator`].  Otherwise, its `Err` value contains
+    /// a string describing the error which occurred.
+    #[cfg(feature = "std")]
+    pub fn sign_bip340(
+        &self,
+        message_hash: &[u8; 32],
+        group_key: &GroupKey,
+        my_secret_commitment_share_list: &mut SecretCommitmentShareList,
+        my_commitment_share_index: usize,
+        signers: &[Signer],
+    ) -> Result<PartialThresholdSignature, &'static str>
+    {
+        if my_commitment_share_index + 1 > my_secret_commitment_share_list.commitments.len() {
+            return Err("Commitment share index out of bounds");
+        }
+
+        let (binding_factors, Rs) = compute_binding_factors_and_group_commitment(message_hash, signers);
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+        let R = R.to_affine();
+        let challenge = compute_bip340_challenge(message_hash, &group_key.0, &R);
+        let my_binding_factor = binding_factors.get(&self.index).ok_or("Could not compute our blinding factor")?;
+        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda: Scalar = calculate_lagrange_coefficients(&self.index, &all_participant_indices)?;
+        let my_commitment_share = my_secret_commitment_share_list.commitments[my_commitment_share_index].clone();
+
+        let mut nonce = my_commitment_share.hiding.nonce + (my_commitment_share.binding.nonce * my_binding_factor);
+        let mut key = self.key;
+
+        // Every signer sees the same R and Y, so every signer negates (or
+        // doesn't) in agreement with all the others.
+        if !has_even_y(&R) {
+            nonce = -nonce;
+        }
+        if !has_even_y(&group_key.0) {
+            key = -key;
+        }
+
+        let z = nonce + (lambda * key * challenge);
+
+        nonce.zeroize();
+        key.zeroize();
+
+        // Zero out our secrets from memory to prevent nonce reuse.
+        my_secret_commitment_share_list.drop_share(my_commitment_share);
+
+        Ok(PartialThresholdSignature { index: self.index, z })
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialThresholdSignature {
+    /// Verify this [`PartialThresholdSignature`], which was created with
+    /// [`SecretKey::sign_bip340`], independently of any [`SignatureAggregator`].
+    ///
+    /// The inputs are the same as for [`PartialThresholdSignature::verify`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the partial signature
+    /// is valid, otherwise a string describing why it is not.
+    pub fn verify_bip340(
+        &self,
+        group_key: &GroupKey,
+        message_hash: &[u8; 32],
+        signers: &[Signer],
+        public_key: &IndividualPublicKey,
+    ) -> Result<(), &'static str>
+    {
+        if public_key.index != self.index {
+            return Err("Public key does not belong to the signer of this partial signature");
+        }
+
+        let (_, Rs) = compute_binding_factors_and_group_commitment(message_hash, signers);
+        let R_i = Rs.get(&self.index).ok_or("Partial signature is not from one of the signers")?;
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+        let R = R.to_affine();
+        let c = compute_bip340_challenge(message_hash, &group_key.0, &R);
+        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda = calculate_lagrange_coefficients(&self.index, &all_participant_indices)?;
+
+        match bip340_partial_signature_is_valid(&self.z, &public_key.share, R_i, &c, &lambda,
+                                                &normalising_sign(&R), &normalising_sign(&group_key.0)) {
+            true => Ok(()),
+            false => Err("Incorrect partial signature"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl SignatureAggregator<Initial> {
+    /// Add a [`PartialThresholdSignature`], which was created with
+    /// [`SecretKey::sign_bip340`], to be included in the aggregation.
+    ///
+    /// This is the BIP340 counterpart of
+    /// [`SignatureAggregator::include_partial_signature`], and the partial
+    /// signature is likewise verified immediately.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the partial signature
+    /// was included, otherwise a string describing why it was rejected.
+    pub fn include_bip340_partial_signature(&mut self, partial_signature: PartialThresholdSignature) -> Result<(), &'static str> {
+        self.expect_no_adaptor()?;
+
+        let public_key = self.expected_partial_signer(&partial_signature)?;
+        let message_hash = self.aggregator.message_hash;
+        let signers = self.get_signers().clone();
+
+        partial_signature.verify_bip340(&self.state.group_key, &message_hash, &signers, &public_key)?;
+
+        self.state.partial_signatures.insert(&partial_signature.index, partial_signature.z);
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl SignatureAggregator<Finalized> {
+    /// Aggregate a set of previously-collected partial signatures, which were
+    /// created with [`SecretKey::sign_bip340`], into a [`Bip340Signature`].
+    ///
+    /// # Returns
+    ///
+    /// A Result whose Ok() value is a [`Bip340Signature`], otherwise a
+    /// `Hashmap<u32, &'static str>` containing the participant indices of the misbehaving
+    /// signers and a description of their misbehaviour.  A key of `0`
+    /// indicates that the aggregator was set up for an adaptor signature.
+    pub fn aggregate_bip340(&self) -> Result<Bip340Signature, HashMap<u32, &'static str>> {
+        let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+
+        if self.state.adaptor.is_some() {
+            misbehaving_participants.insert(0, "Aggregator is set up for an adaptor signature");
+            return Err(misbehaving_participants);
+        }
+
+        let (_, Rs) = compute_binding_factors_and_group_commitment(&self.aggregator.message_hash, &self.state.signers);
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+        let R = R.to_affine();
+        let c = compute_bip340_challenge(&self.aggregator.message_hash, &self.state.group_key.0, &R);
+        let nonce_sign = normalising_sign(&R);
+        let key_sign = normalising_sign(&self.state.group_key.0);
+        let all_participant_indices: Vec<u32> = self.state.signers.iter().map(|x| x.participant_index).collect();
+        let mut z = Scalar::ZERO;
+
+        for signer in self.state.signers.iter() {
+            // These unwrap()s cannot fail, for the same reasons as given in
+            // SignatureAggregator<Finalized>.aggregate().
+            let lambda = calculate_lagrange_coefficients(&signer.participant_index, &all_participant_indices).unwrap();
+            let partial_sig = self.state.partial_signatures.get(&signer.participant_index).unwrap();
+            let Y_i = self.state.public_keys.get(&signer.participant_index).unwrap();
+            let R_i = Rs.get(&signer.participant_index).unwrap();
+
+            if bip340_partial_signature_is_valid(partial_sig, Y_i, R_i, &c, &lambda, &nonce_sign, &key_sign) {
+                z += partial_sig;
+            } else {
+                misbehaving_participants.insert(signer.participant_index, "Incorrect partial signature");
+            }
+        }
+
+        let R = match has_even_y(&R) {
+            true => R,
+            false => -R,
+        };
+
+        match ! misbehaving_participants.is_empty() {
+            true => Err(misbehaving_participants),
+            false => Ok(Bip340Signature { R, z }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tagged_hash_of_empty_challenge() {
+        // SHA256(SHA256("BIP0340/challenge") || SHA256("BIP0340/challenge"))
+        let expected = Sha256::new()
+            .chain_update(Sha256::digest(b"BIP0340/challenge"))
+            .chain_update(Sha256::digest(b"BIP0340/challenge"))
+            .finalize();
+
+        assert_eq!(tagged_hash(b"BIP0340/challenge", &[]), <[u8; 32]>::from(expected));
+    }
+
+    #[test]
+    fn verify_bip340_test_vector_0() {
+        // Test vector 0 from the BIP340 reference test vectors.
+        let mut public_key = [0u8; 32];
+        let mut signature = [0u8; 64];
+
+        public_key.copy_from_slice(&hex::decode("F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9").unwrap());
+        signature.copy_from_slice(&hex::decode(
+            "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA8215\
+             25F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0").unwrap());
+
+        let group_key = GroupKey::from_bip340_bytes(public_key).unwrap();
+        let signature = Bip340Signature::from_bytes(signature).unwrap();
+
+        assert!(signature.verify(&group_key, &[0u8; 32]).is_ok());
+        assert!(signature.verify(&group_key, &[1u8; 32]).is_err());
+        assert_eq!(signature.to_bytes()[..], hex::decode(
+            "E907831F80848D1069A5371B402410364BDF1C5F8307B0084C55F1CE2DCA8215\
+             25F66A4A85EA8B71E482A74F382D2CE5EBEEE8FDB2172F477DF4900D310536C0").unwrap()[..]);
+    }
+}
@@ -0,0 +1,257 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Signing certificates, attributing a [`ThresholdSignature`] to its signers.
+//!
+//! A [`ThresholdSignature`] looks the same whichever \\(t\\) participants
+//! made it.  A [`SigningCertificate`] additionally records which participants
+//! those were, with their [`Signer`] commitments and their verified
+//! [`PartialThresholdSignature`]s, so that anyone with the group's
+//! [`PublicKeyPackage`] can check with [`SigningCertificate::verify`] that
+//! exactly those participants contributed, e.g. for slashing.
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use k256::ProjectivePoint;
+use k256::Scalar;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::keygen::PublicKeyPackage;
+use crate::signature::compute_binding_factors_and_group_commitment;
+use crate::signature::Finalized;
+use crate::signature::PartialThresholdSignature;
+use crate::signature::SignatureAggregator;
+use crate::signature::Signer;
+use crate::signature::ThresholdSignature;
+
+/// A [`ThresholdSignature`] together with the evidence of who made it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SigningCertificate {
+    /// The message hash which was signed.
+    pub message_hash: [u8; 32],
+    /// The aggregated signature.
+    pub signature: ThresholdSignature,
+    /// A bitmap of the signers, in which bit \\(i-1 \bmod 8\\) of byte
+    /// \\(\lfloor (i-1)/8 \rfloor\\) is set if participant \\(i\\) signed.
+    pub signer_bitmap: Vec<u8>,
+    /// The signers and their commitments, sorted by participant index.
+    pub signers: Vec<Signer>,
+    /// The signers' partial signatures, in the same order.
+    pub partial_signatures: Vec<PartialThresholdSignature>,
+}
+
+/// Compute the bitmap of a set of participant `indices`, for `n` participants.
+fn signer_bitmap(indices: &[u32], n: u32) -> Vec<u8> {
+    let mut bitmap = vec![0u8; (n as usize).div_ceil(8)];
+
+    for index in indices.iter().filter(|&&i| i >= 1 && i <= n) {
+        bitmap[(*index as usize - 1) / 8] |= 1 << ((index - 1) % 8);
+    }
+    bitmap
+}
+
+impl SigningCertificate {
+    /// Get the participant indices which are set in the
+    /// [`SigningCertificate::signer_bitmap`], in ascending order.
+    pub fn signer_indices(&self) -> Vec<u32> {
+        (0..self.signer_bitmap.len() as u32 * 8)
+            .filter(|i| self.signer_bitmap[*i as usize / 8] & (1 << (i % 8)) != 0)
+            .map(|i| i + 1)
+            .collect()
+    }
+
+    /// Check this certificate against the public keys of the group.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the participant indices of the signers if
+    /// at least \\(t\\) distinct participants signed, the bitmap lists exactly
+    /// them, each of their partial signatures is valid, and together they make
+    /// up the [`ThresholdSignature`], which is valid.  Otherwise, a string
+    /// describing the first problem found.
+    pub fn verify(&self, public_key_package: &PublicKeyPackage) -> Result<Vec<u32>, &'static str> {
+        let parameters = public_key_package.parameters();
+        let group_key = public_key_package.group_key();
+        let indices: Vec<u32> = self.signers.iter().map(|s| s.participant_index).collect();
+
+        if indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("Signers must be sorted and distinct");
+        }
+        if indices.len() < parameters.t as usize {
+            return Err("Fewer signers than the threshold");
+        }
+        if indices.iter().any(|&i| i == 0 || i > parameters.n) {
+            return Err("Signer index out of range");
+        }
+        if self.signer_bitmap != signer_bitmap(&indices, parameters.n) {
+            return Err("Signer bitmap does not match the signers");
+        }
+        if self.partial_signatures.len() != self.signers.len() {
+            return Err("Wrong number of partial signatures");
+        }
+
+        let mut z = Scalar::ZERO;
+
+        for (signer, partial) in self.signers.iter().zip(self.partial_signatures.iter()) {
+            if partial.index != signer.participant_index {
+                return Err("Partial signatures are not in the order of the signers");
+            }
+            // This cannot fail, since the index was checked to be in range.
+            let public_key = public_key_package.public_key(signer.participant_index).ok_or("Unknown signer")?;
+
+            partial.verify(group_key, &self.message_hash, &self.signers, public_key)?;
+            z += partial.z;
+        }
+
+        let (_, Rs) = compute_binding_factors_and_group_commitment(&self.message_hash, &self.signers);
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+
+        if R.to_affine() != self.signature.R || z != self.signature.z {
+            return Err("Signature was not made from the partial signatures");
+        }
+        self.signature.verify(group_key, &self.message_hash).or(Err("Invalid threshold signature"))?;
+
+        Ok(indices)
+    }
+
+    /// Serialise this certificate, e.g. to be published with the signature.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("a serialisable certificate")
+    }
+
+    /// Deserialise a certificate serialised with
+    /// [`SigningCertificate::to_bytes`].  It should then be checked with
+    /// [`SigningCertificate::verify`].
+    pub fn from_bytes(bytes: &[u8]) -> Option<SigningCertificate> {
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+impl SignatureAggregator<Finalized> {
+    /// Aggregate the partial signatures as with [`SignatureAggregator::aggregate`],
+    /// and certify which participants made the signature.
+    ///
+    /// # Returns
+    ///
+    /// A Result whose Ok() value is a [`SigningCertificate`], otherwise the
+    /// misbehaving participants, exactly as for [`SignatureAggregator::aggregate`].
+    pub fn aggregate_with_certificate(&self) -> Result<SigningCertificate, HashMap<u32, &'static str>> {
+        let signature = self.aggregate()?;
+        let mut signers = self.state.signers.clone();
+
+        signers.sort();
+
+        let indices: Vec<u32> = signers.iter().map(|s| s.participant_index).collect();
+        // These unwrap()s cannot fail, since aggregate() checked that there is
+        // a valid partial signature from every signer.
+        let partial_signatures = indices.iter()
+            .map(|i| PartialThresholdSignature { index: *i, z: *self.state.partial_signatures.get(i).unwrap() })
+            .collect();
+
+        Ok(SigningCertificate {
+            message_hash: self.aggregator.message_hash,
+            signature,
+            signer_bitmap: signer_bitmap(&indices, self.state.parameters.n),
+            signers,
+            partial_signatures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+
+    use rand::rngs::OsRng;
+
+    /// Have the participants at the given `positions` sign a message, and
+    /// certify the signature.
+    fn certify(positions: &[usize]) -> (PublicKeyPackage, SigningCertificate) {
+        let params = Parameters { n: 10, t: 3 };
+        let (participants, package) = dealt_group(params);
+        let mut aggregator = SignatureAggregator::from_public_key_package(package.clone(), b"CONTEXT".to_vec(), b"transfer".to_vec());
+        let mut secret_comshares = Vec::new();
+
+        for i in positions.iter() {
+            let index = participants[*i].public_key.index;
+            let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, index, 1);
+
+            aggregator.try_include_signer(index, public_comshares.commitments[0]).unwrap();
+            secret_comshares.push(secret);
+        }
+
+        let signers = aggregator.get_signers().clone();
+        let message_hash = *aggregator.message_hash();
+
+        for (i, secret) in positions.iter().zip(secret_comshares.iter_mut()) {
+            let p = &participants[*i];
+            let sk = p.secret_key();
+
+            aggregator.include_partial_signature(sk.sign(&message_hash, package.group_key(), secret, 0, &signers).unwrap()).unwrap();
+        }
+
+        (package, aggregator.finalize().unwrap().aggregate_with_certificate().unwrap())
+    }
+
+    #[test]
+    fn certificate_attributes_signers() {
+        let (package, certificate) = certify(&[8, 1, 4]);
+
+        assert_eq!(certificate.signer_bitmap, vec![0b0001_0010, 0b0000_0001]);
+        assert_eq!(certificate.signer_indices(), vec![2, 5, 9]);
+        assert_eq!(certificate.verify(&package), Ok(vec![2, 5, 9]));
+
+        let restored = SigningCertificate::from_bytes(&certificate.to_bytes()).unwrap();
+
+        assert_eq!(restored.verify(&package), Ok(vec![2, 5, 9]));
+        assert_eq!(restored.signature.to_bytes(), certificate.signature.to_bytes());
+    }
+
+    #[test]
+    fn tampered_certificates_fail() {
+        let (package, certificate) = certify(&[0, 1, 2, 3]);
+
+        let mut c = certificate.clone();
+        c.signer_bitmap[0] |= 0b1000_0000;
+        assert_eq!(c.verify(&package), Err("Signer bitmap does not match the signers"));
+
+        // Claiming that one signer didn't take part.
+        let mut c = certificate.clone();
+        c.signers.pop();
+        c.partial_signatures.pop();
+        c.signer_bitmap = signer_bitmap(&[1, 2, 3], 10);
+        assert!(c.verify(&package).is_err());
+
+        // Swapping in another participant's index.
+        let mut c = certificate.clone();
+        c.signers[3].participant_index = 7;
+        c.partial_signatures[3].index = 7;
+        c.signer_bitmap = signer_bitmap(&[1, 2, 3, 7], 10);
+        assert_eq!(c.verify(&package), Err("Incorrect partial signature"));
+
+        let mut c = certificate.clone();
+        c.partial_signatures[0].z += Scalar::ONE;
+        assert_eq!(c.verify(&package), Err("Incorrect partial signature"));
+
+        let mut c = certificate.clone();
+        c.signers.swap(0, 1);
+        assert_eq!(c.verify(&package), Err("Signers must be sorted and distinct"));
+
+        let mut c = certificate;
+        c.message_hash = [0u8; 32];
+        assert!(c.verify(&package).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! [BIP32]-style non-hardened derivation of child keys from a group key.
+//!
+//! A child key is \\(Y' = Y + \tau G\\), where the tweak \\(\tau\\) is derived
+//! from the [`GroupKey`] \\(Y\\), a chain code and a path of non-hardened
+//! indices exactly as in BIP32's public derivation, so that one key generation
+//! can serve many keys.  Since the tweak depends only upon public data, every
+//! participant derives the same one, and adds it to their [`SecretKey`]:
+//! because the Lagrange coefficients of any set of signers sum to one, the
+//! shares \\(x\_i + \tau\\) are shares of \\(x + \tau\\), and the public
+//! shares \\(Y\_i + \tau G\\) are likewise consistent with \\(Y'\\).
+//!
+//! Hardened derivation would require the group's secret key, which no one
+//! knows, and so is not possible.
+//!
+//! [BIP32]: https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
+
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::FieldBytes;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::elliptic_curve::PrimeField;
+use k256::elliptic_curve::group::GroupEncoding;
+
+use hmac::Hmac;
+use hmac::Mac;
+
+use sha2::Sha512;
+
+use crate::keygen::GroupKey;
+use crate::keygen::IndividualPublicKey;
+use crate::keygen::PublicKeyPackage;
+use crate::keygen::SecretKey;
+
+/// The first hardened index, \\(2^{31}\\).
+pub const HARDENED: u32 = 1 << 31;
+
+/// Compute \\(\mathrm{HMAC\text{-}SHA512}(key, data)\\), as used by BIP32.
+fn hmac_sha512(key: &[u8; 32], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+
+    for chunk in data.iter() {
+        mac.update(chunk);
+    }
+
+    mac.finalize().into_bytes().into()
+}
+
+/// A key derived from a [`GroupKey`] along some path.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DerivedKey {
+    /// The child group key \\(Y' = Y + \tau G\\).
+    pub group_key: GroupKey,
+    /// The child's chain code, for deriving its own children.
+    pub chain_code: [u8; 32],
+    /// The total tweak \\(\tau\\) from the parent key.
+    pub tweak: Scalar,
+}
+
+/// Parse a BIP32 derivation path of non-hardened indices, such as `"m/0/1"`.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is the indices of the path, otherwise a string
+/// describing why it could not be parsed, e.g. because it has a hardened step.
+pub fn parse_derivation_path(path: &str) -> Result<Vec<u32>, &'static str> {
+    let mut steps = path.split('/');
+
+    if steps.next() != Some("m") {
+        return Err("Derivation path must start with \"m\"");
+    }
+
+    steps.map(|step| {
+        if step.ends_with('\'') || step.ends_with('h') || step.ends_with('H') {
+            return Err("Hardened derivation is not possible from a public key");
+        }
+        match step.parse::<u32>() {
+            Ok(index) if index < HARDENED => Ok(index),
+            Ok(_) => Err("Hardened derivation is not possible from a public key"),
+            Err(_) => Err("Invalid derivation path step"),
+        }
+    }).collect()
+}
+
+impl GroupKey {
+    /// Derive the non-hardened child of this key with the given `chain_code`
+    /// at `index`, as BIP32's \\(\mathrm{CKDpub}\\).
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the [`DerivedKey`], otherwise a string
+    /// describing the error.  As in BIP32, if the derived key would be
+    /// invalid, which happens with negligible probability, the next index
+    /// should be used instead.
+    pub fn derive_child(&self, chain_code: &[u8; 32], index: u32) -> Result<DerivedKey, &'static str> {
+        if index >= HARDENED {
+            return Err("Hardened derivation is not possible from a public key");
+        }
+
+        let I = hmac_sha512(chain_code, &[&self.0.to_bytes()[..], &index.to_be_bytes()]);
+        let mut IL = [0u8; 32];
+        let mut IR = [0u8; 32];
+
+        IL.copy_from_slice(&I[..32]);
+        IR.copy_from_slice(&I[32..]);
+
+        let tweak: Scalar = Option::from(Scalar::from_repr(FieldBytes::from(IL)))
+            .ok_or("Invalid child key, use the next index")?;
+        let child = (ProjectivePoint::from(self.0) + (ProjectivePoint::GENERATOR * tweak)).to_affine();
+
+        if child == AffinePoint::IDENTITY {
+            return Err("Invalid child key, use the next index");
+        }
+
+        Ok(DerivedKey { group_key: GroupKey(child), chain_code: IR, tweak })
+    }
+
+    /// Derive the descendant of this key with the given `chain_code` along a
+    /// `path` of non-hardened indices, e.g. from [`parse_derivation_path`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the [`DerivedKey`], whose tweak is the sum
+    /// of the tweaks along the path, otherwise a string describing the error.
+    pub fn derive(&self, chain_code: &[u8; 32], path: &[u32]) -> Result<DerivedKey, &'static str> {
+        let mut derived = DerivedKey { group_key: *self, chain_code: *chain_code, tweak: Scalar::ZERO };
+
+        for index in path.iter() {
+            let child = derived.group_key.derive_child(&derived.chain_code, *index)?;
+
+            derived = DerivedKey { tweak: derived.tweak + child.tweak, ..child };
+        }
+
+        Ok(derived)
+    }
+}
+
+impl SecretKey {
+    /// Tweak this secret key share by a [`DerivedKey::tweak`], so that it is
+    /// a share of the derived key.
+    pub fn tweak(&self, tweak: &Scalar) -> SecretKey {
+        SecretKey { index: self.index, key: self.key + tweak }
+    }
+}
+
+impl IndividualPublicKey {
+    /// Tweak this public key share by a [`DerivedKey::tweak`], consistently
+    /// with [`SecretKey::tweak`].
+    pub fn tweak(&self, tweak: &Scalar) -> IndividualPublicKey {
+        let share = ProjectivePoint::from(self.share) + (ProjectivePoint::GENERATOR * tweak);
+
+        IndividualPublicKey { index: self.index, share: share.to_affine() }
+    }
+}
+
+impl PublicKeyPackage {
+    /// Derive the public key material for the descendant of the group key
+    /// with the given `chain_code` along a `path`, e.g. to construct a
+    /// [`SignatureAggregator`](crate::signature::SignatureAggregator) for it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the derived package and the
+    /// [`DerivedKey`], otherwise a string describing the error.
+    pub fn derive(&self, chain_code: &[u8; 32], path: &[u32]) -> Result<(PublicKeyPackage, DerivedKey), &'static str> {
+        let derived = self.group_key().derive(chain_code, path)?;
+        let public_keys = self.public_keys().iter().map(|key| key.tweak(&derived.tweak)).collect();
+        let package = PublicKeyPackage::new(*self.parameters(), derived.group_key, public_keys)?;
+
+        Ok((package, derived))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::SignatureAggregator;
+
+    use k256::CompressedPoint;
+
+    use rand::rngs::OsRng;
+
+    fn from_hex<const N: usize>(s: &str) -> [u8; N] {
+        let mut bytes = [0u8; N];
+
+        bytes.copy_from_slice(&hex::decode(s).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn bip32_test_vector_1() {
+        // The public derivation of m/0H/1 from m/0H.
+        let parent = GroupKey::from_bytes(CompressedPoint::from(from_hex::<33>(
+            "035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56"))).unwrap();
+        let chain_code = from_hex("47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141");
+        let child = parent.derive(&chain_code, &[1]).unwrap();
+
+        assert_eq!(hex::encode(child.group_key.to_bytes()),
+                   "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c");
+        assert_eq!(hex::encode(child.chain_code),
+                   "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19");
+    }
+
+    #[test]
+    fn derivation_paths() {
+        assert_eq!(parse_derivation_path("m"), Ok(vec![]));
+        assert_eq!(parse_derivation_path("m/60/0/7"), Ok(vec![60, 0, 7]));
+        assert!(parse_derivation_path("m/44'/60").is_err());
+        assert!(parse_derivation_path("m/2147483648").is_err());
+        assert!(parse_derivation_path("0/1").is_err());
+        assert!(parse_derivation_path("m/x").is_err());
+
+        let (_, package) = dealt_group(Parameters { n: 3, t: 2 });
+        let group_key = *package.group_key();
+        let chain_code = [7u8; 32];
+
+        // Deriving in steps is the same as deriving along the whole path.
+        let step = group_key.derive(&chain_code, &[5]).unwrap();
+        let step = step.group_key.derive(&step.chain_code, &[9]).unwrap();
+        let whole = group_key.derive(&chain_code, &[5, 9]).unwrap();
+
+        assert_eq!(step.group_key, whole.group_key);
+        assert_eq!(step.chain_code, whole.chain_code);
+        assert_eq!(group_key.derive(&chain_code, &[]).unwrap().group_key, group_key);
+        assert!(group_key.derive_child(&chain_code, HARDENED).is_err());
+    }
+
+    #[test]
+    fn signing_with_a_derived_key() {
+        let (participants, package) = dealt_group(Parameters { n: 5, t: 3 });
+        let chain_code = [42u8; 32];
+
+        let (child_package, derived) = package.derive(&chain_code, &[1, 7]).unwrap();
+
+        let message = b"withdraw on chain 137";
+        let mut aggregator = SignatureAggregator::from_public_key_package(child_package, b"CTX".to_vec(), message.to_vec());
+        let mut secret_comshares = Vec::new();
+
+        for p in participants[..3].iter() {
+            let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, p.public_key.index, 1);
+
+            aggregator.try_include_signer(p.public_key.index, public_comshares.commitments[0]).unwrap();
+            secret_comshares.push(secret);
+        }
+
+        let signers = aggregator.get_signers().clone();
+        let message_hash = *aggregator.message_hash();
+
+        for (p, secret) in participants[..3].iter().zip(secret_comshares.iter_mut()) {
+            let sk = p.secret_key();
+            let child_sk = sk.tweak(&derived.tweak);
+
+            assert_eq!(child_sk.to_public().share, p.public_key.tweak(&derived.tweak).share);
+
+            let partial = child_sk.sign(&message_hash, &derived.group_key, secret, 0, &signers).unwrap();
+
+            aggregator.include_partial_signature(partial).unwrap();
+        }
+
+        let signature = aggregator.finalize().unwrap().aggregate().unwrap();
+
+        assert!(signature.verify(&derived.group_key, &message_hash).is_ok());
+        assert!(signature.verify(package.group_key(), &message_hash).is_err());
+    }
+}
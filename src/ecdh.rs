@@ -0,0 +1,267 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Threshold Diffie-Hellman, for decrypting messages sent to the group.
+//!
+//! A sender [`encapsulate`]s a symmetric key to the [`GroupKey`] \\(Y = xG\\)
+//! by picking an ephemeral secret \\(e\\), publishing \\(P = eG\\), and
+//! deriving the key from \\(eY = xP\\), which it uses to encrypt the message
+//! with an AEAD of its choice.
+//!
+//! To recover the key, \\(t\\) participants each compute a
+//! [`DiffieHellmanShare`] \\(x\_i P\\) with
+//! [`SecretKey::diffie_hellman_share`], together with a
+//! [`NizkOfDiscreteLogEquality`] that it uses the same secret as their
+//! [`IndividualPublicKey`] \\(Y\_i = x\_i G\\).  A combiner then checks the
+//! proofs and interpolates \\(xP = \sum\_i \lambda\_i x\_i P\\) with
+//! [`combine_diffie_hellman_shares`], identifying anyone whose share is
+//! invalid, and derives the same key with [`derive_symmetric_key`].
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::elliptic_curve::Field;
+use k256::elliptic_curve::group::GroupEncoding;
+
+use rand::CryptoRng;
+use rand::Rng;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use sha3::Digest;
+use sha3::Keccak256;
+
+use crate::keygen::GroupKey;
+use crate::keygen::IndividualPublicKey;
+use crate::keygen::PublicKeyPackage;
+use crate::keygen::SecretKey;
+use crate::nizk::NizkOfDiscreteLogEquality;
+use crate::signature::calculate_lagrange_coefficients;
+
+/// The domain separator for the [`NizkOfDiscreteLogEquality`] of a
+/// [`DiffieHellmanShare`].
+const DLEQ_DOMAIN: &[u8] = b"FROST-ECDH-SHARE";
+
+/// Derive a symmetric key from the `ephemeral` point \\(P\\), the `shared`
+/// Diffie-Hellman point \\(xP\\), and some application-specific `info`.
+pub fn derive_symmetric_key(ephemeral: &AffinePoint, shared: &AffinePoint, info: &[u8]) -> [u8; 32] {
+    let mut h = Keccak256::default();
+
+    h.update(b"FROST-ECDH");
+    h.update(ephemeral.to_bytes());
+    h.update(shared.to_bytes());
+    h.update(info);
+
+    h.finalize().into()
+}
+
+/// Generate a fresh symmetric key for encrypting a message to the group.
+///
+/// # Returns
+///
+/// The ephemeral point, which must be sent along with the ciphertext, and the
+/// symmetric key.
+pub fn encapsulate(
+    group_key: &GroupKey,
+    info: &[u8],
+    mut csprng: impl Rng + CryptoRng,
+) -> (AffinePoint, [u8; 32])
+{
+    let mut e = Scalar::random(&mut csprng);
+
+    while e == Scalar::ZERO {
+        e = Scalar::random(&mut csprng);
+    }
+
+    let ephemeral = (AffinePoint::GENERATOR * e).to_affine();
+    let shared = (group_key.0 * e).to_affine();
+
+    (ephemeral, derive_symmetric_key(&ephemeral, &shared, info))
+}
+
+/// A participant's share \\(x\_i P\\) of the Diffie-Hellman point \\(xP\\).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiffieHellmanShare {
+    /// The participant index of the holder of this share.
+    pub index: u32,
+    /// The share \\(x\_i P\\).
+    pub share: AffinePoint,
+    /// A proof that the share uses the same secret as the participant's
+    /// [`IndividualPublicKey`].
+    pub proof: NizkOfDiscreteLogEquality,
+}
+
+impl DiffieHellmanShare {
+    /// Verify this share of the Diffie-Hellman point on an `ephemeral` point
+    /// against the holder's `public_key`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the share is valid,
+    /// otherwise a string describing why it is not.
+    pub fn verify(&self, ephemeral: &AffinePoint, public_key: &IndividualPublicKey) -> Result<(), &'static str> {
+        if public_key.index != self.index {
+            return Err("Public key does not belong to the holder of this share");
+        }
+        if *ephemeral == AffinePoint::IDENTITY {
+            return Err("Ephemeral point is the identity");
+        }
+
+        self.proof.verify(DLEQ_DOMAIN, &public_key.share, ephemeral, &self.share)
+    }
+}
+
+impl SecretKey {
+    /// Compute our [`DiffieHellmanShare`] of the Diffie-Hellman point for an
+    /// `ephemeral` point, from [`encapsulate`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is our share, which should be sent to the
+    /// combiner, otherwise a string describing the error.
+    pub fn diffie_hellman_share(
+        &self,
+        ephemeral: &AffinePoint,
+        csprng: impl Rng + CryptoRng,
+    ) -> Result<DiffieHellmanShare, &'static str>
+    {
+        if *ephemeral == AffinePoint::IDENTITY {
+            return Err("Ephemeral point is the identity");
+        }
+
+        let public_key = (AffinePoint::GENERATOR * self.key).to_affine();
+        let share = (*ephemeral * self.key).to_affine();
+        let proof = NizkOfDiscreteLogEquality::prove(DLEQ_DOMAIN, &self.key, &public_key, ephemeral, &share, csprng);
+
+        Ok(DiffieHellmanShare { index: self.index, share, proof })
+    }
+}
+
+/// Combine at least \\(t\\) participants' [`DiffieHellmanShare`]s for an
+/// `ephemeral` point into the Diffie-Hellman point \\(xP\\), from which the
+/// symmetric key can be derived with [`derive_symmetric_key`].
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is the Diffie-Hellman point, otherwise a
+/// `HashMap<u32, &'static str>` containing the participant indices of the
+/// misbehaving participants and a description of their misbehaviour.  A key
+/// of `0` indicates that there were not enough valid shares, or that the
+/// `ephemeral` point is the identity.
+pub fn combine_diffie_hellman_shares(
+    public_key_package: &PublicKeyPackage,
+    ephemeral: &AffinePoint,
+    shares: &[DiffieHellmanShare],
+) -> Result<AffinePoint, HashMap<u32, &'static str>>
+{
+    let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+    let mut valid: Vec<&DiffieHellmanShare> = Vec::with_capacity(shares.len());
+
+    if *ephemeral == AffinePoint::IDENTITY {
+        misbehaving_participants.insert(0, "Ephemeral point is the identity");
+        return Err(misbehaving_participants);
+    }
+
+    for share in shares.iter() {
+        let public_key = match public_key_package.public_key(share.index) {
+            Some(public_key) => public_key,
+            None => {
+                misbehaving_participants.insert(share.index, "Participant index out of range");
+                continue;
+            },
+        };
+
+        if valid.iter().any(|s| s.index == share.index) {
+            misbehaving_participants.insert(share.index, "Duplicate share");
+        } else if let Err(error) = share.verify(ephemeral, public_key) {
+            misbehaving_participants.insert(share.index, error);
+        } else {
+            valid.push(share);
+        }
+    }
+
+    if ! misbehaving_participants.is_empty() {
+        return Err(misbehaving_participants);
+    }
+    if valid.len() < public_key_package.parameters().t as usize {
+        misbehaving_participants.insert(0, "Not enough shares");
+        return Err(misbehaving_participants);
+    }
+
+    let all_participant_indices: Vec<u32> = valid.iter().map(|s| s.index).collect();
+    let mut shared = ProjectivePoint::IDENTITY;
+
+    for share in valid.iter() {
+        // This unwrap() cannot fail, since the indices were checked to be distinct.
+        let lambda = calculate_lagrange_coefficients(&share.index, &all_participant_indices).unwrap();
+
+        shared += share.share * lambda;
+    }
+
+    Ok(shared.to_affine())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn threshold_decapsulation() {
+        let (participants, package) = dealt_group(Parameters { n: 5, t: 3 });
+        let (ephemeral, key) = encapsulate(package.group_key(), b"bridge config", OsRng);
+
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]].iter() {
+            let shares: Vec<DiffieHellmanShare> = subset.iter()
+                .map(|i| participants[*i].secret_key().diffie_hellman_share(&ephemeral, OsRng).unwrap())
+                .collect();
+            let shared = combine_diffie_hellman_shares(&package, &ephemeral, &shares).unwrap();
+
+            assert_eq!(derive_symmetric_key(&ephemeral, &shared, b"bridge config"), key);
+            assert_ne!(derive_symmetric_key(&ephemeral, &shared, b"other"), key);
+        }
+    }
+
+    #[test]
+    fn invalid_shares_are_identified() {
+        let (participants, package) = dealt_group(Parameters { n: 5, t: 3 });
+        let (ephemeral, _) = encapsulate(package.group_key(), b"", OsRng);
+        let mut shares: Vec<DiffieHellmanShare> = participants[..4].iter()
+            .map(|p| p.secret_key().diffie_hellman_share(&ephemeral, OsRng).unwrap())
+            .collect();
+
+        // Participant 2 submits a bogus share, and participant 4 claims to be 5.
+        shares[1].share = (ProjectivePoint::from(shares[1].share) + ephemeral).to_affine();
+        shares[3].index = 5;
+
+        let misbehaving = combine_diffie_hellman_shares(&package, &ephemeral, &shares).unwrap_err();
+
+        assert_eq!(misbehaving.len(), 2);
+        assert_eq!(misbehaving.get(&2), Some(&"Invalid proof of discrete logarithm equality"));
+        assert_eq!(misbehaving.get(&5), Some(&"Invalid proof of discrete logarithm equality"));
+
+        let misbehaving = combine_diffie_hellman_shares(&package, &ephemeral, &shares[..1]).unwrap_err();
+
+        assert_eq!(misbehaving.get(&0), Some(&"Not enough shares"));
+
+        let duplicated = vec![shares[0].clone(), shares[0].clone(), shares[2].clone()];
+        let misbehaving = combine_diffie_hellman_shares(&package, &ephemeral, &duplicated).unwrap_err();
+
+        assert_eq!(misbehaving.get(&1), Some(&"Duplicate share"));
+        assert!(participants[0].secret_key().diffie_hellman_share(&AffinePoint::IDENTITY, OsRng).is_err());
+    }
+}
@@ -0,0 +1,586 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! On-chain verification of threshold signatures with the [EthSchnorr] contract.
+//!
+//! The [`ThresholdSignature`]s produced by this crate are meant to be checked by
+//! Chainlink's `SchnorrSECP256K1` contract:
+//!
+//! ```solidity
+//! function verifySignature(uint256 signingPubKeyX, uint8 pubKeyYParity,
+//!                          uint256 signature, uint256 msgHash,
+//!                          address nonceTimesGeneratorAddress)
+//!     external pure returns (bool);
+//! ```
+//!
+//! An [`EthSchnorrCalldata`] holds exactly this argument tuple, can ABI-encode
+//! it, and can run a faithful reimplementation of the contract's checks, so
+//! that a signature may be tested off-chain before spending any gas on it.
+//!
+//! # Group keys
+//!
+//! The contract only accepts signing keys whose x-coordinate is below
+//! `HALF_Q`, which a uniformly random group key satisfies with probability
+//! roughly one half.  Negating every share does not help, since \\(-Y\\) has
+//! the same x-coordinate as \\(Y\\), so an unsuitable key can only be discarded
+//! and the key generation run again.  Because every participant computes the
+//! same [`GroupKey`] from the same broadcast commitments, they all reach the
+//! same verdict without any further communication; see
+//! [`DistributedKeyGeneration::ethschnorr_group_key`] and
+//! [`DistributedKeyGeneration::finish_for_ethschnorr`].
+//!
+//! [EthSchnorr]: https://github.com/smartcontractkit/chainlink/blob/v1.0.1/contracts/src/v0.5/dev/SchnorrSECP256K1.sol
+
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use k256::AffinePoint;
+use k256::FieldBytes;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::U256;
+use k256::elliptic_curve::AffineXCoordinate;
+use k256::elliptic_curve::DecompressPoint;
+use k256::elliptic_curve::PrimeField;
+use k256::elliptic_curve::ops::LinearCombination;
+use k256::elliptic_curve::ops::Reduce;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use sha3::Digest;
+use sha3::Keccak256;
+
+use crate::keygen::DistributedKeyGeneration;
+use crate::keygen::GroupKey;
+#[cfg(feature = "std")]
+use crate::keygen::DealtParticipant;
+#[cfg(feature = "std")]
+use crate::keygen::Participant;
+use crate::keygen::RoundOne;
+use crate::keygen::RoundTwo;
+use crate::keygen::SecretKey;
+#[cfg(feature = "std")]
+use crate::keygen::VerifiableSecretSharingCommitment;
+#[cfg(feature = "std")]
+use crate::parameters::Parameters;
+#[cfg(feature = "std")]
+use crate::signature::Finalized;
+#[cfg(feature = "std")]
+use crate::signature::SignatureAggregator;
+use crate::signature::ThresholdSignature;
+
+/// The order of the secp256k1 group, `Q` in the contract.
+const Q: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+/// `HALF_Q = (Q >> 1) + 1` in the contract.  Signing keys must have an
+/// x-coordinate strictly below this.
+pub(crate) const HALF_Q: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa1,
+];
+
+/// The Solidity signature of the contract's verification function.
+pub const VERIFY_SIGNATURE_FUNCTION: &str = "verifySignature(uint256,uint8,uint256,uint256,address)";
+
+/// Compute the Ethereum address of a `point`, i.e. the last 20 bytes of the
+/// Keccak256 hash of its uncompressed encoding.
+pub(crate) fn ethereum_address(point: &AffinePoint) -> [u8; 20] {
+    let encoded = point.to_encoded_point(false);
+    let hash = Keccak256::digest(&encoded.as_bytes()[1..]);
+    let mut address = [0u8; 20];
+
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// The error returned when a key generation produced a [`GroupKey`] which the
+/// EthSchnorr contract will not accept.
+const INVALID_GROUP_KEY: &str = "Group key x-coordinate is not below HALF_Q; the key generation must be restarted";
+
+impl GroupKey {
+    /// Determine whether the EthSchnorr contract will accept signatures made
+    /// with this group key, i.e. whether its x-coordinate is below `HALF_Q`.
+    pub fn is_valid_for_ethschnorr(&self) -> bool {
+        self.0.x()[..] < HALF_Q[..]
+    }
+}
+
+impl DistributedKeyGeneration<RoundOne> {
+    /// Calculate the group key this protocol run will produce, and check that
+    /// it is usable with the EthSchnorr contract.
+    ///
+    /// This should be called by every participant before sending out their
+    /// secret shares.  Every honest participant gets the same answer, so if
+    /// this fails they may all abort and start over with fresh
+    /// [`Participant`](crate::keygen::Participant)s, without having revealed any secret shares.
+    ///
+    /// # Returns
+    ///
+    /// The [`GroupKey`], or a string describing why it is unusable.
+    pub fn ethschnorr_group_key(&self, my_commitment: &AffinePoint) -> Result<GroupKey, &'static str> {
+        let group_key = self.group_key(my_commitment).map_err(|_| "Could not calculate the group key")?;
+
+        match group_key.is_valid_for_ethschnorr() {
+            true => Ok(group_key),
+            false => Err(INVALID_GROUP_KEY),
+        }
+    }
+}
+
+impl DistributedKeyGeneration<RoundTwo> {
+    /// As [`DistributedKeyGeneration::finish`], but refuse to produce a group
+    /// key which the EthSchnorr contract would reject.
+    ///
+    /// Every honest participant gets the same answer.  If this fails, this
+    /// participant's long-lived secret key is zeroed, and all participants
+    /// must start over with fresh
+    /// [`Participant`](crate::keygen::Participant)s.
+    ///
+    /// # Returns
+    ///
+    /// The [`GroupKey`] and this participant's [`SecretKey`], or a string
+    /// describing why the key generation failed.
+    pub fn finish_for_ethschnorr(self, my_commitment: &AffinePoint) -> Result<(GroupKey, SecretKey), &'static str> {
+        let (group_key, secret_key) = self.finish(my_commitment).map_err(|_| "Could not calculate the group key")?;
+
+        // The secret key is zeroed when it is dropped.
+        match group_key.is_valid_for_ethschnorr() {
+            true => Ok((group_key, secret_key)),
+            false => Err(INVALID_GROUP_KEY),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Participant {
+    /// As [`Participant::dealer`], but repeatedly deal new key material until
+    /// the [`GroupKey`] is one which the EthSchnorr contract will accept.
+    pub fn dealer_for_ethschnorr(parameters: &Parameters) -> (Vec<DealtParticipant>, VerifiableSecretSharingCommitment) {
+        loop {
+            let (participants, commitment) = Participant::dealer(parameters);
+
+            if GroupKey(participants[0].group_key).is_valid_for_ethschnorr() {
+                return (participants, commitment);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl SignatureAggregator<Finalized> {
+    /// Aggregate the partial signatures as with [`SignatureAggregator::aggregate`],
+    /// and additionally check the signature exactly as the EthSchnorr contract
+    /// will, so that a signature which would fail on-chain is never returned.
+    ///
+    /// # Returns
+    ///
+    /// A Result whose Ok() value is a [`ThresholdSignature`], otherwise the
+    /// misbehaving participants, as for [`SignatureAggregator::aggregate`].  A
+    /// key of `0` may also indicate that the contract would reject the
+    /// signature, e.g. because the [`GroupKey`] is not usable on-chain (see
+    /// [`GroupKey::is_valid_for_ethschnorr`]), in which case the value is the
+    /// contract's own revert string.
+    pub fn aggregate_for_ethschnorr(&self) -> Result<ThresholdSignature, HashMap<u32, &'static str>> {
+        let signature = self.aggregate()?;
+        let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+
+        match signature.to_ethschnorr_calldata(&self.state.group_key, &self.aggregator.message_hash).verify() {
+            Ok(()) => Ok(signature),
+            Err(error) => {
+                misbehaving_participants.insert(0, error);
+                Err(misbehaving_participants)
+            },
+        }
+    }
+}
+
+/// The arguments to the EthSchnorr contract's `verifySignature()` for some
+/// [`ThresholdSignature`] on a message hash.
+///
+/// All integers are stored as big-endian bytes, exactly as they appear on-chain.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EthSchnorrCalldata {
+    /// The x-coordinate of the [`GroupKey`].
+    pub signing_pub_key_x: [u8; 32],
+    /// The parity of the y-coordinate of the [`GroupKey`]: `0` if even, `1` if odd.
+    pub pub_key_y_parity: u8,
+    /// The \\(z\\) value of the [`ThresholdSignature`].
+    pub signature: [u8; 32],
+    /// The message hash which was signed.
+    pub msg_hash: [u8; 32],
+    /// The Ethereum address of the group commitment \\(R\\).
+    pub nonce_times_generator_address: [u8; 20],
+}
+
+impl ThresholdSignature {
+    /// Convert this signature, along with the `group_key` and `message_hash`
+    /// it was made with, into the argument tuple for the EthSchnorr contract.
+    pub fn to_ethschnorr_calldata(&self, group_key: &GroupKey, message_hash: &[u8; 32]) -> EthSchnorrCalldata {
+        let group_key_bytes = group_key.to_bytes();
+        let mut signing_pub_key_x = [0u8; 32];
+
+        signing_pub_key_x.copy_from_slice(&group_key_bytes[1..]);
+
+        EthSchnorrCalldata {
+            signing_pub_key_x,
+            pub_key_y_parity: group_key_bytes[0] & 1,
+            signature: self.z.to_bytes().into(),
+            msg_hash: *message_hash,
+            nonce_times_generator_address: ethereum_address(&self.R),
+        }
+    }
+}
+
+impl EthSchnorrCalldata {
+    /// ABI-encode the five arguments, as 32-byte words, without a function selector.
+    pub fn abi_encode(&self) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::with_capacity(5 * 32);
+        let mut word = [0u8; 32];
+
+        encoded.extend_from_slice(&self.signing_pub_key_x);
+
+        word[31] = self.pub_key_y_parity;
+        encoded.extend_from_slice(&word);
+
+        encoded.extend_from_slice(&self.signature);
+        encoded.extend_from_slice(&self.msg_hash);
+
+        word = [0u8; 32];
+        word[12..].copy_from_slice(&self.nonce_times_generator_address);
+        encoded.extend_from_slice(&word);
+
+        encoded
+    }
+
+    /// ABI-encode a complete call to `verifySignature()`, i.e. the four-byte
+    /// function selector followed by [`EthSchnorrCalldata::abi_encode`].
+    pub fn abi_encode_call(&self) -> Vec<u8> {
+        let selector = Keccak256::digest(VERIFY_SIGNATURE_FUNCTION.as_bytes());
+        let mut encoded: Vec<u8> = Vec::with_capacity(4 + 5 * 32);
+
+        encoded.extend_from_slice(&selector[..4]);
+        encoded.extend_from_slice(&self.abi_encode());
+        encoded
+    }
+
+    /// Compute the contract's `msgChallenge`, the Keccak256 hash of
+    /// `abi.encodePacked(signingPubKeyX, pubKeyYParity, msgHash, nonceTimesGeneratorAddress)`.
+    pub(crate) fn challenge(&self) -> [u8; 32] {
+        let mut h = Keccak256::default();
+
+        h.update(self.signing_pub_key_x);
+        h.update([self.pub_key_y_parity]);
+        h.update(self.msg_hash);
+        h.update(self.nonce_times_generator_address);
+
+        h.finalize().into()
+    }
+
+    /// Check these arguments in exactly the way the EthSchnorr contract does.
+    ///
+    /// This mirrors the contract's `require()`s, in the same order, and then
+    /// emulates its `ecrecover`-based check of
+    /// \\(e \cdot Y + z \cdot G \stackrel{?}{=} R\\).
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the contract would
+    /// return `true`, otherwise a string describing either the revert reason
+    /// or why the contract would return `false`.
+    pub fn verify(&self) -> Result<(), &'static str> {
+        if self.signing_pub_key_x >= HALF_Q {
+            return Err("Public-key x >= HALF_Q");
+        }
+        if self.signature >= Q {
+            return Err("signature must be reduced modulo Q");
+        }
+        if self.nonce_times_generator_address == [0u8; 20] ||
+            self.signing_pub_key_x == [0u8; 32] ||
+            self.signature == [0u8; 32] ||
+            self.msg_hash == [0u8; 32]
+        {
+            return Err("no zero inputs allowed");
+        }
+
+        let msg_challenge = self.challenge();
+
+        // Both of these are below Q, per the checks above.
+        let x = Scalar::from_repr(FieldBytes::from(self.signing_pub_key_x)).unwrap();
+        let signature = Scalar::from_repr(FieldBytes::from(self.signature)).unwrap();
+        let e = <Scalar as Reduce<U256>>::from_be_bytes_reduced(FieldBytes::from(msg_challenge));
+
+        // ecrecover(bytes32(Q - mulmod(signingPubKeyX, signature, Q)),
+        //           (pubKeyYParity == 0) ? 27 : 28,
+        //           bytes32(signingPubKeyX),
+        //           bytes32(mulmod(msgChallenge, signingPubKeyX, Q)))
+        let hash = -(x * signature);
+        let v = if self.pub_key_y_parity == 0 { 27u8 } else { 28u8 };
+        let r = x;
+        let s = e * x;
+
+        match ecrecover(&hash, v, &r, &s) {
+            Some(address) if address == self.nonce_times_generator_address => Ok(()),
+            _ => Err("Recovered address does not match nonceTimesGeneratorAddress"),
+        }
+    }
+}
+
+/// Emulate the `ecrecover` precompile, with all inputs already reduced modulo
+/// the group order.
+///
+/// # Returns
+///
+/// The recovered address, or `None` where the precompile would return the
+/// zero address.
+fn ecrecover(hash: &Scalar, v: u8, r: &Scalar, s: &Scalar) -> Option<[u8; 20]> {
+    if v != 27 && v != 28 {
+        return None;
+    }
+    if bool::from(r.is_zero()) || bool::from(s.is_zero()) {
+        return None;
+    }
+
+    let R = Option::<AffinePoint>::from(AffinePoint::decompress(&r.to_bytes(), (v - 27).into()))?;
+    let r_inverse = Option::<Scalar>::from(r.invert())?;
+
+    // r^{-1} (s R - hash G)
+    let recovered = ProjectivePoint::lincomb(&R.into(), &(*s * r_inverse), &ProjectivePoint::GENERATOR, &-(*hash * r_inverse));
+    let recovered = recovered.to_affine();
+
+    if recovered == AffinePoint::IDENTITY {
+        return None;
+    }
+    Some(ethereum_address(&recovered))
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::keygen::Participant;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::compute_message_hash;
+    use crate::signature::SignatureAggregator;
+
+    use rand::rngs::OsRng;
+
+    /// Run a 2-out-of-3 key generation, returning each participant's verdict
+    /// after round one and after finishing.
+    #[allow(clippy::type_complexity)]
+    fn ethschnorr_dkg() -> (Vec<Result<GroupKey, &'static str>>, Vec<Result<(GroupKey, SecretKey), &'static str>>) {
+        let params = Parameters { t: 2, n: 3 };
+
+        let (p1, p1coeffs) = Participant::new(&params, 1);
+        let (p2, p2coeffs) = Participant::new(&params, 2);
+        let (p3, p3coeffs) = Participant::new(&params, 3);
+
+        let mut p1_other_participants: Vec<Participant> = vec!(p2.clone(), p3.clone());
+        let p1_state = DistributedKeyGeneration::<RoundOne>::new(&params, &p1.index, &p1coeffs, &mut p1_other_participants).unwrap();
+        let p1_their_secret_shares = p1_state.their_secret_shares().unwrap();
+
+        let mut p2_other_participants: Vec<Participant> = vec!(p1.clone(), p3.clone());
+        let p2_state = DistributedKeyGeneration::<RoundOne>::new(&params, &p2.index, &p2coeffs, &mut p2_other_participants).unwrap();
+        let p2_their_secret_shares = p2_state.their_secret_shares().unwrap();
+
+        let mut p3_other_participants: Vec<Participant> = vec!(p1.clone(), p2.clone());
+        let p3_state = DistributedKeyGeneration::<RoundOne>::new(&params, &p3.index, &p3coeffs, &mut p3_other_participants).unwrap();
+        let p3_their_secret_shares = p3_state.their_secret_shares().unwrap();
+
+        let round_one = vec!(p1_state.ethschnorr_group_key(&p1.public_key().unwrap()),
+                             p2_state.ethschnorr_group_key(&p2.public_key().unwrap()),
+                             p3_state.ethschnorr_group_key(&p3.public_key().unwrap()));
+
+        let p1_my_secret_shares = vec!(p2_their_secret_shares[0].clone(), p3_their_secret_shares[0].clone());
+        let p2_my_secret_shares = vec!(p1_their_secret_shares[0].clone(), p3_their_secret_shares[1].clone());
+        let p3_my_secret_shares = vec!(p1_their_secret_shares[1].clone(), p2_their_secret_shares[1].clone());
+
+        let p1_state = p1_state.to_round_two(p1_my_secret_shares).unwrap();
+        let p2_state = p2_state.to_round_two(p2_my_secret_shares).unwrap();
+        let p3_state = p3_state.to_round_two(p3_my_secret_shares).unwrap();
+
+        let finished = vec!(p1_state.finish_for_ethschnorr(&p1.public_key().unwrap()),
+                            p2_state.finish_for_ethschnorr(&p2.public_key().unwrap()),
+                            p3_state.finish_for_ethschnorr(&p3.public_key().unwrap()));
+
+        (round_one, finished)
+    }
+
+    /// Have a dealt 2-out-of-3 group, whose key is or isn't usable with the
+    /// EthSchnorr contract, attempt to sign a `message`.
+    #[allow(clippy::type_complexity)]
+    fn dealer_sign(message: &[u8], valid_key: bool) -> (GroupKey, Result<ThresholdSignature, HashMap<u32, &'static str>>, [u8; 32]) {
+        let params = Parameters { t: 2, n: 3 };
+        let (participants, package) = loop {
+            let (participants, package) = dealt_group(params);
+
+            if package.group_key().is_valid_for_ethschnorr() == valid_key {
+                break (participants, package);
+            }
+        };
+
+        let p1_sk = participants[0].secret_key();
+        let p2_sk = participants[1].secret_key();
+        let group_key = *package.group_key();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
+
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into()).unwrap();
+
+        let signers = aggregator.get_signers();
+        let message_hash = compute_message_hash(&context[..], message);
+
+        let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
+        let p2_partial = p2_sk.sign(&message_hash, &group_key, &mut p2_secret_comshares, 0, signers).unwrap();
+
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p2_partial).unwrap();
+
+        let aggregator = aggregator.finalize().unwrap();
+
+        (group_key, aggregator.aggregate_for_ethschnorr(), message_hash)
+    }
+
+    #[test]
+    fn half_q_is_half_the_group_order() {
+        let mut half_q = [0u8; 32];
+        let mut carry = 0u8;
+
+        // (Q >> 1) + 1, the long way around.
+        for (i, byte) in Q.iter().enumerate() {
+            half_q[i] = (byte >> 1) | carry;
+            carry = (byte & 1) << 7;
+        }
+        half_q[31] += 1;
+
+        assert_eq!(half_q, HALF_Q);
+    }
+
+    #[test]
+    fn all_participants_agree_on_ethschnorr_validity() {
+        let mut seen_valid = false;
+        let mut seen_invalid = false;
+
+        while !(seen_valid && seen_invalid) {
+            let (round_one, finished) = ethschnorr_dkg();
+
+            match round_one[0] {
+                Ok(group_key) => {
+                    seen_valid = true;
+
+                    assert!(group_key.is_valid_for_ethschnorr());
+                    assert!(round_one.iter().all(|r| r == &Ok(group_key)));
+                    assert!(finished.iter().all(|r| r.as_ref().unwrap().0 == group_key));
+                },
+                Err(e) => {
+                    seen_invalid = true;
+
+                    assert_eq!(e, INVALID_GROUP_KEY);
+                    assert!(round_one.iter().all(|r| r == &Err(INVALID_GROUP_KEY)));
+                    assert!(finished.iter().all(|r| r.as_ref().err() == Some(&INVALID_GROUP_KEY)));
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn dealt_keys_are_valid_for_ethschnorr() {
+        for _ in 0..8 {
+            let (participants, _) = Participant::dealer_for_ethschnorr(&Parameters { t: 2, n: 3 });
+
+            assert!(GroupKey(participants[0].group_key).is_valid_for_ethschnorr());
+        }
+    }
+
+    #[test]
+    fn calldata_verifies_like_the_contract() {
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+
+        for _ in 0..4 {
+            let (group_key, signature, message_hash) = dealer_sign(&message[..], true);
+            let signature = signature.unwrap();
+            let calldata = signature.to_ethschnorr_calldata(&group_key, &message_hash);
+
+            assert!(signature.verify(&group_key, &message_hash).is_ok());
+            assert!(calldata.signing_pub_key_x < HALF_Q);
+            assert!(calldata.verify().is_ok());
+        }
+    }
+
+    #[test]
+    fn aggregator_refuses_keys_invalid_for_ethschnorr() {
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let (_, signature, _) = dealer_sign(&message[..], false);
+        let misbehaving_participants = signature.unwrap_err();
+
+        // Nobody but the aggregator is to blame.
+        assert_eq!(misbehaving_participants.len(), 1);
+        assert_eq!(misbehaving_participants.get(&0), Some(&"Public-key x >= HALF_Q"));
+    }
+
+    #[test]
+    fn calldata_rejects_tampering() {
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+
+        let (group_key, signature, message_hash) = dealer_sign(&message[..], true);
+        let signature = signature.unwrap();
+        let calldata = signature.to_ethschnorr_calldata(&group_key, &message_hash);
+
+        let mut bad = calldata;
+        bad.msg_hash[0] ^= 1;
+        assert!(bad.verify().is_err());
+
+        let mut bad = calldata;
+        bad.pub_key_y_parity ^= 1;
+        assert!(bad.verify().is_err());
+
+        let mut bad = calldata;
+        bad.nonce_times_generator_address[19] ^= 1;
+        assert!(bad.verify().is_err());
+
+        let mut bad = calldata;
+        bad.signature = Q;
+        assert_eq!(bad.verify(), Err("signature must be reduced modulo Q"));
+
+        let mut bad = calldata;
+        bad.msg_hash = [0u8; 32];
+        assert_eq!(bad.verify(), Err("no zero inputs allowed"));
+    }
+
+    #[test]
+    fn calldata_abi_encoding() {
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let (group_key, signature, message_hash) = dealer_sign(&message[..], true);
+        let signature = signature.unwrap();
+        let calldata = signature.to_ethschnorr_calldata(&group_key, &message_hash);
+        let encoded = calldata.abi_encode_call();
+
+        assert_eq!(encoded.len(), 4 + 5 * 32);
+        assert_eq!(&encoded[..4], &Keccak256::digest(VERIFY_SIGNATURE_FUNCTION.as_bytes())[..4]);
+        assert_eq!(&encoded[4..36], &calldata.signing_pub_key_x[..]);
+        assert_eq!(&encoded[36..67], &[0u8; 31][..]);
+        assert_eq!(encoded[67], group_key.to_bytes()[0] & 1);
+        assert_eq!(&encoded[68..100], &signature.z.to_bytes()[..]);
+        assert_eq!(&encoded[100..132], &message_hash[..]);
+        assert_eq!(&encoded[132..144], &[0u8; 12][..]);
+        assert_eq!(&encoded[144..], &ethereum_address(&signature.R)[..]);
+    }
+}
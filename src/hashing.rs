@@ -0,0 +1,504 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Ethereum message hashing conventions.
+//!
+//! Rather than [`compute_message_hash`] of an application-specific context
+//! string and message, validators may sign digests which standard wallets and
+//! contracts know how to display and recompute:
+//!
+//! * [EIP-191] `personal_sign` messages, hashed with [`eip191_hash`], and
+//! * [EIP-712] typed structured data, hashed with [`eip712_hash`] from an
+//!   [`Eip712Domain`], the [`Eip712Types`] of the data, and its
+//!   [`Eip712Value`]s.
+//!
+//! The resulting digests are signed with
+//! [`SignatureAggregator::new_eip191`] and
+//! [`SignatureAggregator::new_eip712`] respectively, which record the
+//! [`HashingMode`] so that signers can recompute what they are being asked to
+//! sign with [`HashingMode::message_hash`].
+//!
+//! [`compute_message_hash`]: crate::signature::compute_message_hash
+//! [`SignatureAggregator::new_eip191`]: crate::signature::SignatureAggregator::new_eip191
+//! [`SignatureAggregator::new_eip712`]: crate::signature::SignatureAggregator::new_eip712
+//! [`HashingMode`]: crate::signature::HashingMode
+//! [`HashingMode::message_hash`]: crate::signature::HashingMode::message_hash
+//! [EIP-191]: https://eips.ethereum.org/EIPS/eip-191
+//! [EIP-712]: https://eips.ethereum.org/EIPS/eip-712
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::string::String;
+use std::string::ToString;
+use std::vec::Vec;
+
+use sha3::Digest;
+use sha3::Keccak256;
+
+/// Compute the [EIP-191] `personal_sign` hash of a `message`, i.e.
+/// \\(\mathrm{Keccak256}(\texttt{"\x19Ethereum Signed Message:\n"} || len(m) || m)\\)
+/// with the length in decimal.
+///
+/// [EIP-191]: https://eips.ethereum.org/EIPS/eip-191
+pub fn eip191_hash(message: &[u8]) -> [u8; 32] {
+    let mut h = Keccak256::default();
+
+    h.update(b"\x19Ethereum Signed Message:\n");
+    h.update(message.len().to_string().as_bytes());
+    h.update(message);
+
+    h.finalize().into()
+}
+
+/// Compute the [EIP-712] digest \\(\mathrm{Keccak256}(\texttt{0x1901} || domainSeparator || hashStruct(message))\\).
+///
+/// [EIP-712]: https://eips.ethereum.org/EIPS/eip-712
+pub fn eip712_digest(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    let mut h = Keccak256::default();
+
+    h.update([0x19, 0x01]);
+    h.update(domain_separator);
+    h.update(struct_hash);
+
+    h.finalize().into()
+}
+
+/// Compute the [EIP-712] digest of the `value` of type `primary_type` in a
+/// `domain`.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is the digest to be signed, otherwise a string
+/// describing why the `value` doesn't match the `types`.
+///
+/// [EIP-712]: https://eips.ethereum.org/EIPS/eip-712
+pub fn eip712_hash(
+    domain: &Eip712Domain,
+    types: &Eip712Types,
+    primary_type: &str,
+    value: &Eip712Value,
+) -> Result<[u8; 32], &'static str>
+{
+    Ok(eip712_digest(&domain.separator(), &types.hash_struct(primary_type, value)?))
+}
+
+/// A value of typed structured data, for [`Eip712Types::hash_struct`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Eip712Value {
+    /// An atomic value, i.e. a `uint<M>`, `int<M>`, `bool`, `address` or
+    /// `bytes<M>`, already encoded to 32 bytes as in the ABI.
+    Word([u8; 32]),
+    /// A dynamic `bytes` value.
+    Bytes(Vec<u8>),
+    /// A `string` value.
+    String(String),
+    /// A fixed or dynamic size array.
+    Array(Vec<Eip712Value>),
+    /// A struct, whose members are in the order of their declaration.
+    Struct(Vec<Eip712Value>),
+}
+
+impl Eip712Value {
+    /// Encode an unsigned integer.
+    pub fn uint(value: u128) -> Eip712Value {
+        let mut word = [0u8; 32];
+
+        word[16..].copy_from_slice(&value.to_be_bytes());
+        Eip712Value::Word(word)
+    }
+
+    /// Encode an `address`.
+    pub fn address(address: [u8; 20]) -> Eip712Value {
+        let mut word = [0u8; 32];
+
+        word[12..].copy_from_slice(&address);
+        Eip712Value::Word(word)
+    }
+
+    /// Encode a `bool`.
+    pub fn bool(value: bool) -> Eip712Value {
+        Eip712Value::uint(value as u128)
+    }
+
+    /// Encode a `string`.
+    pub fn string(value: &str) -> Eip712Value {
+        Eip712Value::String(value.to_string())
+    }
+}
+
+/// The struct types of some typed structured data, each a list of
+/// `(name, type)` pairs of its members.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Eip712Types(BTreeMap<String, Vec<(String, String)>>);
+
+impl Eip712Types {
+    /// Create a new, empty set of types.
+    pub fn new() -> Eip712Types {
+        Eip712Types::default()
+    }
+
+    /// Declare a struct type `name` with some `(name, type)` `members`.
+    pub fn insert(&mut self, name: &str, members: &[(&str, &str)]) {
+        let members = members.iter().map(|(n, t)| (n.to_string(), t.to_string())).collect();
+
+        self.0.insert(name.to_string(), members);
+    }
+
+    /// Compute `encodeType`, e.g. `"Mail(Person from,Person to,string contents)Person(string name,address wallet)"`,
+    /// of the struct type `name`: its own declaration followed by those of the
+    /// struct types it references, sorted by name.
+    pub fn encode_type(&self, name: &str) -> Result<String, &'static str> {
+        let mut referenced: BTreeSet<&str> = BTreeSet::new();
+
+        self.collect_referenced_types(name, &mut referenced)?;
+        referenced.remove(name);
+
+        let mut encoded = String::new();
+
+        for type_name in core::iter::once(name).chain(referenced) {
+            let members: Vec<String> = self.0[type_name].iter().map(|(n, t)| format!("{} {}", t, n)).collect();
+
+            encoded.push_str(&format!("{}({})", type_name, members.join(",")));
+        }
+
+        Ok(encoded)
+    }
+
+    /// Compute `typeHash`, the Keccak256 hash of the struct type `name`'s
+    /// [`Eip712Types::encode_type`].
+    pub fn type_hash(&self, name: &str) -> Result<[u8; 32], &'static str> {
+        Ok(Keccak256::digest(self.encode_type(name)?.as_bytes()).into())
+    }
+
+    /// Compute `hashStruct` of a `value` of the struct type `name`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the hash, otherwise a string describing
+    /// why the `value` doesn't match the type.
+    pub fn hash_struct(&self, name: &str, value: &Eip712Value) -> Result<[u8; 32], &'static str> {
+        let members = self.0.get(name).ok_or("Unknown struct type")?;
+        let values = match value {
+            Eip712Value::Struct(values) => values,
+            _ => return Err("Expected a struct value"),
+        };
+
+        if values.len() != members.len() {
+            return Err("Wrong number of struct members");
+        }
+
+        let mut h = Keccak256::default();
+
+        h.update(self.type_hash(name)?);
+
+        for ((_, member_type), member_value) in members.iter().zip(values) {
+            h.update(self.encode_data(member_type, member_value)?);
+        }
+
+        Ok(h.finalize().into())
+    }
+
+    /// Encode a `value` of any type as the 32 bytes of `encodeData`.
+    fn encode_data(&self, type_name: &str, value: &Eip712Value) -> Result<[u8; 32], &'static str> {
+        if let Some(element_type) = array_element_type(type_name) {
+            let elements = match value {
+                Eip712Value::Array(elements) => elements,
+                _ => return Err("Expected an array value"),
+            };
+            let mut h = Keccak256::default();
+
+            for element in elements.iter() {
+                h.update(self.encode_data(element_type, element)?);
+            }
+            return Ok(h.finalize().into());
+        }
+        if self.0.contains_key(type_name) {
+            return self.hash_struct(type_name, value);
+        }
+
+        match (type_name, value) {
+            ("string", Eip712Value::String(s)) => Ok(Keccak256::digest(s.as_bytes()).into()),
+            ("bytes", Eip712Value::Bytes(b)) => Ok(Keccak256::digest(b).into()),
+            (_, Eip712Value::Word(word)) if is_atomic_type(type_name) => Ok(*word),
+            _ => Err("Value does not match its type"),
+        }
+    }
+
+    /// Collect the struct type `name` and every struct type it references,
+    /// directly or indirectly.
+    fn collect_referenced_types<'a>(&'a self, name: &'a str, referenced: &mut BTreeSet<&'a str>) -> Result<(), &'static str> {
+        let members = self.0.get(name).ok_or("Unknown struct type")?;
+
+        if !referenced.insert(name) {
+            return Ok(());
+        }
+
+        for (_, member_type) in members.iter() {
+            let mut base_type = member_type.as_str();
+
+            while let Some(element_type) = array_element_type(base_type) {
+                base_type = element_type;
+            }
+            if self.0.contains_key(base_type) {
+                self.collect_referenced_types(base_type, referenced)?;
+            } else if !is_atomic_type(base_type) && base_type != "string" && base_type != "bytes" {
+                return Err("Unknown member type");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the element type of an array type, e.g. `Person` for `Person[]` or
+/// `Person[2]`, or `None` if `type_name` isn't an array type.
+fn array_element_type(type_name: &str) -> Option<&str> {
+    if !type_name.ends_with(']') {
+        return None;
+    }
+    type_name.rfind('[').map(|i| &type_name[..i])
+}
+
+/// Check whether `type_name` is one of the atomic types, which are encoded as
+/// a single 32-byte word.
+fn is_atomic_type(type_name: &str) -> bool {
+    let sized = |prefix: &str, valid: &dyn Fn(usize) -> bool| {
+        matches!(type_name.strip_prefix(prefix).and_then(|bits| bits.parse::<usize>().ok()), Some(m) if valid(m))
+    };
+
+    matches!(type_name, "bool" | "address" | "uint" | "int") ||
+        sized("uint", &|m| m > 0 && m <= 256 && m % 8 == 0) ||
+        sized("int", &|m| m > 0 && m <= 256 && m % 8 == 0) ||
+        sized("bytes", &|m| m > 0 && m <= 32)
+}
+
+/// An [EIP-712] domain, whose separator distinguishes the signatures of one
+/// application from those of another.
+///
+/// Fields which are `None` are omitted from the domain, as in
+/// `eth_signTypedData`.
+///
+/// [EIP-712]: https://eips.ethereum.org/EIPS/eip-712
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Eip712Domain {
+    /// The user readable name of the signing domain.
+    pub name: Option<String>,
+    /// The current major version of the signing domain.
+    pub version: Option<String>,
+    /// The EIP-155 chain id.
+    pub chain_id: Option<u64>,
+    /// The address of the contract which will verify the signature.
+    pub verifying_contract: Option<[u8; 20]>,
+    /// A disambiguating salt for the protocol.
+    pub salt: Option<[u8; 32]>,
+}
+
+impl Eip712Domain {
+    /// Compute the domain separator, `hashStruct(eip712Domain)`.
+    pub fn separator(&self) -> [u8; 32] {
+        let mut members: Vec<(&str, &str)> = Vec::new();
+        let mut values: Vec<Eip712Value> = Vec::new();
+
+        if let Some(name) = &self.name {
+            members.push(("name", "string"));
+            values.push(Eip712Value::string(name));
+        }
+        if let Some(version) = &self.version {
+            members.push(("version", "string"));
+            values.push(Eip712Value::string(version));
+        }
+        if let Some(chain_id) = self.chain_id {
+            members.push(("chainId", "uint256"));
+            values.push(Eip712Value::uint(chain_id as u128));
+        }
+        if let Some(verifying_contract) = self.verifying_contract {
+            members.push(("verifyingContract", "address"));
+            values.push(Eip712Value::address(verifying_contract));
+        }
+        if let Some(salt) = self.salt {
+            members.push(("salt", "bytes32"));
+            values.push(Eip712Value::Word(salt));
+        }
+
+        let mut types = Eip712Types::new();
+
+        types.insert("EIP712Domain", &members);
+        types.hash_struct("EIP712Domain", &Eip712Value::Struct(values))
+            .expect("the domain's values match its types")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::HashingMode;
+    use crate::signature::SignatureAggregator;
+
+    use rand::rngs::OsRng;
+
+    fn address(hex: &str) -> [u8; 20] {
+        let mut address = [0u8; 20];
+
+        address.copy_from_slice(&hex::decode(hex).unwrap());
+        address
+    }
+
+    /// The `Mail` example from the EIP-712 specification.
+    fn mail() -> (Eip712Domain, Eip712Types, Eip712Value) {
+        let domain = Eip712Domain {
+            name: Some("Ether Mail".to_string()),
+            version: Some("1".to_string()),
+            chain_id: Some(1),
+            verifying_contract: Some(address("CcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC")),
+            salt: None,
+        };
+        let mut types = Eip712Types::new();
+
+        types.insert("Person", &[("name", "string"), ("wallet", "address")]);
+        types.insert("Mail", &[("from", "Person"), ("to", "Person"), ("contents", "string")]);
+
+        let mail = Eip712Value::Struct(vec![
+            Eip712Value::Struct(vec![
+                Eip712Value::string("Cow"),
+                Eip712Value::address(address("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826")),
+            ]),
+            Eip712Value::Struct(vec![
+                Eip712Value::string("Bob"),
+                Eip712Value::address(address("bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB")),
+            ]),
+            Eip712Value::string("Hello, Bob!"),
+        ]);
+
+        (domain, types, mail)
+    }
+
+    #[test]
+    fn eip191_known_answer() {
+        assert_eq!(hex::encode(eip191_hash(b"Hello World")),
+                   "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2");
+    }
+
+    #[test]
+    fn eip712_known_answers() {
+        let (domain, types, mail) = mail();
+
+        assert_eq!(types.encode_type("Mail").unwrap(),
+                   "Mail(Person from,Person to,string contents)Person(string name,address wallet)");
+        assert_eq!(hex::encode(types.type_hash("Mail").unwrap()),
+                   "a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2");
+        assert_eq!(hex::encode(domain.separator()),
+                   "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f");
+        assert_eq!(hex::encode(types.hash_struct("Mail", &mail).unwrap()),
+                   "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e");
+        assert_eq!(hex::encode(eip712_hash(&domain, &types, "Mail", &mail).unwrap()),
+                   "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2");
+    }
+
+    #[test]
+    fn eip712_arrays() {
+        let mut types = Eip712Types::new();
+
+        types.insert("Person", &[("name", "string"), ("wallets", "address[]")]);
+        types.insert("Group", &[("name", "string"), ("members", "Person[]")]);
+
+        assert_eq!(types.encode_type("Group").unwrap(),
+                   "Group(string name,Person[] members)Person(string name,address[] wallets)");
+
+        let alice = Eip712Value::Struct(vec![
+            Eip712Value::string("Alice"),
+            Eip712Value::Array(vec![Eip712Value::address([1u8; 20]), Eip712Value::address([2u8; 20])]),
+        ]);
+        let group = Eip712Value::Struct(vec![Eip712Value::string("Group"), Eip712Value::Array(vec![alice.clone()])]);
+
+        // An array is encoded as the hash of its concatenated encoded elements.
+        let mut h = Keccak256::default();
+
+        h.update(types.hash_struct("Person", &alice).unwrap());
+
+        let members_hash: [u8; 32] = h.finalize().into();
+        let mut h = Keccak256::default();
+
+        h.update(types.type_hash("Group").unwrap());
+        h.update(Keccak256::digest(b"Group"));
+        h.update(members_hash);
+
+        assert_eq!(types.hash_struct("Group", &group).unwrap(), <[u8; 32]>::from(h.finalize()));
+    }
+
+    #[test]
+    fn signing_typed_data() {
+        let (domain, types, mail) = mail();
+        let params = Parameters { n: 2, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let struct_hash = types.hash_struct("Mail", &mail).unwrap();
+        let mut aggregator = SignatureAggregator::new_eip712(params, group_key, domain.separator(), struct_hash);
+        let mut secret_comshares = Vec::new();
+
+        for p in participants.iter() {
+            let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, p.public_key.index, 1);
+
+            aggregator.include_signer(p.public_key.index, public_comshares.commitments[0], p.public_key.clone()).unwrap();
+            secret_comshares.push(secret);
+        }
+
+        // Each signer recomputes the digest from the typed data they were shown.
+        let message_hash = eip712_hash(&domain, &types, "Mail", &mail).unwrap();
+
+        assert_eq!(aggregator.hashing_mode(), HashingMode::Eip712);
+        assert_eq!(aggregator.message_hash(), &message_hash);
+        assert_eq!(<[u8; 32]>::from(Keccak256::digest(aggregator.message())), message_hash);
+        assert_eq!(HashingMode::Eip712.message_hash(&[], aggregator.message()), Ok(message_hash));
+        assert_eq!(HashingMode::Eip712.message_hash(&[], &aggregator.message()[1..]), Err("Not an EIP-712 message"));
+
+        let signers = aggregator.get_signers().clone();
+
+        for (p, secret) in participants.iter().zip(secret_comshares.iter_mut()) {
+            let sk = p.secret_key();
+            let partial = sk.sign(&message_hash, &group_key, secret, 0, &signers).unwrap();
+
+            aggregator.include_partial_signature(partial).unwrap();
+        }
+
+        let signature = aggregator.finalize().unwrap().aggregate().unwrap();
+
+        assert!(signature.verify(&group_key, &message_hash).is_ok());
+
+        // The EIP-191 digest of the same bytes is a different one.
+        let aggregator = SignatureAggregator::new_eip191(params, group_key, b"Hello World".to_vec());
+
+        assert_eq!(aggregator.hashing_mode(), HashingMode::Eip191);
+        assert_eq!(aggregator.message_hash(), &eip191_hash(b"Hello World"));
+        assert_eq!(aggregator.message(), b"Hello World");
+        assert_eq!(aggregator.hashing_mode().message_hash(aggregator.context(), aggregator.message()),
+                   Ok(eip191_hash(b"Hello World")));
+    }
+
+    #[test]
+    fn eip712_type_mismatches() {
+        let (_, mut types, mail) = mail();
+
+        assert_eq!(types.hash_struct("Letter", &mail), Err("Unknown struct type"));
+        assert_eq!(types.hash_struct("Person", &mail), Err("Wrong number of struct members"));
+        assert_eq!(types.hash_struct("Mail", &Eip712Value::string("Hello, Bob!")), Err("Expected a struct value"));
+
+        let wrong = Eip712Value::Struct(vec![Eip712Value::string("Cow"), Eip712Value::string("Bob")]);
+
+        assert_eq!(types.hash_struct("Person", &wrong), Err("Value does not match its type"));
+
+        types.insert("Parcel", &[("weight", "uint7")]);
+
+        assert_eq!(types.encode_type("Parcel"), Err("Unknown member type"));
+    }
+}
@@ -188,6 +188,8 @@ use k256::elliptic_curve::Field;
 use k256::elliptic_curve::group::GroupEncoding;
 #[cfg(feature = "std")]
 use rand::rngs::OsRng;
+#[cfg(feature = "std")]
+use rand::{CryptoRng, Rng};
 
 use zeroize::Zeroize;
 
@@ -204,9 +206,22 @@ pub struct Coefficients(pub Vec<Scalar>);
 
 /// A commitment to the dealer's secret polynomial coefficients for Feldman's
 /// verifiable secret sharing scheme.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "Vec<AffinePoint>", into = "Vec<AffinePoint>")]
 pub struct VerifiableSecretSharingCommitment(pub(crate) Vec<ProjectivePoint>);
 
+impl From<Vec<AffinePoint>> for VerifiableSecretSharingCommitment {
+    fn from(points: Vec<AffinePoint>) -> VerifiableSecretSharingCommitment {
+        VerifiableSecretSharingCommitment(points.into_iter().map(ProjectivePoint::from).collect())
+    }
+}
+
+impl From<VerifiableSecretSharingCommitment> for Vec<AffinePoint> {
+    fn from(commitment: VerifiableSecretSharingCommitment) -> Vec<AffinePoint> {
+        commitment.0.iter().map(|point| point.to_affine()).collect()
+    }
+}
+
 /// A participant created by a trusted dealer.
 ///
 /// This can be used to create the participants' keys and secret shares without
@@ -319,7 +334,11 @@ impl Participant {
 }
 
 #[cfg(feature = "std")]
-fn generate_shares(parameters: &Parameters, secret: Scalar, mut rng: OsRng) -> (Vec<DealtParticipant>, VerifiableSecretSharingCommitment) {
+pub(crate) fn generate_shares(
+    parameters: &Parameters,
+    secret: Scalar,
+    mut rng: impl Rng + CryptoRng,
+) -> (Vec<DealtParticipant>, VerifiableSecretSharingCommitment) {
     let mut participants: Vec<DealtParticipant> = Vec::with_capacity(parameters.n as usize);
 
     // STEP 1: Every participant P_i samples t random values (a_{i0}, ..., a_{i(t-1)})
@@ -571,6 +590,22 @@ impl DistributedKeyGeneration<RoundOne> {
             data: RoundTwo {},
         })
     }
+
+    /// Calculate the group public key which this protocol run will produce.
+    ///
+    /// The group key depends only upon the commitments which every participant
+    /// has already broadcast, so it is known to everyone after round one, and
+    /// before any secret shares have been sent.  This allows an unsuitable key
+    /// to be detected, and the protocol restarted, early.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let group_key = state.group_key(&participant.public_key()?)?;
+    /// ```
+    pub fn group_key(&self, my_commitment: &AffinePoint) -> Result<GroupKey, ()> {
+        self.calculate_group_key(my_commitment)
+    }
 }
 
 /// A secret share calculated by evaluating a polynomial with secret
@@ -662,7 +697,9 @@ impl DistributedKeyGeneration<RoundTwo> {
 
         Ok(SecretKey { index: self.state.my_secret_share.index, key })
     }
+}
 
+impl<S: DkgState> DistributedKeyGeneration<S> {
     /// Calculate the group public key used for verifying threshold signatures.
     ///
     /// # Returns
@@ -784,6 +821,197 @@ impl GroupKey {
     }
 }
 
+/// The public key material for an entire group of participants: the
+/// protocol [`Parameters`], the [`GroupKey`], and every participant's
+/// [`IndividualPublicKey`].
+///
+/// This is everything a [`SignatureAggregator`](crate::signature::SignatureAggregator),
+/// or anyone else, needs in order to check who may sign for the group and to
+/// verify their partial signatures.
+#[derive(Clone, Debug)]
+pub struct PublicKeyPackage {
+    /// The parameters of the group.
+    pub(crate) parameters: Parameters,
+    /// The group's public key.
+    pub(crate) group_key: GroupKey,
+    /// The public key of every participant, sorted by index, such that the
+    /// key for participant `i` is at position `i - 1`.
+    pub(crate) public_keys: Vec<IndividualPublicKey>,
+}
+
+impl PublicKeyPackage {
+    /// Assemble and verify the public key material for a group.
+    ///
+    /// # Inputs
+    ///
+    /// * The protocol instance [`Parameters`],
+    /// * The [`GroupKey`], and
+    /// * The [`IndividualPublicKey`] of each of the \\(n\\) participants, in any order.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is a [`PublicKeyPackage`] if there is
+    /// exactly one public key for every participant index in \\(1..=n\\), and
+    /// if they are all shares of the same polynomial of degree \\(t-1\\)
+    /// whose constant term is the `group_key`.  Otherwise, its `Err` value
+    /// contains a string describing the error which occurred.
+    pub fn new(
+        parameters: Parameters,
+        group_key: GroupKey,
+        mut public_keys: Vec<IndividualPublicKey>,
+    ) -> Result<PublicKeyPackage, &'static str>
+    {
+        if parameters.t == 0 || parameters.t > parameters.n {
+            return Err("Invalid threshold parameters");
+        }
+        if public_keys.len() != parameters.n as usize {
+            return Err("Wrong number of public keys");
+        }
+
+        public_keys.sort_by_key(|key| key.index);
+
+        for (i, key) in public_keys.iter().enumerate() {
+            if key.index != i as u32 + 1 {
+                return Err("Public keys must have unique participant indices in 1..=n");
+            }
+        }
+
+        // Any t of the shares determine the polynomial, so interpolate the
+        // group key and the remaining shares from the first t of them.
+        let (basis, rest) = public_keys.split_at(parameters.t as usize);
+
+        if interpolate_in_the_exponent(0, basis).to_affine().to_bytes() != group_key.to_bytes() {
+            return Err("Public keys are inconsistent with the group key");
+        }
+        for key in rest.iter() {
+            if interpolate_in_the_exponent(key.index, basis).to_affine().to_bytes() != key.share.to_bytes() {
+                return Err("Public keys are not shares of the same polynomial");
+            }
+        }
+
+        Ok(PublicKeyPackage { parameters, group_key, public_keys })
+    }
+
+    /// Calculate the public key material for a group from the [`Participant`]s
+    /// broadcast by everyone in the distributed key generation protocol.
+    ///
+    /// The `participants` must include ourself.  Since the result depends
+    /// only upon public information, every participant, as well as any
+    /// outside observer of the protocol, calculates the same package.
+    ///
+    /// Every participant's proof of knowledge of their secret key is checked,
+    /// since otherwise a rogue participant could choose their commitments so
+    /// as to cancel out everyone else's, and control the group key.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is a [`PublicKeyPackage`], otherwise a
+    /// string describing the error which occurred.
+    pub fn from_participants(
+        parameters: Parameters,
+        participants: &[Participant],
+    ) -> Result<PublicKeyPackage, &'static str>
+    {
+        if participants.len() != parameters.n as usize {
+            return Err("Wrong number of participants");
+        }
+        if participants.iter().any(|p| p.commitments.len() != parameters.t as usize) {
+            return Err("Participant has the wrong number of commitments");
+        }
+        for p in participants.iter() {
+            if p.proof_of_secret_key.verify(&p.index, &p.commitments[0].to_affine()).is_err() {
+                return Err("Invalid proof of knowledge of the secret key");
+            }
+        }
+
+        let group_key = participants.iter().fold(ProjectivePoint::IDENTITY, |acc, p| acc + p.commitments[0]);
+        let mut public_keys: Vec<IndividualPublicKey> = Vec::with_capacity(parameters.n as usize);
+
+        for index in 1..=parameters.n {
+            let term: Scalar = index.into();
+            let mut share = ProjectivePoint::IDENTITY;
+
+            // Evaluate the sum of everyone's committed polynomials using Horner's method.
+            for k in (0..parameters.t as usize).rev() {
+                share *= term;
+                share = participants.iter().fold(share, |acc, p| acc + p.commitments[k]);
+            }
+            public_keys.push(IndividualPublicKey { index, share: share.to_affine() });
+        }
+
+        PublicKeyPackage::new(parameters, GroupKey(group_key.to_affine()), public_keys)
+    }
+
+    /// The parameters of the group.
+    pub fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+
+    /// The group's public key.
+    pub fn group_key(&self) -> &GroupKey {
+        &self.group_key
+    }
+
+    /// The public keys of all participants, sorted by index.
+    pub fn public_keys(&self) -> &[IndividualPublicKey] {
+        &self.public_keys
+    }
+
+    /// Look up the public key of the participant with the given `index`.
+    pub fn public_key(&self, index: u32) -> Option<&IndividualPublicKey> {
+        match index {
+            0 => None,
+            _ => self.public_keys.get(index as usize - 1),
+        }
+    }
+}
+
+/// Interpolate the polynomial, whose evaluations "in the exponent" at each
+/// participant's index are the given public `shares`, at `x`.
+///
+/// The participant indices of the `shares` must be distinct and non-zero.
+fn interpolate_in_the_exponent(x: u32, shares: &[IndividualPublicKey]) -> ProjectivePoint {
+    let x: Scalar = x.into();
+    let mut result = ProjectivePoint::IDENTITY;
+
+    for share in shares.iter() {
+        let mine: Scalar = share.index.into();
+        let mut num = Scalar::ONE;
+        let mut den = Scalar::ONE;
+
+        for other in shares.iter() {
+            if other.index == share.index {
+                continue;
+            }
+            let theirs: Scalar = other.index.into();
+
+            num *= x - theirs;
+            den *= mine - theirs;
+        }
+        result += share.share * (num * den.invert().unwrap());
+    }
+    result
+}
+
+#[cfg(all(test, feature = "std"))]
+impl DealtParticipant {
+    /// This participant's long-lived [`SecretKey`], for signing in tests.
+    pub(crate) fn secret_key(&self) -> SecretKey {
+        SecretKey { index: self.public_key.index, key: self.secret_share.polynomial_evaluation }
+    }
+}
+
+/// Deal keys to a group of participants, and assemble their
+/// [`PublicKeyPackage`], for tests.
+#[cfg(all(test, feature = "std"))]
+pub(crate) fn dealt_group(parameters: Parameters) -> (Vec<DealtParticipant>, PublicKeyPackage) {
+    let (participants, _) = Participant::dealer(&parameters);
+    let public_keys: Vec<IndividualPublicKey> = participants.iter().map(|p| p.public_key.clone()).collect();
+    let package = PublicKeyPackage::new(parameters, GroupKey(participants[0].group_key), public_keys).unwrap();
+
+    (participants, package)
+}
+
 #[cfg(test)]
 mod test {
     use k256::elliptic_curve::group::GroupEncoding;
@@ -887,8 +1115,8 @@ mod test {
 
         let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
@@ -896,8 +1124,8 @@ mod test {
         let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
         let p2_partial = p2_sk.sign(&message_hash, &group_key, &mut p2_secret_comshares, 0, signers).unwrap();
 
-        aggregator.include_partial_signature(p1_partial);
-        aggregator.include_partial_signature(p2_partial);
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p2_partial).unwrap();
 
         let aggregator = aggregator.finalize().unwrap();
         let signing_result = aggregator.aggregate();
@@ -1324,6 +1552,86 @@ P2 share conversion
         }
         assert!(do_test().is_ok());
     }
-   
-}
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn public_key_package_from_participants() {
+        let params = Parameters { n: 3, t: 2 };
+
+        let (p1, p1coeffs) = Participant::new(&params, 1);
+        let (p2, p2coeffs) = Participant::new(&params, 2);
+        let (p3, p3coeffs) = Participant::new(&params, 3);
+
+        let mut p1_other_participants: Vec<Participant> = vec!(p2.clone(), p3.clone());
+        let p1_state = DistributedKeyGeneration::<RoundOne>::new(&params, &p1.index, &p1coeffs, &mut p1_other_participants).unwrap();
+        let p1_their_secret_shares = p1_state.their_secret_shares().unwrap();
+
+        let mut p2_other_participants: Vec<Participant> = vec!(p1.clone(), p3.clone());
+        let p2_state = DistributedKeyGeneration::<RoundOne>::new(&params, &p2.index, &p2coeffs, &mut p2_other_participants).unwrap();
+        let p2_their_secret_shares = p2_state.their_secret_shares().unwrap();
+
+        let mut p3_other_participants: Vec<Participant> = vec!(p1.clone(), p2.clone());
+        let p3_state = DistributedKeyGeneration::<RoundOne>::new(&params, &p3.index, &p3coeffs, &mut p3_other_participants).unwrap();
+        let p3_their_secret_shares = p3_state.their_secret_shares().unwrap();
+
+        let p1_my_secret_shares = vec!(p2_their_secret_shares[0].clone(), p3_their_secret_shares[0].clone());
+        let p2_my_secret_shares = vec!(p1_their_secret_shares[0].clone(), p3_their_secret_shares[1].clone());
+        let p3_my_secret_shares = vec!(p1_their_secret_shares[1].clone(), p2_their_secret_shares[1].clone());
+
+        let (group_key, p1_sk) = p1_state.to_round_two(p1_my_secret_shares).unwrap().finish(&p1.public_key().unwrap()).unwrap();
+        let (_, p2_sk) = p2_state.to_round_two(p2_my_secret_shares).unwrap().finish(&p2.public_key().unwrap()).unwrap();
+        let (_, p3_sk) = p3_state.to_round_two(p3_my_secret_shares).unwrap().finish(&p3.public_key().unwrap()).unwrap();
+
+        // The order of the participants shouldn't matter.
+        let package = PublicKeyPackage::from_participants(params, &[p3.clone(), p1.clone(), p2.clone()]).unwrap();
+
+        assert!(package.group_key() == &group_key);
+        assert_eq!(package.public_key(1).unwrap().share, p1_sk.to_public().share);
+        assert_eq!(package.public_key(2).unwrap().share, p2_sk.to_public().share);
+        assert_eq!(package.public_key(3).unwrap().share, p3_sk.to_public().share);
+        assert!(package.public_key(0).is_none());
+        assert!(package.public_key(4).is_none());
+
+        assert!(PublicKeyPackage::from_participants(params, &[p1.clone(), p2.clone()]).is_err());
+
+        // A rogue participant who cancels out everyone else's commitments can't
+        // prove knowledge of the resulting secret key.
+        let mut rogue = p3;
+        rogue.commitments[0] = (AffinePoint::GENERATOR * Scalar::random(&mut OsRng)) - p1.commitments[0] - p2.commitments[0];
+
+        assert_eq!(PublicKeyPackage::from_participants(params, &[p1, p2, rogue]).unwrap_err(),
+                   "Invalid proof of knowledge of the secret key");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn public_key_package_rejects_bad_keys() {
+        let params = Parameters { n: 4, t: 3 };
+        let (participants, _) = Participant::dealer(&params);
+        let group_key = GroupKey(participants[0].group_key);
+        let public_keys: Vec<IndividualPublicKey> = participants.iter().map(|p| p.public_key.clone()).collect();
+
+        assert!(PublicKeyPackage::new(params, group_key, public_keys.clone()).is_ok());
+
+        // Missing a participant.
+        assert_eq!(PublicKeyPackage::new(params, group_key, public_keys[1..].to_vec()).unwrap_err(),
+                   "Wrong number of public keys");
+
+        // Duplicating a participant.
+        let mut duplicated = public_keys.clone();
+        duplicated[3] = duplicated[2].clone();
+        assert_eq!(PublicKeyPackage::new(params, group_key, duplicated).unwrap_err(),
+                   "Public keys must have unique participant indices in 1..=n");
+
+        // Swapping in a share of some other polynomial.
+        let mut forged = public_keys.clone();
+        forged[3].share = (AffinePoint::GENERATOR * Scalar::random(&mut OsRng)).to_affine();
+        assert_eq!(PublicKeyPackage::new(params, group_key, forged).unwrap_err(),
+                   "Public keys are not shares of the same polynomial");
+
+        // Using the wrong group key.
+        let wrong_key = GroupKey(participants[1].public_key.share);
+        assert_eq!(PublicKeyPackage::new(params, wrong_key, public_keys).unwrap_err(),
+                   "Public keys are inconsistent with the group key");
+    }
+}
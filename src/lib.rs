@@ -529,8 +529,8 @@
 //! #
 //! # let mut aggregator = SignatureAggregator::new(params, bob_group_key.clone(), context.to_vec(), message.to_vec());
 //! #
-//! aggregator.include_signer(1, alice_public_comshares.commitments[0], alice_public_key);
-//! aggregator.include_signer(3, carol_public_comshares.commitments[0], carol_public_key);
+//! aggregator.include_signer(1, alice_public_comshares.commitments[0], alice_public_key).unwrap();
+//! aggregator.include_signer(3, carol_public_comshares.commitments[0], carol_public_key).unwrap();
 //! # Ok(()) }
 //! # #[cfg(feature = "std")]
 //! # fn main() { assert!(do_test().is_ok()); }
@@ -538,6 +538,15 @@
 //! # fn main() { }
 //! ```
 //!
+//! If the aggregator runs more than one signing session, it should first check
+//! that nobody re-advertised a commitment share which was already used, since
+//! that would reveal their secret key.  A [`CommitmentRegistry`] remembers
+//! every consumed commitment share across sessions, and can be persisted.
+//!
+//! ```rust,ignore
+//! aggregator.consume_commitments(&mut registry)?;
+//! ```
+//!
 //! The aggregator should then publicly announce which participants are expected to be signers.
 //!
 //! ```rust,ignore
@@ -611,8 +620,8 @@
 //! #
 //! # let mut aggregator = SignatureAggregator::new(params, bob_group_key.clone(), context.to_vec(), message.to_vec());
 //! #
-//! # aggregator.include_signer(1, alice_public_comshares.commitments[0], (&alice_secret_key).into());
-//! # aggregator.include_signer(3, carol_public_comshares.commitments[0], (&carol_secret_key).into());
+//! # aggregator.include_signer(1, alice_public_comshares.commitments[0], (&alice_secret_key).into()).unwrap();
+//! # aggregator.include_signer(3, carol_public_comshares.commitments[0], (&carol_secret_key).into()).unwrap();
 //! #
 //! # let signers = aggregator.get_signers();
 //!
@@ -621,8 +630,8 @@
 //! let carol_partial = carol_secret_key.sign(&message_hash, &carol_group_key,
 //!                                           &mut carol_secret_comshares, 0, signers)?;
 //!
-//! aggregator.include_partial_signature(alice_partial);
-//! aggregator.include_partial_signature(carol_partial);
+//! aggregator.include_partial_signature(alice_partial)?;
+//! aggregator.include_partial_signature(carol_partial)?;
 //! # Ok(()) }
 //! # #[cfg(feature = "std")]
 //! # fn main() { assert!(do_test().is_ok()); }
@@ -630,6 +639,11 @@
 //! # fn main() { }
 //! ```
 //!
+//! The aggregator verifies each partial signature as soon as it is included,
+//! and rejects it if it is invalid, is a duplicate, or is from someone who was
+//! not expected to sign, so that a misbehaving signer can be replaced without
+//! waiting for the others.
+//!
 //! ## Signature Aggregation
 //!
 //! Once all the expected signers have sent their partial signatures, the
@@ -693,18 +707,60 @@ pub mod nizk;
 // The signing protocol uses Hashmap (currently for both the signature aggregator
 // and signers), which requires std.
 pub mod signature;
+pub mod bip340;
+pub mod ethschnorr;
+#[cfg(feature = "std")]
+pub mod hashing;
+#[cfg(feature = "std")]
+pub mod abi;
+#[cfg(feature = "std")]
+pub mod merkle;
+#[cfg(feature = "std")]
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod signing_log;
+#[cfg(feature = "std")]
+pub mod certificate;
+#[cfg(feature = "std")]
+pub mod adaptor;
+#[cfg(feature = "std")]
+pub mod ecdh;
+#[cfg(feature = "std")]
+pub mod vrf;
+#[cfg(feature = "std")]
+pub mod derivation;
+#[cfg(feature = "std")]
+pub mod taproot;
+#[cfg(feature = "std")]
+pub mod nested;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+pub mod batch;
 
+pub use bip340::Bip340Signature;
+pub use ethschnorr::EthSchnorrCalldata;
 pub use keygen::DistributedKeyGeneration;
 pub use keygen::GroupKey;
 pub use keygen::IndividualPublicKey;
 pub use keygen::Participant;
+pub use keygen::PublicKeyPackage;
 pub use keygen::SecretKey as IndividualSecretKey;
 pub use parameters::Parameters;
 #[cfg(feature = "std")]
+pub use batch::BatchSignatureAggregator;
+#[cfg(feature = "std")]
+pub use pool::CommitmentPool;
+#[cfg(feature = "std")]
+pub use precomputation::CommitmentRegistry;
+#[cfg(feature = "std")]
 pub use precomputation::generate_commitment_share_lists;
+pub use signature::HashingMode;
 pub use signature::ThresholdSignature;
 
 #[cfg(feature = "std")]
 pub use crate::signature::compute_message_hash;
 #[cfg(feature = "std")]
 pub use crate::signature::SignatureAggregator;
+#[cfg(feature = "std")]
+pub use crate::signature::verify_batch;
@@ -591,7 +591,7 @@ fn main() {
             let xyz = PublicKey::from_affine(pk_sk_affinepoint);
             let xyz = xyz.unwrap();
 
-            aggregator.include_signer(count, comms.commitments[0], alpha);
+            aggregator.include_signer(count, comms.commitments[0], alpha).unwrap();
 
             //let publickey_party_count=IndividualPublicKey::clone_from(&mut self, source)
 
@@ -651,7 +651,10 @@ fn main() {
             );
             // use aggregator function and include partial signature
             // for aggreagator
-            aggregator.include_partial_signature(partial_sign1);
+            let partial_sign1_index = partial_sign1.index;
+            if let Err(e) = aggregator.include_partial_signature(partial_sign1) {
+                println!("Rejected partial signature from index id {}: {}", partial_sign1_index, e);
+            }
 
             counttss = counttss + 1;
         }
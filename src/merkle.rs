@@ -0,0 +1,323 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Signing a whole batch of payloads at once, as a Keccak Merkle root.
+//!
+//! Instead of one [`ThresholdSignature`] per payload, the signers sign the
+//! [`MerkleTree::root`] of a batch of them once, with
+//! [`SignatureAggregator::new_prehashed`].  Each payload is then accompanied
+//! by its [`MerkleProof`] and the root's signature, which are checked together
+//! with [`ThresholdSignature::verify_merkle_inclusion`].
+//!
+//! Pairs of nodes are sorted before hashing, and leaves are hashed twice, as
+//! in OpenZeppelin's `MerkleProof` and `StandardMerkleTree`, so that a proof
+//! can be checked on-chain with
+//! `MerkleProof.verify(proof, root, keccak256(bytes.concat(keccak256(payload))))`.
+//!
+//! [`SignatureAggregator::new_prehashed`]: crate::signature::SignatureAggregator::new_prehashed
+
+use std::vec::Vec;
+
+use sha3::Digest;
+use sha3::Keccak256;
+
+use crate::keygen::GroupKey;
+use crate::signature::ThresholdSignature;
+
+/// Compute the leaf of a `payload`, \\(\mathrm{Keccak256}(\mathrm{Keccak256}(payload))\\).
+///
+/// Hashing twice ensures that no leaf can be mistaken for an inner node,
+/// which is the hash of 64 bytes.
+pub fn leaf_hash(payload: &[u8]) -> [u8; 32] {
+    Keccak256::digest(Keccak256::digest(payload)).into()
+}
+
+/// Hash a pair of nodes in sorted order, as OpenZeppelin's `_hashPair`.
+fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut h = Keccak256::default();
+
+    if a <= b {
+        h.update(a);
+        h.update(b);
+    } else {
+        h.update(b);
+        h.update(a);
+    }
+
+    h.finalize().into()
+}
+
+/// A Merkle tree over a batch of leaves.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MerkleTree {
+    /// Every layer of the tree, from the leaves up to the root.
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Build a tree over some `leaves`, e.g. from [`leaf_hash`], in order.
+    ///
+    /// An unpaired node at the end of a layer is carried up to the next one
+    /// unchanged.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the tree, otherwise a string describing
+    /// the error, i.e. that there are no leaves.
+    pub fn new(leaves: Vec<[u8; 32]>) -> Result<MerkleTree, &'static str> {
+        if leaves.is_empty() {
+            return Err("Cannot build a Merkle tree without leaves");
+        }
+
+        let mut layers = vec![leaves];
+
+        while layers[layers.len() - 1].len() > 1 {
+            let next = layers[layers.len() - 1].chunks(2).map(|pair| match pair {
+                [a, b] => hash_pair(a, b),
+                [a] => *a,
+                _ => unreachable!(),
+            }).collect();
+
+            layers.push(next);
+        }
+
+        Ok(MerkleTree { layers })
+    }
+
+    /// Build a tree over the [`leaf_hash`]es of some `payloads`, in order.
+    pub fn from_payloads<P: AsRef<[u8]>>(payloads: &[P]) -> Result<MerkleTree, &'static str> {
+        MerkleTree::new(payloads.iter().map(|p| leaf_hash(p.as_ref())).collect())
+    }
+
+    /// The root of this tree, which is the digest to be signed.
+    pub fn root(&self) -> [u8; 32] {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    /// The number of leaves of this tree.
+    pub fn len(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    /// Whether this tree has no leaves, which is never the case.
+    pub fn is_empty(&self) -> bool {
+        self.layers[0].is_empty()
+    }
+
+    /// Get the inclusion proof of the leaf at position `index`.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        let leaf = *self.layers[0].get(index)?;
+        let mut siblings = Vec::with_capacity(self.layers.len() - 1);
+        let mut index = index;
+
+        for layer in self.layers[..self.layers.len() - 1].iter() {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                siblings.push(*sibling);
+            }
+            index /= 2;
+        }
+
+        Some(MerkleProof { leaf, siblings })
+    }
+}
+
+/// A proof that a leaf is included in a [`MerkleTree`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MerkleProof {
+    /// The leaf, e.g. from [`leaf_hash`].
+    pub leaf: [u8; 32],
+    /// The sibling nodes on the path from the leaf to the root, which is the
+    /// `proof` argument to OpenZeppelin's `MerkleProof.verify`.
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// Compute the root of the tree which this proof claims the leaf is in.
+    pub fn compute_root(&self) -> [u8; 32] {
+        self.siblings.iter().fold(self.leaf, |node, sibling| hash_pair(&node, sibling))
+    }
+
+    /// Check that the leaf is included in the tree with the given `root`.
+    pub fn verify(&self, root: &[u8; 32]) -> Result<(), &'static str> {
+        match self.compute_root() == *root {
+            true => Ok(()),
+            false => Err("Merkle proof does not match the root"),
+        }
+    }
+}
+
+impl ThresholdSignature {
+    /// Verify this [`ThresholdSignature`] on the root of a [`MerkleTree`], and
+    /// that the `payload` is in that tree, by way of an inclusion `proof`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the `payload` was
+    /// signed, otherwise a string describing the error.  Since the signed
+    /// root is computed from the `proof`, a bad proof shows up as a bad
+    /// signature.  A proof whose leaf is not the [`leaf_hash`] of the
+    /// `payload`, e.g. one for an inner node, is refused.
+    pub fn verify_merkle_inclusion(
+        &self,
+        group_key: &GroupKey,
+        payload: &[u8],
+        proof: &MerkleProof,
+    ) -> Result<(), &'static str>
+    {
+        if proof.leaf != leaf_hash(payload) {
+            return Err("Merkle proof is not for this payload");
+        }
+
+        self.verify(group_key, &proof.compute_root()).or(Err("Invalid signature on the Merkle root"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::SignatureAggregator;
+
+    use rand::rngs::OsRng;
+
+    fn payloads(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|i| format!("Bridge transfer number {}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn small_trees() {
+        let leaves: Vec<[u8; 32]> = payloads(3).iter().map(|p| leaf_hash(p)).collect();
+
+        assert_eq!(MerkleTree::new(Vec::new()), Err("Cannot build a Merkle tree without leaves"));
+        assert_eq!(MerkleTree::new(leaves[..1].to_vec()).unwrap().root(), leaves[0]);
+
+        // With three leaves, the third is carried up unchanged.
+        let tree = MerkleTree::new(leaves.clone()).unwrap();
+        let expected = hash_pair(&hash_pair(&leaves[0], &leaves[1]), &leaves[2]);
+
+        assert_eq!(tree.root(), expected);
+        assert_eq!(tree.proof(2).unwrap().siblings, vec![hash_pair(&leaves[0], &leaves[1])]);
+        assert_eq!(tree.proof(0).unwrap().siblings, vec![leaves[1], leaves[2]]);
+        assert!(tree.proof(3).is_none());
+
+        // The order of a pair doesn't matter.
+        assert_eq!(hash_pair(&leaves[0], &leaves[1]), hash_pair(&leaves[1], &leaves[0]));
+    }
+
+    #[test]
+    fn openzeppelin_compatible_hashing() {
+        // keccak256(bytes.concat(keccak256("a"))) and a sorted pair of two.
+        let a = leaf_hash(b"a");
+        let b = leaf_hash(b"b");
+        let (low, high) = if a < b { (a, b) } else { (b, a) };
+
+        assert_eq!(a, <[u8; 32]>::from(Keccak256::digest(Keccak256::digest(b"a"))));
+        assert_eq!(MerkleTree::new(vec![a, b]).unwrap().root(),
+                   <[u8; 32]>::from(Keccak256::digest([low, high].concat())));
+    }
+
+    #[test]
+    fn every_proof_verifies() {
+        for count in 1..=17 {
+            let payloads = payloads(count);
+            let tree = MerkleTree::from_payloads(&payloads).unwrap();
+
+            assert_eq!(tree.len(), count);
+
+            for (i, payload) in payloads.iter().enumerate() {
+                let proof = tree.proof(i).unwrap();
+
+                assert_eq!(proof.leaf, leaf_hash(payload));
+                assert!(proof.verify(&tree.root()).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_proofs_fail() {
+        let tree = MerkleTree::from_payloads(&payloads(8)).unwrap();
+        let mut proof = tree.proof(5).unwrap();
+
+        proof.leaf = leaf_hash(b"Bridge transfer number 500");
+        assert!(proof.verify(&tree.root()).is_err());
+
+        let mut proof = tree.proof(5).unwrap();
+
+        proof.siblings.pop();
+        assert!(proof.verify(&tree.root()).is_err());
+
+        // An inner node can't be passed off as the leaf of a 64-byte payload.
+        let inner = hash_pair(&tree.layers[0][0], &tree.layers[0][1]);
+        let (low, high) = if tree.layers[0][0] < tree.layers[0][1] {
+            (tree.layers[0][0], tree.layers[0][1])
+        } else {
+            (tree.layers[0][1], tree.layers[0][0])
+        };
+
+        assert_ne!(leaf_hash(&[low, high].concat()), inner);
+    }
+
+    #[test]
+    fn signing_a_merkle_root() {
+        let params = Parameters { n: 2, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let payloads = payloads(100);
+        let tree = MerkleTree::from_payloads(&payloads).unwrap();
+        let mut aggregator = SignatureAggregator::new_prehashed(params, group_key, tree.root());
+        let mut secret_comshares = Vec::new();
+
+        for p in participants.iter() {
+            let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, p.public_key.index, 1);
+
+            aggregator.include_signer(p.public_key.index, public_comshares.commitments[0], p.public_key.clone()).unwrap();
+            secret_comshares.push(secret);
+        }
+
+        let signers = aggregator.get_signers().clone();
+
+        for (p, secret) in participants.iter().zip(secret_comshares.iter_mut()) {
+            let sk = p.secret_key();
+            let partial = sk.sign(&tree.root(), &group_key, secret, 0, &signers).unwrap();
+
+            aggregator.include_partial_signature(partial).unwrap();
+        }
+
+        let signature = aggregator.finalize().unwrap().aggregate().unwrap();
+
+        // One signature covers every payload.
+        for i in [0, 41, 99] {
+            assert!(signature.verify_merkle_inclusion(&group_key, &payloads[i], &tree.proof(i).unwrap()).is_ok());
+        }
+
+        let forged = MerkleProof { leaf: leaf_hash(b"Bridge transfer number 100"), siblings: tree.proof(99).unwrap().siblings };
+
+        assert_eq!(signature.verify_merkle_inclusion(&group_key, b"Bridge transfer number 100", &forged),
+                   Err("Invalid signature on the Merkle root"));
+        assert_eq!(signature.verify_merkle_inclusion(&group_key, &payloads[41], &tree.proof(40).unwrap()),
+                   Err("Merkle proof is not for this payload"));
+
+        // A signed inner node, or the root itself, is not a leaf, even though
+        // its proof checks out against the signed root.
+        let inner = MerkleProof { leaf: tree.layers[1][0], siblings: tree.proof(0).unwrap().siblings[1..].to_vec() };
+        let root = MerkleProof { leaf: tree.root(), siblings: Vec::new() };
+
+        assert!(signature.verify(&group_key, &inner.compute_root()).is_ok());
+        assert!(signature.verify(&group_key, &root.compute_root()).is_ok());
+
+        for proof in [inner, root] {
+            assert_eq!(signature.verify_merkle_inclusion(&group_key, &proof.leaf, &proof),
+                       Err("Merkle proof is not for this payload"));
+        }
+    }
+}
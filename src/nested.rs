@@ -0,0 +1,622 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Nested thresholds, for holding one participant's share across several devices.
+//!
+//! A participant's [`SecretKey`] \\(x\_i\\) is itself secret shared with
+//! Feldman's verifiable secret sharing, \\(t'\\)-of-\\(n'\\), among the
+//! participant's devices with [`SecretKey::split_across_devices`], so that
+//! device \\(j\\) holds a [`DeviceKey`] \\(x\_{ij}\\) and anyone holding the
+//! [`NestedPublicKey`] can compute its public share \\(X\_{ij}\\).
+//!
+//! To sign, a set \\(S\\) of at least \\(t'\\) devices each publish a
+//! [`PublicCommitmentShareList`] as usual, which a coordinator for the
+//! participant combines into a [`NestedCommitmentShareList`].  Its commitments
+//! are the weighted sums \\(D\_i = \sum\_{j \in S} \beta\_{ij} D\_{ij}\\)
+//! and \\(E\_i = \sum\_{j \in S} \beta\_{ij} E\_{ij}\\), and are published
+//! to the outer [`SignatureAggregator`] as the participant's own.  Once the
+//! aggregator has picked the signers, every device in \\(S\\) computes
+//!
+//! \\[
+//! z\_{ij} = \beta\_{ij} (d\_{ij} + e\_{ij} \rho\_i) - \mu\_j \lambda\_i c x\_{ij}
+//! \\]
+//!
+//! with [`DeviceKey::sign`], where \\(\mu\_j\\) is its Lagrange coefficient
+//! within \\(S\\), and the coordinator verifies and sums these into the
+//! participant's [`PartialThresholdSignature`] with
+//! [`NestedCommitment::combine`].  The participant's share and nonces are
+//! never reconstructed, and the outer aggregator sees a normal participant.
+//!
+//! The inner binding factor \\(\beta\_{ij}\\) is a hash of the participant's
+//! index, the device's index, and every commitment of the devices in
+//! \\(S\\), in the same way as the outer binding factor \\(\rho\_i\\) binds
+//! the signers' commitments.  Were the commitments simply added, a malicious
+//! device or coordinator could pick its commitment after seeing the others',
+//! e.g. across many concurrent sessions, to cancel them out, which is the
+//! basis of the ROS attack on Schnorr multisignatures.  Every device
+//! recomputes the factors when signing, and refuses to sign unless the
+//! participant's commitment in the signers is exactly the one bound to its
+//! own commitment.
+//!
+//! Every device in \\(S\\) must respond, since the nonces are additive; if one
+//! does not, the participant must sign with fresh commitments from a
+//! different set of devices.  Only the EthSchnorr signing mode is supported.
+//!
+//! [`SignatureAggregator`]: crate::signature::SignatureAggregator
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::FieldBytes;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::U256;
+use k256::elliptic_curve::group::GroupEncoding;
+use k256::elliptic_curve::ops::Reduce;
+
+use rand::CryptoRng;
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use sha3::Digest;
+use sha3::Keccak256;
+
+use zeroize::Zeroize;
+
+use crate::keygen::GroupKey;
+use crate::keygen::IndividualPublicKey;
+use crate::keygen::SecretKey;
+use crate::keygen::SecretShare;
+use crate::keygen::VerifiableSecretSharingCommitment;
+use crate::keygen::generate_shares;
+use crate::parameters::Parameters;
+use crate::precomputation::PublicCommitmentShareList;
+use crate::precomputation::SecretCommitmentShareList;
+use crate::signature::PartialThresholdSignature;
+use crate::signature::Signer;
+use crate::signature::calculate_lagrange_coefficients;
+use crate::signature::compute_binding_factors_and_group_commitment;
+use crate::signature::compute_challenge;
+use crate::signature::partial_signature_is_valid;
+
+/// One device's share of a participant's [`SecretKey`].
+#[derive(Debug, Zeroize, Serialize, Deserialize)]
+#[zeroize(drop)]
+pub struct DeviceKey {
+    /// The index of the participant whose key this is a share of.
+    pub participant_index: u32,
+    /// The index of this device amongst the participant's devices.
+    pub index: u32,
+    /// The device's share of the participant's secret key.
+    pub key: Scalar,
+}
+
+/// The public commitments to the sharing of a participant's [`SecretKey`]
+/// amongst their devices.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NestedPublicKey {
+    /// The index of the participant whose key was shared.
+    pub participant_index: u32,
+    /// The parameters of the sharing amongst the participant's devices.
+    pub parameters: Parameters,
+    /// The Feldman commitments to the sharing polynomial, whose constant term
+    /// is the participant's secret key.
+    pub(crate) commitment: VerifiableSecretSharingCommitment,
+}
+
+impl NestedPublicKey {
+    /// The [`IndividualPublicKey`] of the participant, as seen by the rest of
+    /// the group.
+    pub fn participant_public_key(&self) -> IndividualPublicKey {
+        IndividualPublicKey { index: self.participant_index, share: self.commitment.0[0].to_affine() }
+    }
+
+    /// Compute the public share \\(X\_{ij}\\) of the device with the given
+    /// `index`, by evaluating the committed polynomial in the exponent.
+    ///
+    /// # Returns
+    ///
+    /// The public share, or `None` if there is no such device.
+    pub fn device_public_key(&self, index: u32) -> Option<AffinePoint> {
+        if index == 0 || index > self.parameters.n {
+            return None;
+        }
+
+        let term = Scalar::from(index);
+        let share = self.commitment.0.iter().rev().fold(ProjectivePoint::IDENTITY, |acc, x| (acc * term) + x);
+
+        Some(share.to_affine())
+    }
+}
+
+impl SecretKey {
+    /// Secret share this key amongst a participant's devices, with the given
+    /// device `parameters`, using a trusted dealer with randomness from the
+    /// `csprng`.
+    ///
+    /// # Warning
+    ///
+    /// This key should be wiped as soon as the [`DeviceKey`]s have been handed
+    /// out, and every device MUST check that it was given the same
+    /// [`NestedPublicKey`], and check its [`DeviceKey`] against it with
+    /// [`DeviceKey::verify`].
+    ///
+    /// # Returns
+    ///
+    /// The key of each device and the public commitments to the sharing.
+    pub fn split_across_devices(
+        &self,
+        parameters: &Parameters,
+        csprng: impl Rng + CryptoRng,
+    ) -> (Vec<DeviceKey>, NestedPublicKey)
+    {
+        let (shares, commitment) = generate_shares(parameters, self.key, csprng);
+        let devices = shares.iter()
+            .map(|d| DeviceKey {
+                participant_index: self.index,
+                index: d.secret_share.index,
+                key: d.secret_share.polynomial_evaluation,
+            })
+            .collect();
+
+        (devices, NestedPublicKey { participant_index: self.index, parameters: *parameters, commitment })
+    }
+}
+
+impl DeviceKey {
+    /// Verify this device's share against the [`NestedPublicKey`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the share is a share of
+    /// the committed key, otherwise a string describing why it is not.
+    pub fn verify(&self, nested_public_key: &NestedPublicKey) -> Result<(), &'static str> {
+        if self.participant_index != nested_public_key.participant_index {
+            return Err("Device key belongs to a different participant");
+        }
+
+        let share = SecretShare { index: self.index, polynomial_evaluation: self.key };
+
+        share.verify(&nested_public_key.commitment).or(Err("Device share does not match the commitment"))
+    }
+
+    /// Compute this device's contribution to the participant's
+    /// [`PartialThresholdSignature`] on a `message_hash`.
+    ///
+    /// # Inputs
+    ///
+    /// * The `message_hash` and public [`GroupKey`], as for [`SecretKey::sign`],
+    /// * The participant's `nested_commitment` which the outer aggregator
+    ///   picked, from [`NestedCommitmentShareList::commitment_for`],
+    /// * This device's [`SecretCommitmentShareList`], which must contain the
+    ///   secret half of this device's commitment in `nested_commitment`, and
+    /// * The list of all the participating [`Signer`]s, as for [`SecretKey::sign`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is this device's [`DevicePartialSignature`],
+    /// which should be sent to the participant's coordinator, otherwise a string
+    /// describing the error.
+    pub fn sign(
+        &self,
+        message_hash: &[u8; 32],
+        group_key: &GroupKey,
+        nested_commitment: &NestedCommitment,
+        my_secret_commitment_share_list: &mut SecretCommitmentShareList,
+        signers: &[Signer],
+    ) -> Result<DevicePartialSignature, &'static str>
+    {
+        if nested_commitment.participant_index != self.participant_index {
+            return Err("Commitment belongs to a different participant");
+        }
+
+        let (position, (_, published)) = nested_commitment.device_commitments.iter()
+            .enumerate()
+            .find(|(_, (index, _))| *index == self.index)
+            .ok_or("This device is not one of the signing devices")?;
+        let device_binding_factor = nested_commitment.device_binding_factors()[position];
+        let position = my_secret_commitment_share_list.index_of(published).ok_or("Commitment share was already used")?;
+        let signer = signers.iter()
+            .find(|s| s.participant_index == self.participant_index)
+            .ok_or("Our participant is not one of the signers")?;
+
+        // Make sure that the aggregator is using the commitment made from
+        // exactly these devices' commitments, including ours, bound with the
+        // inner binding factors which we have just computed ourselves.
+        if !same_commitment(&signer.published_commitment_share, &nested_commitment.publish()) {
+            return Err("Signers do not include our participant's nested commitment");
+        }
+
+        let (binding_factors, Rs) = compute_binding_factors_and_group_commitment(message_hash, signers);
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+        let challenge = compute_challenge(message_hash, group_key, &R.to_affine());
+        let binding_factor = binding_factors.get(&self.participant_index).ok_or("Could not compute our blinding factor")?;
+        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda = calculate_lagrange_coefficients(&self.participant_index, &all_participant_indices)?;
+        let mu = calculate_lagrange_coefficients(&self.index, &nested_commitment.device_indices())?;
+        let my_commitment_share = my_secret_commitment_share_list.commitments[position].clone();
+        let z = (device_binding_factor * (my_commitment_share.hiding.nonce +
+                                          (my_commitment_share.binding.nonce * binding_factor))) -
+            (mu * lambda * self.key * challenge);
+
+        // Zero out our secrets from memory to prevent nonce reuse.
+        my_secret_commitment_share_list.drop_share(my_commitment_share);
+
+        Ok(DevicePartialSignature { index: self.index, z })
+    }
+}
+
+/// A device's contribution \\(z\_{ij}\\) to its participant's
+/// [`PartialThresholdSignature`].
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DevicePartialSignature {
+    /// The index of the device which made this contribution.
+    pub index: u32,
+    /// The contribution.
+    pub z: Scalar,
+}
+
+/// Compare two published commitment shares.
+fn same_commitment(a: &(AffinePoint, AffinePoint), b: &(AffinePoint, AffinePoint)) -> bool {
+    a.0.to_bytes() == b.0.to_bytes() && a.1.to_bytes() == b.1.to_bytes()
+}
+
+/// The commitment shares of a set of a participant's devices, combined into
+/// commitment shares for the participant.
+#[derive(Debug)]
+pub struct NestedCommitmentShareList {
+    /// The index of the participant whose devices these are.
+    pub participant_index: u32,
+    /// The devices' public commitment share lists, sorted by device index.
+    pub devices: Vec<PublicCommitmentShareList>,
+}
+
+impl NestedCommitmentShareList {
+    /// Combine the [`PublicCommitmentShareList`]s of at least \\(t'\\) of a
+    /// participant's devices, each of which must have the same number of
+    /// commitments.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the combined list, otherwise a string
+    /// describing the error.
+    pub fn new(
+        nested_public_key: &NestedPublicKey,
+        mut devices: Vec<PublicCommitmentShareList>,
+    ) -> Result<NestedCommitmentShareList, &'static str>
+    {
+        devices.sort_by_key(|list| list.participant_index);
+
+        if devices.windows(2).any(|pair| pair[0].participant_index == pair[1].participant_index) {
+            return Err("Duplicate device");
+        }
+        if devices.len() < nested_public_key.parameters.t as usize {
+            return Err("Fewer devices than the threshold");
+        }
+        if devices.iter().any(|list| list.participant_index == 0 || list.participant_index > nested_public_key.parameters.n) {
+            return Err("Device index out of range");
+        }
+        if devices.iter().any(|list| list.commitments.len() != devices[0].commitments.len()) {
+            return Err("Devices published different numbers of commitments");
+        }
+
+        Ok(NestedCommitmentShareList { participant_index: nested_public_key.participant_index, devices })
+    }
+
+    /// The participant's combined [`PublicCommitmentShareList`], which should
+    /// be published to the outer aggregator in place of an ordinary one.
+    pub fn public_commitment_share_list(&self) -> PublicCommitmentShareList {
+        let commitments = (0..self.devices[0].commitments.len())
+            .map(|position| self.commitment(position).publish())
+            .collect();
+
+        PublicCommitmentShareList { participant_index: self.participant_index, commitments }
+    }
+
+    /// Get the devices' commitments at the given `position` in their lists.
+    fn commitment(&self, position: usize) -> NestedCommitment {
+        NestedCommitment {
+            participant_index: self.participant_index,
+            device_commitments: self.devices.iter()
+                .map(|list| (list.participant_index, list.commitments[position]))
+                .collect(),
+        }
+    }
+
+    /// Find the devices' commitments which make up the combined commitment
+    /// share which was `published`, e.g. the one which the aggregator picked
+    /// from [`NestedCommitmentShareList::public_commitment_share_list`].
+    pub fn commitment_for(&self, published: &(AffinePoint, AffinePoint)) -> Option<NestedCommitment> {
+        (0..self.devices[0].commitments.len())
+            .map(|position| self.commitment(position))
+            .find(|commitment| same_commitment(&commitment.publish(), published))
+    }
+}
+
+/// The commitments of a set of a participant's devices for one signing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NestedCommitment {
+    /// The index of the participant whose devices these are.
+    pub participant_index: u32,
+    /// The device indices and their published commitment shares.
+    pub device_commitments: Vec<(u32, (AffinePoint, AffinePoint))>,
+}
+
+impl NestedCommitment {
+    /// The indices of the signing devices.
+    fn device_indices(&self) -> Vec<u32> {
+        self.device_commitments.iter().map(|(index, _)| *index).collect()
+    }
+
+    /// Compute the inner binding factor \\(\beta\_{ij}\\) of each device, in
+    /// the same order as the `device_commitments`.
+    fn device_binding_factors(&self) -> Vec<Scalar> {
+        let mut sorted = self.device_commitments.clone();
+
+        sorted.sort_by_key(|(index, _)| *index);
+
+        let mut h = Keccak256::default();
+
+        h.update(b"FROST-NESTED-BINDING");
+        h.update(self.participant_index.to_be_bytes());
+
+        for (index, (D, E)) in sorted.iter() {
+            h.update(index.to_be_bytes());
+            h.update(D.to_bytes());
+            h.update(E.to_bytes());
+        }
+
+        let prefix = h.finalize();
+
+        self.device_commitments.iter().map(|(index, _)| {
+            let mut h = Keccak256::default();
+
+            h.update(prefix);
+            h.update(index.to_be_bytes());
+
+            <Scalar as Reduce<U256>>::from_be_bytes_reduced(FieldBytes::from(h.finalize()))
+        }).collect()
+    }
+
+    /// The participant's combined commitment share \\((D\_i, E\_i)\\), where
+    /// each device's commitment is weighted by its inner binding factor.
+    pub fn publish(&self) -> (AffinePoint, AffinePoint) {
+        let (hiding, binding) = self.device_commitments.iter().zip(self.device_binding_factors()).fold(
+            (ProjectivePoint::IDENTITY, ProjectivePoint::IDENTITY),
+            |(hiding, binding), ((_, (D, E)), beta)| (hiding + (*D * beta), binding + (*E * beta)),
+        );
+
+        (hiding.to_affine(), binding.to_affine())
+    }
+
+    /// Verify and combine the devices' [`DevicePartialSignature`]s into the
+    /// participant's [`PartialThresholdSignature`].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the participant's partial signature,
+    /// which should be sent to the outer aggregator, otherwise a
+    /// `HashMap<u32, &'static str>` containing the device indices of the
+    /// misbehaving devices and a description of their misbehaviour.  A key of
+    /// `0` indicates that the signers do not include this commitment.
+    pub fn combine(
+        &self,
+        nested_public_key: &NestedPublicKey,
+        message_hash: &[u8; 32],
+        group_key: &GroupKey,
+        signers: &[Signer],
+        partial_signatures: &[DevicePartialSignature],
+    ) -> Result<PartialThresholdSignature, HashMap<u32, &'static str>>
+    {
+        let mut misbehaving_devices: HashMap<u32, &'static str> = HashMap::new();
+        let participant_index = self.participant_index;
+
+        let signer = match signers.iter().find(|s| s.participant_index == participant_index) {
+            Some(signer) if same_commitment(&signer.published_commitment_share, &self.publish()) => signer,
+            _ => {
+                misbehaving_devices.insert(0, "Signers do not include our participant's nested commitment");
+                return Err(misbehaving_devices);
+            },
+        };
+        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda = match calculate_lagrange_coefficients(&signer.participant_index, &all_participant_indices) {
+            Ok(lambda) => lambda,
+            Err(error) => {
+                misbehaving_devices.insert(0, error);
+                return Err(misbehaving_devices);
+            },
+        };
+        let (binding_factors, Rs) = compute_binding_factors_and_group_commitment(message_hash, signers);
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+        let challenge = compute_challenge(message_hash, group_key, &R.to_affine());
+        // This unwrap() cannot fail, since we are one of the signers.
+        let binding_factor = binding_factors.get(&participant_index).unwrap();
+        let device_indices = self.device_indices();
+        let device_binding_factors = self.device_binding_factors();
+        let mut z = Scalar::ZERO;
+
+        for ((index, (D, E)), beta) in self.device_commitments.iter().zip(device_binding_factors) {
+            let partials: Vec<&DevicePartialSignature> = partial_signatures.iter().filter(|p| p.index == *index).collect();
+
+            if partials.len() > 1 {
+                misbehaving_devices.insert(*index, "Duplicate partial signature");
+                continue;
+            }
+
+            let partial = match partials.first() {
+                Some(partial) => partial,
+                None => {
+                    misbehaving_devices.insert(*index, "Missing partial signature");
+                    continue;
+                },
+            };
+            let X = match nested_public_key.device_public_key(*index) {
+                Some(X) => X,
+                None => {
+                    misbehaving_devices.insert(*index, "Device index out of range");
+                    continue;
+                },
+            };
+            let mu = match calculate_lagrange_coefficients(index, &device_indices) {
+                Ok(mu) => mu,
+                Err(error) => {
+                    misbehaving_devices.insert(*index, error);
+                    continue;
+                },
+            };
+            let R_ij = (((*E * binding_factor) + D) * beta).to_affine();
+
+            if partial_signature_is_valid(&partial.z, &X, &R_ij, &challenge, &(mu * lambda)) {
+                z += partial.z;
+            } else {
+                misbehaving_devices.insert(*index, "Incorrect partial signature");
+            }
+        }
+
+        for partial in partial_signatures.iter().filter(|p| !device_indices.contains(&p.index)) {
+            misbehaving_devices.insert(partial.index, "Partial signature is not from one of the signing devices");
+        }
+
+        match ! misbehaving_devices.is_empty() {
+            true => Err(misbehaving_devices),
+            false => Ok(PartialThresholdSignature { index: participant_index, z }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::SignatureAggregator;
+
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn device_shares_verify() {
+        let sk = SecretKey { index: 2, key: Scalar::from(1234u32) };
+        let (devices, nested) = sk.split_across_devices(&Parameters { n: 3, t: 2 }, OsRng);
+
+        assert_eq!(nested.participant_public_key().share, sk.to_public().share);
+
+        for device in devices.iter() {
+            assert!(device.verify(&nested).is_ok());
+            assert_eq!(nested.device_public_key(device.index), Some((AffinePoint::GENERATOR * device.key).to_affine()));
+        }
+
+        let forged = DeviceKey { participant_index: 2, index: 1, key: devices[0].key + Scalar::ONE };
+
+        assert!(forged.verify(&nested).is_err());
+        assert_eq!(nested.device_public_key(4), None);
+
+        // Devices are given their keys, and the public commitments, serialised.
+        let restored: NestedPublicKey = bincode::deserialize(&bincode::serialize(&nested).unwrap()).unwrap();
+        let device: DeviceKey = bincode::deserialize(&bincode::serialize(&devices[1]).unwrap()).unwrap();
+
+        assert!(device.verify(&restored).is_ok());
+        assert_eq!(restored.participant_public_key().share, sk.to_public().share);
+        assert_eq!(restored.device_public_key(3), nested.device_public_key(3));
+    }
+
+    #[test]
+    fn nested_participant_signs_as_normal() {
+        let params = Parameters { n: 3, t: 2 };
+        let device_params = Parameters { n: 3, t: 2 };
+        let (participants, package) = dealt_group(params);
+
+        // Participant 1 signs normally, and participant 3 signs with devices 1 and 3.
+        let p1 = participants[0].secret_key();
+        let p3 = participants[2].secret_key();
+        let (devices, nested) = p3.split_across_devices(&device_params, OsRng);
+
+        assert_eq!(nested.participant_public_key().share, participants[2].public_key.share);
+
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 2);
+        let (d1_public_comshares, mut d1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 2);
+        let (d3_public_comshares, mut d3_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 3, 2);
+        let combined = NestedCommitmentShareList::new(&nested, vec![d3_public_comshares, d1_public_comshares]).unwrap();
+        let p3_public_comshares = combined.public_commitment_share_list();
+
+        let mut aggregator = SignatureAggregator::from_public_key_package(package.clone(), b"CTX".to_vec(), b"nested".to_vec());
+
+        aggregator.try_include_signer(1, p1_public_comshares.commitments[1]).unwrap();
+        aggregator.try_include_signer(3, p3_public_comshares.commitments[1]).unwrap();
+
+        let signers = aggregator.get_signers().clone();
+        let message_hash = *aggregator.message_hash();
+        let group_key = package.group_key();
+
+        let p1_partial = p1.sign(&message_hash, group_key, &mut p1_secret_comshares, 1, &signers).unwrap();
+        let nested_commitment = combined.commitment_for(&p3_public_comshares.commitments[1]).unwrap();
+
+        // The devices refuse a commitment which isn't bound to their own,
+        // such as the plain sum of the devices' commitments.
+        let (D, E) = nested_commitment.device_commitments.iter().fold(
+            (ProjectivePoint::IDENTITY, ProjectivePoint::IDENTITY),
+            |(D, E), (_, (D_ij, E_ij))| (D + D_ij, E + E_ij),
+        );
+        let mut unbound = signers.clone();
+
+        unbound[1].published_commitment_share = (D.to_affine(), E.to_affine());
+
+        assert_eq!(devices[0].sign(&message_hash, group_key, &nested_commitment, &mut d1_secret_comshares, &unbound).unwrap_err(),
+                   "Signers do not include our participant's nested commitment");
+        assert_eq!(d1_secret_comshares.commitments.len(), 2);
+        let d1_partial = devices[0].sign(&message_hash, group_key, &nested_commitment, &mut d1_secret_comshares, &signers).unwrap();
+        let d3_partial = devices[2].sign(&message_hash, group_key, &nested_commitment, &mut d3_secret_comshares, &signers).unwrap();
+
+        // Device 2 didn't commit, and the devices' nonces can't be used twice.
+        assert!(devices[1].sign(&message_hash, group_key, &nested_commitment, &mut d1_secret_comshares, &signers).is_err());
+        assert!(devices[0].sign(&message_hash, group_key, &nested_commitment, &mut d1_secret_comshares, &signers).is_err());
+
+        let mut bogus = d3_partial;
+        bogus.z += Scalar::ONE;
+
+        let misbehaving = nested_commitment.combine(&nested, &message_hash, group_key, &signers, &[d1_partial, bogus]).unwrap_err();
+        assert_eq!(misbehaving.get(&3), Some(&"Incorrect partial signature"));
+
+        let misbehaving = nested_commitment.combine(&nested, &message_hash, group_key, &signers, &[d1_partial]).unwrap_err();
+        assert_eq!(misbehaving.get(&3), Some(&"Missing partial signature"));
+
+        // Device partial signatures are sent to the coordinator serialised.
+        let d3_partial: DevicePartialSignature = bincode::deserialize(&bincode::serialize(&d3_partial).unwrap()).unwrap();
+        let p3_partial = nested_commitment.combine(&nested, &message_hash, group_key, &signers, &[d1_partial, d3_partial]).unwrap();
+
+        assert!(p3_partial.verify(group_key, &message_hash, &signers, &nested.participant_public_key()).is_ok());
+
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p3_partial).unwrap();
+
+        let signature = aggregator.finalize().unwrap().aggregate().unwrap();
+
+        assert!(signature.verify(group_key, &message_hash).is_ok());
+    }
+
+    #[test]
+    fn invalid_device_lists_are_rejected() {
+        let sk = SecretKey { index: 1, key: Scalar::from(99u32) };
+        let (_, nested) = sk.split_across_devices(&Parameters { n: 3, t: 2 }, OsRng);
+
+        let (d1, _) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        assert_eq!(NestedCommitmentShareList::new(&nested, vec![d1]).unwrap_err(), "Fewer devices than the threshold");
+
+        let (d1, _) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (d4, _) = generate_commitment_share_lists(&mut OsRng, 4, 1);
+        assert_eq!(NestedCommitmentShareList::new(&nested, vec![d1, d4]).unwrap_err(), "Device index out of range");
+
+        let (d1, _) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (d2, _) = generate_commitment_share_lists(&mut OsRng, 2, 2);
+        assert_eq!(NestedCommitmentShareList::new(&nested, vec![d1, d2]).unwrap_err(),
+                   "Devices published different numbers of commitments");
+    }
+}
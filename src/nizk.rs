@@ -16,13 +16,27 @@ use k256::Scalar;
 
 #[cfg(feature = "std")]
 use k256::elliptic_curve::Field;
+use k256::CompressedPoint;
+use k256::FieldBytes;
+use k256::U256;
 use k256::elliptic_curve::PrimeField;
 use k256::elliptic_curve::group::GroupEncoding;
+use k256::elliptic_curve::ops::LinearCombination;
+use k256::elliptic_curve::ops::Reduce;
 #[cfg(feature = "std")]
 use rand::{CryptoRng, Rng};
+use serde::{Deserialize, Serialize};
 use sha3::Digest;
 use sha3::Keccak256;
 
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+#[cfg(feature = "std")]
+use crate::signature::multiscalar_mul;
+#[cfg(feature = "std")]
+use crate::signature::random_batch_coefficient;
+
 /// A proof of knowledge of a secret key, created by making a Schnorr signature
 /// with the secret key.
 ///
@@ -91,3 +105,253 @@ impl NizkOfSecretKey {
         Err(())
     }
 }
+
+/// A Chaum-Pedersen proof that two points have the same discrete logarithm
+/// with respect to two bases, i.e. that \\( X = xG \\) and \\( Y = xP \\)
+/// for some secret \\( x \\), without revealing \\( x \\).
+///
+/// The prover picks \\( k \stackrel{\\$}{\leftarrow} \mathbb{Z}\_q \\), and
+/// computes \\( A = kG \\), \\( B = kP \\),
+/// \\( c = \mathcal{H}(d, X, P, Y, A, B) \\) for a domain separator \\( d \\),
+/// and \\( s = k - c \cdot x \\).  Verification is done by recomputing \\( c \\)
+/// and checking that \\( sG + cX \stackrel{?}{=} A \\) and
+/// \\( sP + cY \stackrel{?}{=} B \\).
+///
+/// Keeping the commitments \\( A \\) and \\( B \\) in the proof, rather than
+/// the challenge, allows many proofs to be checked at once with
+/// [`NizkOfDiscreteLogEquality::verify_batch`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NizkOfDiscreteLogEquality {
+    /// The commitment \\( A = kG \\).
+    pub A: AffinePoint,
+    /// The commitment \\( B = kP \\).
+    pub B: AffinePoint,
+    /// The response \\( s = k - c \cdot x \\).
+    pub s: Scalar,
+}
+
+/// Compute the challenge of a [`NizkOfDiscreteLogEquality`] in a `domain`,
+/// which is prefixed with its length so that no two domains' inputs collide.
+pub(crate) fn dleq_challenge(
+    domain: &[u8],
+    public_key: &AffinePoint,
+    base: &AffinePoint,
+    point: &AffinePoint,
+    A: &AffinePoint,
+    B: &AffinePoint,
+) -> Scalar
+{
+    let mut h = Keccak256::default();
+
+    h.update(b"FROST-DLEQ");
+    h.update((domain.len() as u64).to_be_bytes());
+    h.update(domain);
+
+    for P in [public_key, base, point, A, B].iter() {
+        h.update(P.to_bytes());
+    }
+
+    <Scalar as Reduce<U256>>::from_be_bytes_reduced(FieldBytes::from(h.finalize()))
+}
+
+impl NizkOfDiscreteLogEquality {
+    /// Prove, in some application-specific `domain`, that the `public_key`
+    /// \\( X = xG \\) and the `point` \\( Y = xP \\) on the `base` \\( P \\)
+    /// have the same discrete logarithm, the `secret_key` \\( x \\).
+    #[cfg(feature = "std")]
+    pub fn prove(
+        domain: &[u8],
+        secret_key: &Scalar,
+        public_key: &AffinePoint,
+        base: &AffinePoint,
+        point: &AffinePoint,
+        mut csprng: impl Rng + CryptoRng,
+    ) -> Self
+    {
+        let k: Scalar = Scalar::random(&mut csprng);
+        let A = (AffinePoint::GENERATOR * k).to_affine();
+        let B = (*base * k).to_affine();
+
+        let c = dleq_challenge(domain, public_key, base, point, &A, &B);
+        let s = k - (c * secret_key);
+
+        NizkOfDiscreteLogEquality { A, B, s }
+    }
+
+    /// Verify that the `public_key` and the `point` on the `base` have the
+    /// same discrete logarithm, with a proof made in the given `domain`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the proof is valid,
+    /// otherwise a string describing why it is not.
+    pub fn verify(
+        &self,
+        domain: &[u8],
+        public_key: &AffinePoint,
+        base: &AffinePoint,
+        point: &AffinePoint,
+    ) -> Result<(), &'static str>
+    {
+        let c = dleq_challenge(domain, public_key, base, point, &self.A, &self.B);
+        let A = ProjectivePoint::lincomb(&ProjectivePoint::GENERATOR, &self.s, &(*public_key).into(), &c);
+        let B = ProjectivePoint::lincomb(&(*base).into(), &self.s, &(*point).into(), &c);
+
+        match A.to_affine() == self.A && B.to_affine() == self.B {
+            true => Ok(()),
+            false => Err("Invalid proof of discrete logarithm equality"),
+        }
+    }
+
+    /// Verify many proofs made in the same `domain` at once.
+    ///
+    /// Each proof's two equations are multiplied by independent random
+    /// 128-bit coefficients and summed, so that all of them are checked with
+    /// one multiscalar multiplication.
+    ///
+    /// # Inputs
+    ///
+    /// * A cryptographically secure random number generator,
+    /// * The `domain` in which the proofs were made, and
+    /// * The `(proof, public_key, base, point)` statements to verify.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if every proof is valid.
+    /// Otherwise, each proof is then checked individually, and the `Err`
+    /// value contains the positions in `proofs` of those which are invalid.
+    #[cfg(feature = "std")]
+    pub fn verify_batch(
+        mut csprng: impl CryptoRng + Rng,
+        domain: &[u8],
+        proofs: &[(&NizkOfDiscreteLogEquality, &AffinePoint, &AffinePoint, &AffinePoint)],
+    ) -> Result<(), Vec<usize>>
+    {
+        let mut commitment_scalars: Vec<Scalar> = Vec::with_capacity(2 * proofs.len());
+        let mut commitments: Vec<AffinePoint> = Vec::with_capacity(2 * proofs.len());
+        let mut scalars: Vec<Scalar> = Vec::with_capacity(3 * proofs.len() + 1);
+        let mut points: Vec<AffinePoint> = Vec::with_capacity(3 * proofs.len() + 1);
+        let mut generator_coefficient = Scalar::ZERO;
+
+        for (proof, public_key, base, point) in proofs.iter() {
+            let a = random_batch_coefficient(&mut csprng);
+            let b = random_batch_coefficient(&mut csprng);
+            let c = dleq_challenge(domain, public_key, base, point, &proof.A, &proof.B);
+
+            // a(sG + cX - A) + b(sP + cY - B)
+            generator_coefficient += a * proof.s;
+            scalars.extend_from_slice(&[a * c, b * proof.s, b * c]);
+            points.extend_from_slice(&[**public_key, **base, **point]);
+            commitment_scalars.extend_from_slice(&[-a, -b]);
+            commitments.extend_from_slice(&[proof.A, proof.B]);
+        }
+        scalars.push(generator_coefficient);
+        points.push(AffinePoint::GENERATOR);
+
+        let check = multiscalar_mul(&commitment_scalars, &commitments, 256) + multiscalar_mul(&scalars, &points, 256);
+
+        if check == ProjectivePoint::IDENTITY {
+            return Ok(());
+        }
+
+        Err(proofs.iter()
+            .enumerate()
+            .filter(|(_, (proof, public_key, base, point))| proof.verify(domain, public_key, base, point).is_err())
+            .map(|(i, _)| i)
+            .collect())
+    }
+
+    /// Serialize this proof to the 98 bytes \\( A || B || s \\).
+    pub fn to_bytes(&self) -> [u8; 98] {
+        let mut bytes = [0u8; 98];
+
+        bytes[..33].copy_from_slice(&self.A.to_bytes()[..]);
+        bytes[33..66].copy_from_slice(&self.B.to_bytes()[..]);
+        bytes[66..].copy_from_slice(&self.s.to_bytes()[..]);
+        bytes
+    }
+
+    /// Attempt to deserialize a proof from the 98 bytes \\( A || B || s \\).
+    pub fn from_bytes(bytes: [u8; 98]) -> Option<NizkOfDiscreteLogEquality> {
+        let mut A = [0u8; 33];
+        let mut B = [0u8; 33];
+        let mut s = [0u8; 32];
+
+        A.copy_from_slice(&bytes[..33]);
+        B.copy_from_slice(&bytes[33..66]);
+        s.copy_from_slice(&bytes[66..]);
+
+        let A = Option::from(AffinePoint::from_bytes(&CompressedPoint::from(A)))?;
+        let B = Option::from(AffinePoint::from_bytes(&CompressedPoint::from(B)))?;
+        let s = Option::from(Scalar::from_repr(FieldBytes::from(s)))?;
+
+        Some(NizkOfDiscreteLogEquality { A, B, s })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::rngs::OsRng;
+
+    /// Make a statement \\( (X, P, Y) \\) and its proof.
+    fn statement(valid: bool) -> (NizkOfDiscreteLogEquality, AffinePoint, AffinePoint, AffinePoint) {
+        let x = Scalar::random(&mut OsRng);
+        let X = (AffinePoint::GENERATOR * x).to_affine();
+        let P = (AffinePoint::GENERATOR * Scalar::random(&mut OsRng)).to_affine();
+        let Y = match valid {
+            true => (P * x).to_affine(),
+            false => (P * (x + Scalar::ONE)).to_affine(),
+        };
+
+        (NizkOfDiscreteLogEquality::prove(b"test", &x, &X, &P, &Y, OsRng), X, P, Y)
+    }
+
+    #[test]
+    fn discrete_log_equality() {
+        let (proof, X, P, Y) = statement(true);
+
+        assert!(proof.verify(b"test", &X, &P, &Y).is_ok());
+        assert!(proof.verify(b"other", &X, &P, &Y).is_err());
+        assert!(proof.verify(b"test", &X, &Y, &P).is_err());
+
+        // A point with a different discrete logarithm.
+        let (proof, X, P, Y) = statement(false);
+
+        assert!(proof.verify(b"test", &X, &P, &Y).is_err());
+    }
+
+    #[test]
+    fn discrete_log_equality_serialisation() {
+        let (proof, X, P, Y) = statement(true);
+        let restored = NizkOfDiscreteLogEquality::from_bytes(proof.to_bytes()).unwrap();
+
+        assert_eq!(restored, proof);
+        assert!(restored.verify(b"test", &X, &P, &Y).is_ok());
+        assert_eq!(bincode::deserialize::<NizkOfDiscreteLogEquality>(&bincode::serialize(&proof).unwrap()).unwrap(), proof);
+
+        let mut bytes = proof.to_bytes();
+
+        bytes[66..].copy_from_slice(&[0xff; 32]);
+        assert!(NizkOfDiscreteLogEquality::from_bytes(bytes).is_none());
+    }
+
+    #[test]
+    fn discrete_log_equality_batch() {
+        let mut statements: Vec<_> = (0..20).map(|_| statement(true)).collect();
+        let batch = |statements: &[(NizkOfDiscreteLogEquality, AffinePoint, AffinePoint, AffinePoint)]| {
+            let proofs: Vec<_> = statements.iter().map(|(proof, X, P, Y)| (proof, X, P, Y)).collect();
+
+            NizkOfDiscreteLogEquality::verify_batch(OsRng, b"test", &proofs)
+        };
+
+        assert_eq!(batch(&statements), Ok(()));
+        assert_eq!(batch(&[]), Ok(()));
+
+        statements[3] = statement(false);
+        statements[17].0.s += Scalar::ONE;
+
+        assert_eq!(batch(&statements), Err(vec![3, 17]));
+    }
+}
@@ -9,9 +9,11 @@
 
 //! Configurable parameters for an instance of a FROST signing protocol.
 
+use serde::{Deserialize, Serialize};
+
 /// The configuration parameters for conducting the process of creating a
 /// threshold signature.
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct Parameters {
     /// The number of participants in the scheme.
     pub n: u32,
@@ -0,0 +1,389 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! Signer-side policies on what to sign.
+//!
+//! [`SecretKey::sign`] signs whatever `message_hash` it is given, so a signer
+//! who takes the hash from a compromised aggregator can be made to sign
+//! anything.  Instead, a signer should be sent the whole [`SigningRequest`],
+//! recompute the message and its hash from it, and check it against their own
+//! [`Policy`] first, which is what [`SecretKey::sign_with_policy`] does.
+//!
+//! A [`Policy`] denies everything by default: the contracts which may be
+//! called and the assets which may be moved must be allowed explicitly, with
+//! optional per-request limits and per-period quotas on the amounts.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+use crate::abi;
+use crate::abi::Token;
+use crate::keygen::GroupKey;
+use crate::keygen::SecretKey;
+use crate::precomputation::SecretCommitmentShareList;
+use crate::signature::compute_message_hash;
+use crate::signature::PartialThresholdSignature;
+use crate::signature::Signer;
+
+/// A structured request to sign a bridge transfer.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SigningRequest {
+    /// The context string for computing the message hash.
+    pub context: Vec<u8>,
+    /// The EIP-155 id of the chain on which the signature will be used.
+    pub chain_id: u64,
+    /// The address of the contract which will verify the signature.
+    pub contract: [u8; 20],
+    /// The address of the asset being transferred.
+    pub asset: [u8; 20],
+    /// The amount of the asset being transferred.
+    pub amount: u128,
+    /// Any further data of the transfer, e.g. the recipient and a nonce.
+    pub payload: Vec<u8>,
+}
+
+impl SigningRequest {
+    /// Compute the message to be signed, which is
+    /// `abi.encode(chainId, contract, asset, amount, payload)`, so that every
+    /// field of the request is bound by the signature.
+    pub fn message(&self) -> Vec<u8> {
+        abi::encode(&[
+            Token::uint(256, self.chain_id as u128),
+            Token::Address(self.contract),
+            Token::Address(self.asset),
+            Token::uint(256, self.amount),
+            Token::Bytes(self.payload.clone()),
+        ]).expect("well-formed tokens")
+    }
+
+    /// Compute the hash to be signed, i.e. [`compute_message_hash`] of the
+    /// `context` and the [`SigningRequest::message`].
+    ///
+    /// The aggregator should be constructed with the same context and
+    /// message, with [`SignatureAggregator::new`].
+    ///
+    /// [`SignatureAggregator::new`]: crate::signature::SignatureAggregator::new
+    pub fn message_hash(&self) -> [u8; 32] {
+        compute_message_hash(&self.context, &self.message())
+    }
+}
+
+/// A rolling quota on the total amount of an asset per period.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+struct Quota {
+    /// The total amount allowed within any period.
+    limit: u128,
+    /// The length of a period, in seconds.
+    period: u64,
+}
+
+/// A signer's rules on which [`SigningRequest`]s to sign.
+///
+/// Since the quotas depend upon what was already signed, a policy should be
+/// persisted with [`Policy::to_bytes`] after every signature, and restored
+/// with [`Policy::from_bytes`] after a restart.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Policy {
+    /// The `(chain_id, contract)` pairs which may be called.
+    allowed_contracts: HashSet<(u64, [u8; 20])>,
+    /// The assets which may be moved, with their limit per request, if any.
+    allowed_assets: HashMap<[u8; 20], Option<u128>>,
+    /// The quotas per asset.
+    quotas: HashMap<[u8; 20], Quota>,
+    /// The amounts of each asset with a quota signed for, with the time of
+    /// signing, within the last period.
+    history: HashMap<[u8; 20], Vec<(u64, u128)>>,
+}
+
+impl Policy {
+    /// Create a new [`Policy`], which refuses everything.
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+
+    /// Allow signing for the `contract` on the chain with id `chain_id`.
+    pub fn allow_contract(&mut self, chain_id: u64, contract: [u8; 20]) -> &mut Policy {
+        self.allowed_contracts.insert((chain_id, contract));
+        self
+    }
+
+    /// Allow signing transfers of an `asset` of up to `limit` per request, or
+    /// of any amount if the `limit` is `None`.
+    pub fn allow_asset(&mut self, asset: [u8; 20], limit: Option<u128>) -> &mut Policy {
+        self.allowed_assets.insert(asset, limit);
+        self
+    }
+
+    /// Limit the total amount of an `asset` signed for within any `period`
+    /// seconds to `limit`.
+    ///
+    /// Only requests signed after the quota was set are counted against it.
+    pub fn limit_per_period(&mut self, asset: [u8; 20], limit: u128, period: u64) -> &mut Policy {
+        self.quotas.insert(asset, Quota { limit, period });
+        self
+    }
+
+    /// Get the total amount of an `asset` signed for within the period up to
+    /// the time `now`, in seconds.
+    fn spent(&self, asset: &[u8; 20], quota: &Quota, now: u64) -> u128 {
+        self.history.get(asset).map_or(0, |history| {
+            history.iter()
+                .filter(|(time, _)| time.saturating_add(quota.period) > now)
+                .fold(0u128, |total, (_, amount)| total.saturating_add(*amount))
+        })
+    }
+
+    /// Check a `request` against this policy at the time `now`, in seconds.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the request may be
+    /// signed, otherwise a string describing why it may not, which should be
+    /// sent back to the aggregator.
+    pub fn check(&self, request: &SigningRequest, now: u64) -> Result<(), &'static str> {
+        if !self.allowed_contracts.contains(&(request.chain_id, request.contract)) {
+            return Err("Contract is not allowed");
+        }
+
+        let limit = self.allowed_assets.get(&request.asset).ok_or("Asset is not allowed")?;
+
+        if matches!(limit, Some(limit) if request.amount > *limit) {
+            return Err("Amount exceeds the per-request limit");
+        }
+        if let Some(quota) = self.quotas.get(&request.asset) {
+            if self.spent(&request.asset, quota, now).saturating_add(request.amount) > quota.limit {
+                return Err("Amount exceeds the quota for this period");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record that a `request` was signed at the time `now`, in seconds,
+    /// against the quotas.
+    ///
+    /// Nothing is recorded for an asset without a quota, and whatever has
+    /// dropped out of the period is forgotten, so that the history stays
+    /// bounded.
+    fn record(&mut self, request: &SigningRequest, now: u64) {
+        let quota = match self.quotas.get(&request.asset) {
+            Some(quota) => quota,
+            None => return,
+        };
+        let history = self.history.entry(request.asset).or_default();
+
+        history.retain(|(time, _)| time.saturating_add(quota.period) > now);
+        history.push((now, request.amount));
+    }
+
+    /// Serialise this policy, along with what was signed within the current
+    /// periods, for persisting it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("a serialisable policy")
+    }
+
+    /// Deserialise a policy persisted with [`Policy::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Option<Policy> {
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+impl SecretKey {
+    /// Compute this signer's [`PartialThresholdSignature`] on a
+    /// [`SigningRequest`], if our [`Policy`] allows it.
+    ///
+    /// The message hash is recomputed from the `request`, rather than taken
+    /// from the aggregator, and the request is counted against the `policy`'s
+    /// quotas if it is signed.
+    ///
+    /// # Inputs
+    ///
+    /// * The `request` to be signed,
+    /// * Our `policy`,
+    /// * The current time `now`, in seconds, e.g. since the UNIX epoch,
+    /// * The remaining inputs are as for [`SecretKey::sign`].
+    ///
+    /// # Returns
+    ///
+    /// A Result whose `Ok` value contains a [`PartialThresholdSignature`], which
+    /// should be sent to the aggregator.  Otherwise, its `Err` value contains
+    /// a string describing why we refused to sign, or the error which occurred.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sign_with_policy(
+        &self,
+        request: &SigningRequest,
+        policy: &mut Policy,
+        now: u64,
+        group_key: &GroupKey,
+        my_secret_commitment_share_list: &mut SecretCommitmentShareList,
+        my_commitment_share_index: usize,
+        signers: &[Signer],
+    ) -> Result<PartialThresholdSignature, &'static str>
+    {
+        policy.check(request, now)?;
+
+        let partial_signature = self.sign(&request.message_hash(), group_key, my_secret_commitment_share_list,
+                                          my_commitment_share_index, signers)?;
+
+        policy.record(request, now);
+
+        Ok(partial_signature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::SignatureAggregator;
+
+    use rand::rngs::OsRng;
+
+    const BRIDGE: [u8; 20] = [0xb1; 20];
+    const USDC: [u8; 20] = [0xa0; 20];
+    const WETH: [u8; 20] = [0xc0; 20];
+
+    fn request(amount: u128) -> SigningRequest {
+        SigningRequest {
+            context: b"BRIDGE WITHDRAWAL".to_vec(),
+            chain_id: 1,
+            contract: BRIDGE,
+            asset: USDC,
+            amount,
+            payload: b"recipient and nonce".to_vec(),
+        }
+    }
+
+    fn policy() -> Policy {
+        let mut policy = Policy::new();
+
+        policy.allow_contract(1, BRIDGE)
+              .allow_asset(USDC, Some(1_000))
+              .limit_per_period(USDC, 2_500, 3600);
+        policy
+    }
+
+    #[test]
+    fn policy_refusals() {
+        let policy = policy();
+
+        assert_eq!(Policy::new().check(&request(1), 0), Err("Contract is not allowed"));
+        assert!(policy.check(&request(1_000), 0).is_ok());
+        assert_eq!(policy.check(&request(1_001), 0), Err("Amount exceeds the per-request limit"));
+        assert_eq!(policy.check(&SigningRequest { chain_id: 5, ..request(1) }, 0), Err("Contract is not allowed"));
+        assert_eq!(policy.check(&SigningRequest { contract: WETH, ..request(1) }, 0), Err("Contract is not allowed"));
+        assert_eq!(policy.check(&SigningRequest { asset: WETH, ..request(1) }, 0), Err("Asset is not allowed"));
+    }
+
+    #[test]
+    fn quotas_roll_over() {
+        let mut policy = policy();
+
+        policy.record(&request(1_000), 0);
+        policy.record(&request(1_000), 1800);
+
+        assert!(policy.check(&request(500), 3599).is_ok());
+        assert_eq!(policy.check(&request(501), 3599), Err("Amount exceeds the quota for this period"));
+
+        // The first transfer has dropped out of the period.
+        assert!(policy.check(&request(1_000), 3600).is_ok());
+        assert!(policy.check(&request(1_000), 5400).is_ok());
+
+        // And is forgotten once something else is recorded.
+        policy.record(&request(1), 3600);
+
+        assert_eq!(policy.history[&USDC], vec![(1800, 1_000), (3600, 1)]);
+
+        // Assets without a quota keep no history at all.
+        policy.allow_asset(WETH, None);
+
+        for now in 0..100 {
+            policy.record(&SigningRequest { asset: WETH, ..request(1) }, now);
+        }
+        assert!(!policy.history.contains_key(&WETH));
+    }
+
+    #[test]
+    fn quotas_survive_a_restart() {
+        let mut policy = policy();
+
+        policy.record(&request(1_000), 0);
+        policy.record(&request(1_000), 1800);
+
+        let mut restored = Policy::from_bytes(&policy.to_bytes()).unwrap();
+
+        assert_eq!(restored.check(&request(501), 3599), Err("Amount exceeds the quota for this period"));
+        assert_eq!(restored.check(&request(1), 3599), policy.check(&request(1), 3599));
+        assert_eq!(restored.check(&SigningRequest { asset: WETH, ..request(1) }, 0), Err("Asset is not allowed"));
+
+        restored.record(&request(500), 3599);
+
+        assert_eq!(restored.check(&request(1), 3599), Err("Amount exceeds the quota for this period"));
+        assert!(Policy::from_bytes(&[0xff]).is_none());
+    }
+
+    #[test]
+    fn messages_bind_every_field() {
+        let hash = request(1).message_hash();
+
+        assert_ne!(SigningRequest { chain_id: 5, ..request(1) }.message_hash(), hash);
+        assert_ne!(SigningRequest { contract: WETH, ..request(1) }.message_hash(), hash);
+        assert_ne!(SigningRequest { asset: WETH, ..request(1) }.message_hash(), hash);
+        assert_ne!(request(2).message_hash(), hash);
+        assert_ne!(SigningRequest { payload: Vec::new(), ..request(1) }.message_hash(), hash);
+    }
+
+    #[test]
+    fn signing_with_policy() {
+        let params = Parameters { n: 2, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+        let mut policies = [policy(), policy()];
+
+        for (i, amount) in [1_000, 1_000, 1_000].iter().enumerate() {
+            let request = request(*amount);
+            let mut aggregator = SignatureAggregator::new(params, group_key, request.context.clone(), request.message());
+            let mut secret_comshares = Vec::new();
+
+            for p in participants.iter() {
+                let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, p.public_key.index, 1);
+
+                aggregator.include_signer(p.public_key.index, public_comshares.commitments[0], p.public_key.clone()).unwrap();
+                secret_comshares.push(secret);
+            }
+
+            let signers = aggregator.get_signers().clone();
+            let mut refusals = Vec::new();
+
+            for ((p, secret), policy) in participants.iter().zip(secret_comshares.iter_mut()).zip(policies.iter_mut()) {
+                let sk = p.secret_key();
+
+                match sk.sign_with_policy(&request, policy, 60 * i as u64, &group_key, secret, 0, &signers) {
+                    Ok(partial) => aggregator.include_partial_signature(partial).unwrap(),
+                    Err(reason) => refusals.push(reason),
+                }
+            }
+
+            // The third transfer within the hour is over the quota.
+            if i < 2 {
+                let signature = aggregator.finalize().unwrap().aggregate().unwrap();
+
+                assert!(signature.verify(&group_key, &request.message_hash()).is_ok());
+            } else {
+                assert_eq!(refusals, ["Amount exceeds the quota for this period"; 2]);
+            }
+        }
+    }
+}
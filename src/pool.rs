@@ -0,0 +1,429 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! A pool of published commitment shares, for one-round signing.
+//!
+//! The point of FROST's preprocessing round is that each signer publishes many
+//! commitment shares in advance, so that signing a message later takes only a
+//! single round: the aggregator picks one unused commitment share of each
+//! signer, and every signer answers with their partial signature straight
+//! away.
+//!
+//! A [`CommitmentPool`] is kept by the aggregator for this purpose.  Signers
+//! [`publish`] batches of commitment shares, which the pool hands out to
+//! signing sessions with [`assign`] exactly once each, recording them in a
+//! [`CommitmentRegistry`] so that re-published ones are refused.  Whenever a
+//! signer has fewer than a low watermark of commitment shares left, they are
+//! listed by [`needs_replenishing`], so that they can be asked for another
+//! batch from [`generate_commitment_share_lists`] before the pool runs dry.
+//!
+//! On the signer's side, the commitment share picked for a session is found in
+//! the list of [`Signer`]s, and its position in the signer's own
+//! [`SecretCommitmentShareList`] with [`SecretCommitmentShareList::index_of`].
+//!
+//! Both the pool and the signers' [`SecretCommitmentShareList`]s can be
+//! serialised, and must be persisted across restarts: losing track of which
+//! commitment shares were used is how nonces get reused.
+//!
+//! [`publish`]: CommitmentPool::publish
+//! [`assign`]: CommitmentPool::assign
+//! [`needs_replenishing`]: CommitmentPool::needs_replenishing
+//! [`generate_commitment_share_lists`]: crate::precomputation::generate_commitment_share_lists
+//! [`SecretCommitmentShareList`]: crate::precomputation::SecretCommitmentShareList
+//! [`SecretCommitmentShareList::index_of`]: crate::precomputation::SecretCommitmentShareList::index_of
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::elliptic_curve::group::GroupEncoding;
+
+use serde::{Deserialize, Serialize};
+
+use crate::parameters::Parameters;
+use crate::precomputation::CommitmentRegistry;
+use crate::precomputation::PublicCommitmentShareList;
+use crate::signature::Initial;
+use crate::signature::SignatureAggregator;
+use crate::signature::Signer;
+
+/// The published commitment shares which are available for signing sessions,
+/// per participant.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommitmentPool {
+    /// The protocol instance parameters.
+    parameters: Parameters,
+    /// Below how many available commitment shares a participant should
+    /// publish more.
+    low_watermark: usize,
+    /// The commitment shares which have not yet been assigned to a session, in
+    /// the order they were published.
+    available: HashMap<u32, VecDeque<(AffinePoint, AffinePoint)>>,
+    /// Every commitment share which was assigned to a session.
+    registry: CommitmentRegistry,
+}
+
+impl CommitmentPool {
+    /// Create a new, empty [`CommitmentPool`] for the participants described by
+    /// `parameters`, who should replenish their commitment shares whenever
+    /// fewer than `low_watermark` are left.
+    pub fn new(parameters: Parameters, low_watermark: usize) -> CommitmentPool {
+        CommitmentPool {
+            parameters,
+            low_watermark,
+            available: HashMap::new(),
+            registry: CommitmentRegistry::new(),
+        }
+    }
+
+    /// Add a batch of published commitment shares to the pool.
+    ///
+    /// The batch is either accepted as a whole or not at all.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the number of commitment shares now
+    /// available for the publishing participant, otherwise a string
+    /// describing why the batch was refused, e.g. because one of its
+    /// commitment shares was already published or used.
+    pub fn publish(&mut self, published: &PublicCommitmentShareList) -> Result<usize, &'static str> {
+        let index = published.participant_index;
+
+        if index == 0 || index > self.parameters.n {
+            return Err("Participant index out of range");
+        }
+
+        let available = self.available.entry(index).or_default();
+        let mut seen: Vec<AffinePoint> = available.iter().flat_map(|(hiding, binding)| [*hiding, *binding]).collect();
+
+        for commitment in published.commitments.iter() {
+            let (hiding, binding) = commitment;
+
+            if *hiding == AffinePoint::IDENTITY || *binding == AffinePoint::IDENTITY {
+                return Err("Commitment is the identity");
+            }
+            if self.registry.is_used(index, commitment) {
+                return Err("Nonce commitment was already used");
+            }
+            if seen.iter().any(|p| p.to_bytes() == hiding.to_bytes() || p.to_bytes() == binding.to_bytes()) {
+                return Err("Nonce commitment was already published");
+            }
+            seen.push(*hiding);
+            seen.push(*binding);
+        }
+
+        available.extend(published.commitments.iter().copied());
+
+        Ok(available.len())
+    }
+
+    /// Get the number of commitment shares available for the participant
+    /// with index `participant_index`.
+    pub fn available(&self, participant_index: u32) -> usize {
+        self.available.get(&participant_index).map_or(0, |available| available.len())
+    }
+
+    /// Get the participants who should publish more commitment shares.
+    ///
+    /// # Returns
+    ///
+    /// A sorted `Vec` of the indices of every participant with fewer than the
+    /// low watermark of commitment shares available.
+    pub fn needs_replenishing(&self) -> Vec<u32> {
+        (1..=self.parameters.n).filter(|index| self.available(*index) < self.low_watermark).collect()
+    }
+
+    /// Assign one commitment share of each of the `participants` to a signing
+    /// session, after which it can never be assigned again.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is a sorted `Vec` of the [`Signer`]s of the
+    /// session.  Otherwise, a `Hashmap<u32, &'static str>` containing the
+    /// participant indices for which no commitment share could be assigned,
+    /// and why, in which case nothing is assigned at all.
+    pub fn assign(&mut self, participants: &[u32]) -> Result<Vec<Signer>, HashMap<u32, &'static str>> {
+        let participants = sorted_and_deduplicated(participants);
+        let misbehaving_participants = self.check_assignable(&participants);
+
+        if !misbehaving_participants.is_empty() {
+            return Err(misbehaving_participants);
+        }
+
+        Ok(self.take(&participants))
+    }
+
+    /// Check, without changing anything, that one commitment share of each of
+    /// the `participants` can be assigned.
+    fn check_assignable(&self, participants: &[u32]) -> HashMap<u32, &'static str> {
+        let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+
+        for index in participants.iter() {
+            if *index == 0 || *index > self.parameters.n {
+                misbehaving_participants.insert(*index, "Participant index out of range");
+            } else if self.available(*index) == 0 {
+                misbehaving_participants.insert(*index, "No commitments available");
+            }
+        }
+
+        misbehaving_participants
+    }
+
+    /// Take the next commitment share of each of the `participants`, which
+    /// must have been checked with [`CommitmentPool::check_assignable`].
+    fn take(&mut self, participants: &[u32]) -> Vec<Signer> {
+        let mut signers: Vec<Signer> = Vec::with_capacity(participants.len());
+
+        for participant_index in participants.iter().copied() {
+            // These can't fail: the commitment share is available, and
+            // publish() ensured that it was not used before.
+            let published_commitment_share = self.available.get_mut(&participant_index)
+                .and_then(|available| available.pop_front())
+                .expect("an available commitment share");
+
+            self.registry.consume(participant_index, &published_commitment_share)
+                .expect("a fresh commitment share");
+
+            signers.push(Signer { participant_index, published_commitment_share });
+        }
+
+        signers
+    }
+
+    /// Assign one commitment share of each of the `participants` to a signing
+    /// session and include them in its `aggregator`, which must have been
+    /// constructed with [`SignatureAggregator::from_public_key_package`].
+    ///
+    /// Either every participant is included, or nothing is assigned and the
+    /// `aggregator` is left unchanged.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple, otherwise a
+    /// `Hashmap<u32, &'static str>` as for [`CommitmentPool::assign`], which
+    /// also lists the participants whom the `aggregator` refused, e.g.
+    /// because they were already included.
+    ///
+    /// If the `Hashmap` contains a key for `0`, this indicates that the
+    /// `aggregator` has no public key package.
+    pub fn include_signers(
+        &mut self,
+        aggregator: &mut SignatureAggregator<Initial>,
+        participants: &[u32],
+    ) -> Result<(), HashMap<u32, &'static str>>
+    {
+        if aggregator.state.public_key_package.is_none() {
+            let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+
+            misbehaving_participants.insert(0, "Aggregator was not constructed with a public key package");
+            return Err(misbehaving_participants);
+        }
+
+        let participants = sorted_and_deduplicated(participants);
+
+        // Check everything first, so that nothing is used up unless every
+        // participant can be included.
+        let mut misbehaving_participants = self.check_assignable(&participants);
+
+        for index in participants.iter() {
+            if misbehaving_participants.contains_key(index) {
+                continue;
+            }
+            if let Err(error) = aggregator.check_new_signer(*index) {
+                misbehaving_participants.insert(*index, error);
+            }
+        }
+
+        if !misbehaving_participants.is_empty() {
+            return Err(misbehaving_participants);
+        }
+
+        for signer in self.take(&participants) {
+            // This can't fail, since every signer was checked above.
+            aggregator.try_include_signer(signer.participant_index, signer.published_commitment_share)
+                .expect("an includable signer");
+        }
+
+        Ok(())
+    }
+
+    /// Serialise this pool, for persisting it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("a serialisable pool")
+    }
+
+    /// Deserialise a pool persisted with [`CommitmentPool::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Option<CommitmentPool> {
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+/// The `participants`, sorted and without duplicates.
+fn sorted_and_deduplicated(participants: &[u32]) -> Vec<u32> {
+    let mut participants = participants.to_vec();
+
+    participants.sort_unstable();
+    participants.dedup();
+    participants
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::compute_message_hash;
+
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn publish_and_assign() {
+        let params = Parameters { n: 3, t: 2 };
+        let mut pool = CommitmentPool::new(params, 2);
+        let (p1_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 1, 3);
+        let (p2_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        assert_eq!(pool.needs_replenishing(), vec![1, 2, 3]);
+        assert_eq!(pool.publish(&p1_public_comshares), Ok(3));
+        assert_eq!(pool.publish(&p2_public_comshares), Ok(1));
+        assert_eq!(pool.needs_replenishing(), vec![2, 3]);
+
+        // Re-publishing is refused, and so is nonsense.
+        assert_eq!(pool.publish(&p1_public_comshares), Err("Nonce commitment was already published"));
+
+        let stranger = PublicCommitmentShareList { participant_index: 4, commitments: Vec::new() };
+
+        assert_eq!(pool.publish(&stranger), Err("Participant index out of range"));
+
+        // Participant 3 has nothing to offer, so nothing is assigned.
+        let misbehaving_participants = pool.assign(&[1, 3]).unwrap_err();
+
+        assert_eq!(misbehaving_participants.get(&3), Some(&"No commitments available"));
+        assert_eq!(pool.available(1), 3);
+
+        let signers = pool.assign(&[2, 1]).unwrap();
+
+        assert_eq!(signers[0].participant_index, 1);
+        assert_eq!(signers[0].published_commitment_share, p1_public_comshares.commitments[0]);
+        assert_eq!(signers[1].participant_index, 2);
+        assert_eq!(pool.available(1), 2);
+        assert_eq!(pool.available(2), 0);
+        assert_eq!(pool.needs_replenishing(), vec![2, 3]);
+
+        // Commitment shares are handed out in order, and never again.
+        assert_eq!(pool.assign(&[1]).unwrap()[0].published_commitment_share, p1_public_comshares.commitments[1]);
+
+        let replay = PublicCommitmentShareList {
+            participant_index: 2,
+            commitments: p2_public_comshares.commitments.clone(),
+        };
+
+        assert_eq!(pool.publish(&replay), Err("Nonce commitment was already used"));
+    }
+
+    #[test]
+    fn persistence() {
+        let params = Parameters { n: 3, t: 2 };
+        let mut pool = CommitmentPool::new(params, 2);
+        let (p1_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 1, 2);
+
+        pool.publish(&p1_public_comshares).unwrap();
+        pool.assign(&[1]).unwrap();
+
+        let mut pool = CommitmentPool::from_bytes(&pool.to_bytes()).unwrap();
+
+        assert_eq!(pool.available(1), 1);
+        assert_eq!(pool.publish(&p1_public_comshares), Err("Nonce commitment was already used"));
+        assert_eq!(pool.assign(&[1]).unwrap()[0].published_commitment_share, p1_public_comshares.commitments[1]);
+        assert!(CommitmentPool::from_bytes(&[0u8; 3]).is_none());
+    }
+
+    #[test]
+    fn single_round_signing() {
+        let params = Parameters { n: 3, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let p1_sk = participants[0].secret_key();
+        let p3_sk = participants[2].secret_key();
+
+        // The preprocessing, done once for many messages.
+        let mut pool = CommitmentPool::new(params, 1);
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 4);
+        let (p3_public_comshares, mut p3_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 3, 4);
+
+        pool.publish(&p1_public_comshares).unwrap();
+        pool.publish(&p3_public_comshares).unwrap();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+
+        for i in 0..4 {
+            let message = format!("Bridge transfer number {}", i).into_bytes();
+            let message_hash = compute_message_hash(&context[..], &message[..]);
+            let mut aggregator = SignatureAggregator::from_public_key_package(package.clone(), context.to_vec(), message);
+
+            pool.include_signers(&mut aggregator, &[1, 3]).unwrap();
+
+            // The single round: each signer finds the commitment share which
+            // was picked for them, and signs with it.
+            let signers = aggregator.get_signers().clone();
+            let p1_index = p1_secret_comshares.index_of(&signers[0].published_commitment_share).unwrap();
+            let p3_index = p3_secret_comshares.index_of(&signers[1].published_commitment_share).unwrap();
+            let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, p1_index, &signers).unwrap();
+            let p3_partial = p3_sk.sign(&message_hash, &group_key, &mut p3_secret_comshares, p3_index, &signers).unwrap();
+
+            aggregator.include_partial_signature(p1_partial).unwrap();
+            aggregator.include_partial_signature(p3_partial).unwrap();
+
+            let threshold_signature = aggregator.finalize().unwrap().aggregate().unwrap();
+
+            assert!(threshold_signature.verify(&group_key, &message_hash).is_ok());
+        }
+
+        // The pool is empty, and the signers' secret lists with it.
+        assert_eq!(pool.needs_replenishing(), vec![1, 2, 3]);
+        assert!(p1_secret_comshares.commitments.is_empty());
+        assert!(p3_secret_comshares.commitments.is_empty());
+
+        let mut aggregator = SignatureAggregator::from_public_key_package(package, context.to_vec(), Vec::new());
+
+        assert!(pool.include_signers(&mut aggregator, &[1, 3]).is_err());
+    }
+
+    #[test]
+    fn failed_inclusion_uses_up_nothing() {
+        let params = Parameters { n: 3, t: 2 };
+        let (_, package) = dealt_group(params);
+        let mut pool = CommitmentPool::new(params, 1);
+        let (p1_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 1, 2);
+        let (p2_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 2, 2);
+        let (extra_comshares, _) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        pool.publish(&p1_public_comshares).unwrap();
+        pool.publish(&p2_public_comshares).unwrap();
+
+        // Participant 2 is already in the session, so they can't be included again.
+        let mut aggregator = SignatureAggregator::from_public_key_package(package, Vec::new(), b"m".to_vec());
+
+        aggregator.try_include_signer(2, extra_comshares.commitments[0]).unwrap();
+
+        let misbehaving_participants = pool.include_signers(&mut aggregator, &[1, 2]).unwrap_err();
+
+        assert_eq!(misbehaving_participants.len(), 1);
+        assert_eq!(misbehaving_participants.get(&2), Some(&"Signer was already included"));
+        assert_eq!(pool.available(1), 2);
+        assert_eq!(pool.available(2), 2);
+        assert_eq!(aggregator.get_signers().len(), 1);
+
+        // Nothing was recorded as used, so the same commitment shares are assigned later.
+        assert_eq!(pool.assign(&[1]).unwrap()[0].published_commitment_share, p1_public_comshares.commitments[0]);
+    }
+}
@@ -9,6 +9,10 @@
 
 //! Precomputation for one-round signing.
 
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
 #[cfg(feature = "std")]
 use std::vec::Vec;
 
@@ -24,6 +28,8 @@ use k256::elliptic_curve::group::GroupEncoding;
 #[cfg(feature = "std")]
 use rand::{CryptoRng, Rng};
 
+use serde::{Deserialize, Serialize};
+
 use subtle::Choice;
 use subtle::ConstantTimeEq;
 
@@ -59,7 +65,7 @@ impl From<NoncePair> for CommitmentShare {
 }
 
 /// A pair of a nonce and a commitment to it.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct Commitment {
     /// The nonce.
     pub(crate) nonce: Scalar,
@@ -89,7 +95,7 @@ impl ConstantTimeEq for Commitment {
 }
 
 /// A precomputed commitment share.
-#[derive(Clone, Debug, Zeroize)]
+#[derive(Clone, Debug, Zeroize, Serialize, Deserialize)]
 #[zeroize(drop)]
 pub struct CommitmentShare {
     /// The hiding commitment.
@@ -118,7 +124,7 @@ impl CommitmentShare {
 
 /// A secret commitment share list, containing the revealed nonces for the
 /// hiding and binding commitments.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SecretCommitmentShareList {
     /// The secret commitment shares.
     pub commitments: Vec<CommitmentShare>,
@@ -129,7 +135,7 @@ pub struct SecretCommitmentShareList {
 ///
 /// This should be published somewhere before the signing protocol takes place
 /// for the other signing participants to obtain.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PublicCommitmentShareList {
     /// The participant's index.
     pub participant_index: u32,
@@ -195,6 +201,143 @@ impl SecretCommitmentShareList {
         }
         drop(share);
     }
+
+    /// Find the position in this list of the [`CommitmentShare`] whose public
+    /// commitments are `published`, e.g. the one which the aggregator picked
+    /// for us from our [`PublicCommitmentShareList`].
+    ///
+    /// # Returns
+    ///
+    /// The index to give to [`SecretKey::sign`], or `None` if we don't have
+    /// (or no longer have) the commitment share, in which case we must not
+    /// sign.
+    ///
+    /// [`SecretKey::sign`]: crate::keygen::SecretKey::sign
+    pub fn index_of(&self, published: &(AffinePoint, AffinePoint)) -> Option<usize> {
+        self.commitments.iter().position(|s| {
+            let (hiding, binding) = s.publish();
+
+            hiding.to_bytes() == published.0.to_bytes() && binding.to_bytes() == published.1.to_bytes()
+        })
+    }
+}
+
+/// A record of every published commitment share which has already been used
+/// in a signing session, kept by the signature aggregator(s).
+///
+/// Signing twice with the same nonces reveals the signer's long-term secret
+/// key, so an aggregator must never put a commitment into a [`Signer`] list
+/// twice, even if a buggy or malicious signer re-advertises it.  A hiding or
+/// binding commitment is considered used as soon as it has appeared in any
+/// session, regardless of what it was paired with.
+///
+/// The registry can be serialised, so that it survives restarts of the
+/// aggregator and can be shared by several aggregators.
+///
+/// [`Signer`]: crate::signature::Signer
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CommitmentRegistry {
+    /// The compressed hiding and binding commitments used by each participant.
+    #[serde(with = "compressed_point_sets")]
+    used: HashMap<u32, HashSet<[u8; 33]>>,
+}
+
+/// Serde has no implementations for arrays longer than 32 bytes, so the
+/// compressed points in a [`CommitmentRegistry`] are (de)serialised as slices.
+#[cfg(feature = "std")]
+mod compressed_point_sets {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    use serde::de::Error;
+    use serde::{Deserializer, Serializer};
+
+    pub(super) fn serialize<S>(used: &HashMap<u32, HashSet<[u8; 33]>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let used: HashMap<u32, Vec<&[u8]>> = used.iter()
+            .map(|(index, points)| (*index, points.iter().map(|point| &point[..]).collect()))
+            .collect();
+
+        used.serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<HashMap<u32, HashSet<[u8; 33]>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let used: HashMap<u32, Vec<Vec<u8>>> = HashMap::deserialize(deserializer)?;
+
+        used.into_iter()
+            .map(|(index, points)| {
+                let points = points.into_iter()
+                    .map(|point| <[u8; 33]>::try_from(point).map_err(|_| D::Error::custom("Invalid compressed point length")))
+                    .collect::<Result<HashSet<[u8; 33]>, D::Error>>()?;
+
+                Ok((index, points))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl CommitmentRegistry {
+    /// Create a new, empty [`CommitmentRegistry`].
+    pub fn new() -> CommitmentRegistry {
+        CommitmentRegistry::default()
+    }
+
+    /// Check whether either half of a `published_commitment_share` has already
+    /// been used by the participant with index `participant_index`.
+    pub fn is_used(&self, participant_index: u32, published_commitment_share: &(AffinePoint, AffinePoint)) -> bool {
+        let (hiding, binding) = published_commitment_share;
+
+        match self.used.get(&participant_index) {
+            Some(used) => used.contains(&compress(hiding)) || used.contains(&compress(binding)),
+            None => false,
+        }
+    }
+
+    /// Record that the participant with index `participant_index` has used a
+    /// `published_commitment_share`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the commitment share
+    /// was fresh, otherwise a string describing the reuse, in which case
+    /// nothing is recorded.
+    pub fn consume(
+        &mut self,
+        participant_index: u32,
+        published_commitment_share: &(AffinePoint, AffinePoint),
+    ) -> Result<(), &'static str>
+    {
+        let (hiding, binding) = published_commitment_share;
+
+        if hiding.to_bytes() == binding.to_bytes() {
+            return Err("Hiding and binding commitments are identical");
+        }
+        if self.is_used(participant_index, published_commitment_share) {
+            return Err("Nonce commitment was already used");
+        }
+
+        let used = self.used.entry(participant_index).or_default();
+
+        used.insert(compress(hiding));
+        used.insert(compress(binding));
+
+        Ok(())
+    }
+}
+
+/// The fixed-size compressed encoding of a `point`, as stored in a
+/// [`CommitmentRegistry`].
+#[cfg(feature = "std")]
+fn compress(point: &AffinePoint) -> [u8; 33] {
+    point.to_bytes().into()
 }
 
 #[cfg(feature = "std")]
@@ -234,4 +377,66 @@ mod test {
 
         assert!(secret_share_list.commitments.len() == 7);
     }
+
+    #[test]
+    fn commitment_registry_refuses_reuse() {
+        let (public_share_list, _secret_share_list) = generate_commitment_share_lists(&mut OsRng, 1, 2);
+        let mut registry = CommitmentRegistry::new();
+        let (hiding, binding) = public_share_list.commitments[0];
+        let (other_hiding, other_binding) = public_share_list.commitments[1];
+
+        assert!(!registry.is_used(1, &(hiding, binding)));
+        assert!(registry.consume(1, &(hiding, binding)).is_ok());
+        assert!(registry.is_used(1, &(hiding, binding)));
+
+        // Reusing either half of a commitment share is just as bad.
+        assert_eq!(registry.consume(1, &(hiding, binding)), Err("Nonce commitment was already used"));
+        assert_eq!(registry.consume(1, &(hiding, other_binding)), Err("Nonce commitment was already used"));
+        assert_eq!(registry.consume(1, &(other_hiding, binding)), Err("Nonce commitment was already used"));
+        assert_eq!(registry.consume(1, &(other_hiding, other_hiding)),
+                   Err("Hiding and binding commitments are identical"));
+
+        // The failed attempts didn't consume the fresh halves.
+        assert!(registry.consume(1, &(other_hiding, other_binding)).is_ok());
+
+        // Commitments are tracked per participant.
+        assert!(!registry.is_used(2, &(hiding, binding)));
+    }
+
+    #[test]
+    fn commitment_registry_serialisation() {
+        let (public_share_list, _secret_share_list) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let mut registry = CommitmentRegistry::new();
+
+        registry.consume(1, &public_share_list.commitments[0]).unwrap();
+
+        let bytes = bincode::serialize(&registry).unwrap();
+        let registry: CommitmentRegistry = bincode::deserialize(&bytes).unwrap();
+
+        assert!(registry.is_used(1, &public_share_list.commitments[0]));
+    }
+
+    #[test]
+    fn index_of_published_commitment_share() {
+        let (public_share_list, mut secret_share_list) = generate_commitment_share_lists(&mut OsRng, 1, 3);
+
+        assert_eq!(secret_share_list.index_of(&public_share_list.commitments[2]), Some(2));
+
+        let used_share = secret_share_list.commitments[0].clone();
+
+        secret_share_list.drop_share(used_share);
+
+        assert_eq!(secret_share_list.index_of(&public_share_list.commitments[0]), None);
+        assert_eq!(secret_share_list.index_of(&public_share_list.commitments[2]), Some(1));
+    }
+
+    #[test]
+    fn secret_commitment_share_list_serialisation() {
+        let (public_share_list, secret_share_list) = generate_commitment_share_lists(&mut OsRng, 1, 2);
+
+        let bytes = bincode::serialize(&secret_share_list).unwrap();
+        let secret_share_list: SecretCommitmentShareList = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(secret_share_list.index_of(&public_share_list.commitments[1]), Some(1));
+    }
 }
@@ -25,6 +25,9 @@ use sha3::Keccak256;
 
 use core::cmp::Ordering;
 
+#[cfg(feature = "std")]
+use rand::{CryptoRng, Rng};
+
 #[cfg(feature = "std")]
 use std::collections::HashMap;
 #[cfg(feature = "std")]
@@ -38,9 +41,19 @@ use k256::CompressedPoint;
 use k256::AffinePoint;
 use k256::Scalar;
 
+use serde::{Deserialize, Serialize};
+
 use crate::keygen::GroupKey;
 use crate::keygen::IndividualPublicKey;
+#[cfg(feature = "std")]
+use crate::keygen::PublicKeyPackage;
 use crate::parameters::Parameters;
+#[cfg(feature = "std")]
+use crate::adaptor::AdaptorScheme;
+#[cfg(feature = "std")]
+use crate::hashing::{eip191_hash, eip712_digest};
+#[cfg(feature = "std")]
+use crate::precomputation::CommitmentRegistry;
 use crate::precomputation::SecretCommitmentShareList;
 
 pub use crate::keygen::SecretKey;
@@ -49,7 +62,7 @@ pub use crate::keygen::SecretKey;
 //     signer's long-term secret key; it must be prevented at all costs.
 
 /// An individual signer in the threshold signature scheme.
-#[derive(Clone, Copy, Debug, Eq)]
+#[derive(Clone, Copy, Debug, Eq, Serialize, Deserialize)]
 pub struct Signer {
     /// The participant index of this signer.
     pub participant_index: u32,
@@ -81,9 +94,18 @@ impl PartialEq for Signer {
     }
 }
 
+/// Determine whether two signers are the same participant with the same
+/// published commitment share.
+#[cfg(feature = "std")]
+fn same_signer_and_commitment(a: &Signer, b: &Signer) -> bool {
+    a.participant_index == b.participant_index &&
+        a.published_commitment_share.0.to_bytes() == b.published_commitment_share.0.to_bytes() &&
+        a.published_commitment_share.1.to_bytes() == b.published_commitment_share.1.to_bytes()
+}
+
 /// A partially-constructed threshold signature, made by each participant in the
 /// signing protocol during the first phase of a signature creation.
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PartialThresholdSignature {
     /// Index of the signature
     pub index: u32,
@@ -92,7 +114,7 @@ pub struct PartialThresholdSignature {
 }
 
 /// A complete, aggregated threshold signature.
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ThresholdSignature {
     pub(crate) R: AffinePoint,
     pub(crate) z: Scalar,
@@ -166,7 +188,7 @@ impl $type {
 //     and its algorithms?
 #[cfg(feature = "std")]
 #[derive(Debug)]
-struct SignerRs(pub(crate) HashMap<[u8; 4], AffinePoint>);
+pub(crate) struct SignerRs(pub(crate) HashMap<[u8; 4], AffinePoint>);
 
 #[cfg(feature = "std")]
 impl_indexed_hashmap!(Type = SignerRs, Item = AffinePoint);
@@ -200,7 +222,7 @@ pub fn compute_message_hash(context_string: &[u8], message: &[u8]) -> [u8; 32] {
 }
 
 #[cfg(feature = "std")]
-fn compute_binding_factors_and_group_commitment(
+pub(crate) fn compute_binding_factors_and_group_commitment(
     message_hash: &[u8; 32],
     signers: &[Signer],
 ) -> (HashMap<u32, Scalar>, SignerRs)
@@ -261,7 +283,7 @@ fn compute_binding_factors_and_group_commitment(
 }
 
 // [DIFFERENT_FROM_PAPER] H(Y | m | Addr(Y))
-fn compute_challenge(message_hash: &[u8; 32], group_key: &GroupKey, R: &AffinePoint) -> Scalar {
+pub(crate) fn compute_challenge(message_hash: &[u8; 32], group_key: &GroupKey, R: &AffinePoint) -> Scalar {
     let mut h1 = Keccak256::default();
     let enc = R.to_encoded_point(false).to_bytes();
     h1.update(&enc[1..33]);
@@ -361,16 +383,11 @@ impl SecretKey {
             return Err("Commitment share index out of bounds");
         }
 
-        let (binding_factors, Rs) = compute_binding_factors_and_group_commitment(&message_hash, &signers);
-        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
-        let challenge = compute_challenge(&message_hash, &group_key, &R.to_affine());
-        let my_binding_factor = binding_factors.get(&self.index).ok_or("Could not compute our blinding factor")?;
-        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
-        let lambda: Scalar = calculate_lagrange_coefficients(&self.index, &all_participant_indices)?;
+        let (my_binding_factor, lambda_challenge) = self.signing_coefficients(message_hash, group_key, signers)?;
         let my_commitment_share = my_secret_commitment_share_list.commitments[my_commitment_share_index].clone();
         let z = my_commitment_share.hiding.nonce +
             (my_commitment_share.binding.nonce * my_binding_factor) -
-            (lambda * self.key * challenge); // [DIFFERENT_TO_PAPER] this term is positive in the paper.
+            (lambda_challenge * self.key); // [DIFFERENT_TO_PAPER] this term is positive in the paper.
 
         // [DIFFERENT_TO_PAPER] We need to instead pass in the commitment
         // share list and zero-out the used commitment share, which means the
@@ -387,6 +404,40 @@ impl SecretKey {
 
         Ok(PartialThresholdSignature { index: self.index, z })
     }
+
+    /// Compute everything which [`SecretKey::sign`] needs from the `signers`,
+    /// without touching any secret commitment shares, so that a malformed
+    /// list of `signers` can be refused before any nonces are used up.
+    ///
+    /// # Returns
+    ///
+    /// Our binding factor \\(\rho\_i\\) and the product \\(\lambda\_i c\\) of
+    /// our Lagrange coefficient and the challenge, otherwise a string
+    /// describing the error.
+    #[cfg(feature = "std")]
+    pub(crate) fn signing_coefficients(
+        &self,
+        message_hash: &[u8; 32],
+        group_key: &GroupKey,
+        signers: &[Signer],
+    ) -> Result<(Scalar, Scalar), &'static str>
+    {
+        let (binding_factors, Rs) = compute_binding_factors_and_group_commitment(message_hash, signers);
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+        let challenge = compute_challenge(message_hash, group_key, &R.to_affine());
+        let my_binding_factor = binding_factors.get(&self.index).ok_or("Could not compute our blinding factor")?;
+        let mut all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda: Scalar = calculate_lagrange_coefficients(&self.index, &all_participant_indices)?;
+
+        all_participant_indices.sort_unstable();
+        all_participant_indices.dedup();
+
+        if all_participant_indices.len() != signers.len() {
+            return Err("Duplicate signers provided");
+        }
+
+        Ok((*my_binding_factor, lambda * challenge))
+    }
 }
 
 /// A signature aggregator, in any of various states.
@@ -407,6 +458,16 @@ pub(crate) struct AggregatorState {
     pub(crate) partial_signatures: PartialThresholdSignatures,
     /// The group public key for all the participants.
     pub(crate) group_key: GroupKey,
+    /// The public keys of all the participants, if known, from which the
+    /// signers' public keys are looked up.
+    pub(crate) public_key_package: Option<PublicKeyPackage>,
+    /// The signers whose commitment shares were already recorded as used by
+    /// [`SignatureAggregator::consume_commitments`].
+    pub(crate) consumed_commitments: Vec<Signer>,
+    /// The scheme and adaptor point, if this aggregator was set up with
+    /// [`SignatureAggregator::set_adaptor`] to make an
+    /// [`AdaptorSignature`](crate::adaptor::AdaptorSignature).
+    pub(crate) adaptor: Option<(AdaptorScheme, AffinePoint)>,
 }
 
 /// A signature aggregator is an untrusted party who coalesces all of the
@@ -423,6 +484,63 @@ pub struct SignatureAggregator<A: Aggregator> {
     pub(crate) aggregator: A,
 }
 
+/// How the 32-byte digest which is actually signed was obtained from the
+/// message.
+///
+/// This should be sent to the signers along with the context string and
+/// message, so that they can recompute the digest with
+/// [`HashingMode::message_hash`] before signing it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum HashingMode {
+    /// The digest is [`compute_message_hash`] of a context string and the
+    /// message, as given to [`SignatureAggregator::new`].
+    ContextAndMessage,
+    /// The digest was computed elsewhere, e.g. an ABI-encoded hash, and given
+    /// to [`SignatureAggregator::new_prehashed`] as is.
+    Prehashed,
+    /// The digest is the EIP-191 `personal_sign` hash of the message, as given
+    /// to [`SignatureAggregator::new_eip191`].
+    Eip191,
+    /// The digest is the EIP-712 hash of typed structured data, whose domain
+    /// separator and struct hash were given to
+    /// [`SignatureAggregator::new_eip712`].
+    Eip712,
+}
+
+#[cfg(feature = "std")]
+impl HashingMode {
+    /// Recompute the digest to be signed from the `context` string and
+    /// `message`, as given by [`SignatureAggregator::context`] and
+    /// [`SignatureAggregator::message`], so that a signer can confirm that
+    /// the `message_hash` they are asked to sign is the one they expect.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the digest, otherwise a string
+    /// describing the error.  A digest in [`HashingMode::Prehashed`] can't be
+    /// recomputed from the message, and must instead be checked by the signer
+    /// against the digest of whatever they meant to sign.
+    pub fn message_hash(&self, context: &[u8], message: &[u8]) -> Result<[u8; 32], &'static str> {
+        match self {
+            HashingMode::ContextAndMessage => Ok(compute_message_hash(context, message)),
+            HashingMode::Prehashed => Err("A prehashed digest can't be recomputed from the message"),
+            HashingMode::Eip191 => Ok(eip191_hash(message)),
+            HashingMode::Eip712 => {
+                if message.len() != 66 || message[..2] != [0x19, 0x01] {
+                    return Err("Not an EIP-712 message");
+                }
+                let mut domain_separator = [0u8; 32];
+                let mut struct_hash = [0u8; 32];
+
+                domain_separator.copy_from_slice(&message[2..34]);
+                struct_hash.copy_from_slice(&message[34..]);
+
+                Ok(eip712_digest(&domain_separator, &struct_hash))
+            },
+        }
+    }
+}
+
 /// The initial state for a [`SignatureAggregator`], which may include invalid
 /// or non-sensical data.
 #[derive(Debug)]
@@ -431,6 +549,10 @@ pub struct Initial {
     pub(crate) context: Vec<u8>,
     /// The message to be signed.
     pub(crate) message: Vec<u8>,
+    /// The digest to be signed.
+    pub(crate) message_hash: [u8; 32],
+    /// How the `message_hash` was obtained.
+    pub(crate) hashing_mode: HashingMode,
 }
 
 impl Aggregator for Initial {}
@@ -453,6 +575,8 @@ impl Aggregator for Initial {}
 pub struct Finalized {
     /// The hashed context and message for signing.
     pub(crate) message_hash: [u8; 32],
+    /// How the `message_hash` was obtained.
+    pub(crate) hashing_mode: HashingMode,
 }
 
 impl Aggregator for Finalized {}
@@ -487,9 +611,184 @@ impl SignatureAggregator<Initial> {
         let signers: Vec<Signer> = Vec::with_capacity(parameters.t as usize);
         let public_keys = IndividualPublicKeys::new();
         let partial_signatures = PartialThresholdSignatures::new();
-        let state = AggregatorState { parameters, signers, public_keys, partial_signatures, group_key };
+        let state = AggregatorState {
+            parameters,
+            signers,
+            public_keys,
+            partial_signatures,
+            group_key,
+            public_key_package: None,
+            consumed_commitments: Vec::new(),
+            adaptor: None,
+        };
+
+        let message_hash = compute_message_hash(&context, &message);
+        let hashing_mode = HashingMode::ContextAndMessage;
+
+        SignatureAggregator { state: Box::new(state), aggregator: Initial { context, message, message_hash, hashing_mode } }
+    }
+
+    /// Construct a new signature aggregator from some protocol instantiation
+    /// `parameters` and a precomputed `message_hash` to be signed as is.
+    ///
+    /// # Inputs
+    ///
+    /// * The [`Parameters`] for this threshold signing operation,
+    /// * The public [`GroupKey`] for the intended sets of signers,
+    /// * The 32-byte `message_hash`, e.g. an EIP-191 or EIP-712 digest.
+    ///
+    /// # Notes
+    ///
+    /// Signers should check that [`SignatureAggregator::message_hash`] is the
+    /// digest they expect, and sign exactly it.
+    ///
+    /// # Returns
+    ///
+    /// A new [`SignatureAggregator`] in [`HashingMode::Prehashed`].
+    pub fn new_prehashed(
+        parameters: Parameters,
+        group_key: GroupKey,
+        message_hash: [u8; 32],
+    ) -> SignatureAggregator<Initial> {
+        let mut aggregator = SignatureAggregator::new(parameters, group_key, Vec::new(), Vec::new());
+
+        aggregator.aggregator.message_hash = message_hash;
+        aggregator.aggregator.hashing_mode = HashingMode::Prehashed;
+        aggregator
+    }
+
+    /// Construct a new signature aggregator from some protocol instantiation
+    /// `parameters` and a `message` to be signed as with `personal_sign`.
+    ///
+    /// # Returns
+    ///
+    /// A new [`SignatureAggregator`] in [`HashingMode::Eip191`], whose
+    /// [`SignatureAggregator::message_hash`] is the [`eip191_hash`] of the
+    /// `message`.
+    pub fn new_eip191(
+        parameters: Parameters,
+        group_key: GroupKey,
+        message: Vec<u8>,
+    ) -> SignatureAggregator<Initial> {
+        let mut aggregator = SignatureAggregator::new_prehashed(parameters, group_key, eip191_hash(&message));
+
+        aggregator.aggregator.message = message;
+        aggregator.aggregator.hashing_mode = HashingMode::Eip191;
+        aggregator
+    }
+
+    /// Construct a new signature aggregator from some protocol instantiation
+    /// `parameters` and EIP-712 typed structured data to be signed.
+    ///
+    /// # Inputs
+    ///
+    /// * The [`Parameters`] for this threshold signing operation,
+    /// * The public [`GroupKey`] for the intended sets of signers,
+    /// * The `domain_separator`, e.g. from [`Eip712Domain::separator`],
+    /// * The `struct_hash` of the data, e.g. from [`Eip712Types::hash_struct`].
+    ///
+    /// # Returns
+    ///
+    /// A new [`SignatureAggregator`] in [`HashingMode::Eip712`], whose
+    /// [`SignatureAggregator::message`] is the 66 bytes
+    /// `0x1901 || domain_separator || struct_hash` which are hashed.
+    ///
+    /// [`Eip712Domain::separator`]: crate::hashing::Eip712Domain::separator
+    /// [`Eip712Types::hash_struct`]: crate::hashing::Eip712Types::hash_struct
+    pub fn new_eip712(
+        parameters: Parameters,
+        group_key: GroupKey,
+        domain_separator: [u8; 32],
+        struct_hash: [u8; 32],
+    ) -> SignatureAggregator<Initial> {
+        let message_hash = eip712_digest(&domain_separator, &struct_hash);
+        let mut aggregator = SignatureAggregator::new_prehashed(parameters, group_key, message_hash);
 
-        SignatureAggregator { state: Box::new(state), aggregator: Initial { context, message } }
+        aggregator.aggregator.message = [&[0x19, 0x01][..], &domain_separator, &struct_hash].concat();
+        aggregator.aggregator.hashing_mode = HashingMode::Eip712;
+        aggregator
+    }
+
+    /// Construct a new signature aggregator for the group described by a
+    /// verified [`PublicKeyPackage`] and a `message` to be signed.
+    ///
+    /// Signers should then be added with
+    /// [`SignatureAggregator::try_include_signer`], which looks up their
+    /// public keys in the `public_key_package`, rather than trusting the
+    /// caller to provide them.
+    ///
+    /// # Inputs
+    ///
+    /// * The [`PublicKeyPackage`] of the group,
+    /// * An optional `context` string for computing the message hash,
+    /// * The `message` to be signed.
+    ///
+    /// # Returns
+    ///
+    /// A new [`SignatureAggregator`].
+    pub fn from_public_key_package(
+        public_key_package: PublicKeyPackage,
+        context: Vec<u8>,
+        message: Vec<u8>,
+    ) -> SignatureAggregator<Initial> {
+        let mut aggregator = SignatureAggregator::new(
+            public_key_package.parameters,
+            public_key_package.group_key,
+            context,
+            message,
+        );
+
+        aggregator.state.public_key_package = Some(public_key_package);
+        aggregator
+    }
+
+    /// Construct a new signature aggregator for the group described by a
+    /// verified [`PublicKeyPackage`] and a precomputed `message_hash` to be
+    /// signed as is.
+    ///
+    /// This is to [`SignatureAggregator::from_public_key_package`] as
+    /// [`SignatureAggregator::new_prehashed`] is to [`SignatureAggregator::new`].
+    ///
+    /// # Returns
+    ///
+    /// A new [`SignatureAggregator`] in [`HashingMode::Prehashed`].
+    pub fn from_public_key_package_prehashed(
+        public_key_package: PublicKeyPackage,
+        message_hash: [u8; 32],
+    ) -> SignatureAggregator<Initial> {
+        let mut aggregator = SignatureAggregator::new_prehashed(
+            public_key_package.parameters,
+            public_key_package.group_key,
+            message_hash,
+        );
+
+        aggregator.state.public_key_package = Some(public_key_package);
+        aggregator
+    }
+
+    /// The context string which the message is hashed with, which is empty
+    /// unless in [`HashingMode::ContextAndMessage`].
+    pub fn context(&self) -> &[u8] {
+        &self.aggregator.context
+    }
+
+    /// The message to be signed, which is empty in [`HashingMode::Prehashed`].
+    pub fn message(&self) -> &[u8] {
+        &self.aggregator.message
+    }
+
+    /// The digest which the signers are asked to sign, i.e. the
+    /// `message_hash` to give to [`SecretKey::sign`].
+    ///
+    /// This should be sent to the signers along with the list of signers, so
+    /// that they can confirm that it is what they meant to sign.
+    pub fn message_hash(&self) -> &[u8; 32] {
+        &self.aggregator.message_hash
+    }
+
+    /// How the [`SignatureAggregator::message_hash`] was obtained.
+    pub fn hashing_mode(&self) -> HashingMode {
+        self.aggregator.hashing_mode
     }
 
     /// Include a signer in the protocol.
@@ -501,21 +800,194 @@ impl SignatureAggregator<Initial> {
     /// [`SignatureAggregator.include_partial_signature`], otherwise the signing
     /// procedure will fail.
     ///
-    /// # Panics
+    /// # Returns
     ///
-    /// If the `signer.participant_index` doesn't match the `public_key.index`.
+    /// A `Result` whose `Ok` value is an empty tuple if the signer was
+    /// included, otherwise a string describing why they were not, e.g. because
+    /// the `public_key` is not for the participant with `participant_index`,
+    /// or because partial signatures were already included, since a new
+    /// signer would change what a valid partial signature is.
+    /// If this aggregator was constructed with a [`PublicKeyPackage`], the
+    /// `public_key` must also be the one in the package.
     pub fn include_signer(
         &mut self,
         participant_index: u32,
         published_commitment_share: (AffinePoint, AffinePoint),
-        public_key: IndividualPublicKey)
+        public_key: IndividualPublicKey,
+    ) -> Result<(), &'static str>
     {
-        assert_eq!(participant_index, public_key.index,
-                   "Tried to add signer with participant index {}, but public key is for participant with index {}",
-                   participant_index, public_key.index);
+        if participant_index != public_key.index {
+            return Err("Public key does not belong to this participant");
+        }
+        if !self.state.partial_signatures.0.is_empty() {
+            return Err("Cannot include signers after partial signatures");
+        }
+        if let Some(public_key_package) = self.state.public_key_package.as_ref() {
+            match public_key_package.public_key(participant_index) {
+                Some(expected) if expected.share.to_bytes() == public_key.share.to_bytes() => (),
+                Some(_) => return Err("Public key does not match the public key package"),
+                None => return Err("Participant index out of range"),
+            }
+        }
 
         self.state.signers.push(Signer { participant_index, published_commitment_share });
         self.state.public_keys.insert(&public_key.index, public_key.share);
+
+        Ok(())
+    }
+
+    /// Include a signer in the protocol, looking up their public key in the
+    /// [`PublicKeyPackage`] this aggregator was constructed with.
+    ///
+    /// # Warning
+    ///
+    /// If this method is called for a specific participant, then that
+    /// participant MUST provide a partial signature to give to
+    /// [`SignatureAggregator.include_partial_signature`], otherwise the signing
+    /// procedure will fail.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the signer was
+    /// included, otherwise a string describing why they were not, e.g. because
+    /// their index is out of range or they were already included.
+    pub fn try_include_signer(
+        &mut self,
+        participant_index: u32,
+        published_commitment_share: (AffinePoint, AffinePoint),
+    ) -> Result<(), &'static str>
+    {
+        let share = self.check_new_signer(participant_index)?;
+
+        self.state.public_keys.insert(&participant_index, share);
+        self.state.signers.push(Signer { participant_index, published_commitment_share });
+
+        Ok(())
+    }
+
+    /// Check, without changing anything, that the participant with index
+    /// `participant_index` could be included with
+    /// [`SignatureAggregator::try_include_signer`].
+    ///
+    /// # Returns
+    ///
+    /// The public key share of the participant, otherwise a string describing
+    /// why they can't be included.
+    pub(crate) fn check_new_signer(&self, participant_index: u32) -> Result<AffinePoint, &'static str> {
+        let public_key_package = self.state.public_key_package.as_ref()
+            .ok_or("Aggregator was not constructed with a public key package")?;
+
+        if participant_index == 0 || participant_index > public_key_package.parameters.n {
+            return Err("Participant index out of range");
+        }
+
+        let public_key = public_key_package.public_key(participant_index).ok_or("Unknown participant index")?;
+
+        if self.state.signers.iter().any(|s| s.participant_index == participant_index) {
+            return Err("Signer was already included");
+        }
+        if !self.state.partial_signatures.0.is_empty() {
+            return Err("Cannot include signers after partial signatures");
+        }
+
+        Ok(public_key.share)
+    }
+
+    /// Check every included signer's published commitment share against a
+    /// `registry` of previously used ones, and record them as used.
+    ///
+    /// This must be called before the list of signers is handed out to the
+    /// signers, since signing twice with the same nonces reveals a signer's
+    /// secret key.  Calling it again is harmless: commitment shares which this
+    /// aggregator already consumed are not checked again, so only signers
+    /// included since the last call are.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if every commitment share
+    /// was fresh, otherwise a `Hashmap<u32, &'static str>` containing the
+    /// participant indices of the signers who advertised a used commitment
+    /// share and a description of their misbehaviour.  In the latter case
+    /// neither this aggregator nor the `registry` is changed, so that those
+    /// signers can be removed with [`SignatureAggregator::remove_signer`] (and
+    /// replaced, or included again with fresh commitment shares) before
+    /// calling this method again.
+    ///
+    /// If the `Hashmap` contains a key for `0`, this indicates that the
+    /// aggregator already accepted partial signatures, i.e. it is too late.
+    pub fn consume_commitments(
+        &mut self,
+        registry: &mut CommitmentRegistry,
+    ) -> Result<(), HashMap<u32, &'static str>>
+    {
+        let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+
+        if !self.state.partial_signatures.0.is_empty() {
+            misbehaving_participants.insert(0, "Cannot consume commitments after partial signatures");
+            return Err(misbehaving_participants);
+        }
+
+        let consumed = &self.state.consumed_commitments;
+        let fresh: Vec<Signer> = self.state.signers.iter()
+            .filter(|s| !consumed.iter().any(|c| same_signer_and_commitment(c, s)))
+            .cloned()
+            .collect();
+
+        // Check everything first, so that nothing is recorded unless every
+        // signer, including a signer who was included twice, is fresh.
+        let mut in_this_session: HashMap<u32, Vec<&AffinePoint>> = HashMap::new();
+
+        for signer in fresh.iter() {
+            let (hiding, binding) = &signer.published_commitment_share;
+            let seen = in_this_session.entry(signer.participant_index).or_default();
+
+            if hiding == binding {
+                misbehaving_participants.insert(signer.participant_index, "Hiding and binding commitments are identical");
+            } else if registry.is_used(signer.participant_index, &signer.published_commitment_share) ||
+                seen.contains(&hiding) || seen.contains(&binding)
+            {
+                misbehaving_participants.insert(signer.participant_index, "Nonce commitment was already used");
+            }
+            seen.push(hiding);
+            seen.push(binding);
+        }
+
+        if !misbehaving_participants.is_empty() {
+            return Err(misbehaving_participants);
+        }
+
+        for signer in fresh.iter() {
+            // This can't fail, since every signer was checked above.
+            registry.consume(signer.participant_index, &signer.published_commitment_share)
+                .expect("a fresh commitment share");
+        }
+
+        self.state.consumed_commitments.extend(fresh);
+
+        Ok(())
+    }
+
+    /// Remove a signer, e.g. one accused by
+    /// [`SignatureAggregator::consume_commitments`], from the protocol.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the signer was
+    /// removed, otherwise a string describing why they were not, e.g. because
+    /// partial signatures were already included, since removing a signer
+    /// would change what a valid partial signature is.
+    pub fn remove_signer(&mut self, participant_index: u32) -> Result<(), &'static str> {
+        if !self.state.partial_signatures.0.is_empty() {
+            return Err("Cannot remove signers after partial signatures");
+        }
+        if !self.state.signers.iter().any(|s| s.participant_index == participant_index) {
+            return Err("Not one of the signers");
+        }
+
+        self.state.signers.retain(|s| s.participant_index != participant_index);
+        self.state.public_keys.0.remove(&participant_index.to_be_bytes());
+
+        Ok(())
     }
 
     /// Get the list of partipating signers.
@@ -558,8 +1030,70 @@ impl SignatureAggregator<Initial> {
     }
 
     /// Add a [`PartialThresholdSignature`] to be included in the aggregation.
-    pub fn include_partial_signature(&mut self, partial_signature: PartialThresholdSignature) {
+    ///
+    /// The partial signature is verified immediately, so that a misbehaving
+    /// signer can be detected, and replaced, without waiting for everyone else.
+    ///
+    /// # Warning
+    ///
+    /// Every signer must be included, with
+    /// [`SignatureAggregator::include_signer`] or
+    /// [`SignatureAggregator::try_include_signer`], before any partial
+    /// signatures are, since the set of signers determines what a valid
+    /// partial signature is.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the partial signature
+    /// was included, otherwise a string describing why it was rejected, e.g.
+    /// because it is invalid, is from an unexpected signer, or is a duplicate.
+    pub fn include_partial_signature(&mut self, partial_signature: PartialThresholdSignature) -> Result<(), &'static str> {
+        self.expect_no_adaptor()?;
+
+        let public_key = self.expected_partial_signer(&partial_signature)?;
+        let message_hash = self.aggregator.message_hash;
+        let signers = self.get_signers().clone();
+
+        partial_signature.verify(&self.state.group_key, &message_hash, &signers, &public_key)?;
+
         self.state.partial_signatures.insert(&partial_signature.index, partial_signature.z);
+
+        Ok(())
+    }
+
+    /// Check that this aggregator was not set up for an adaptor signature, so
+    /// that it may take ordinary partial signatures.
+    pub(crate) fn expect_no_adaptor(&self) -> Result<(), &'static str> {
+        match self.state.adaptor {
+            Some(_) => Err("Aggregator is set up for an adaptor signature"),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that a `partial_signature` is from one of our signers, who has not
+    /// already contributed one.
+    ///
+    /// # Returns
+    ///
+    /// The [`IndividualPublicKey`] of the signer, otherwise a string describing
+    /// why we shouldn't accept their partial signature.
+    pub(crate) fn expected_partial_signer(
+        &self,
+        partial_signature: &PartialThresholdSignature,
+    ) -> Result<IndividualPublicKey, &'static str>
+    {
+        let index = partial_signature.index;
+
+        if !self.state.signers.iter().any(|s| s.participant_index == index) {
+            return Err("Partial signature is from an unexpected signer");
+        }
+        if self.state.partial_signatures.get(&index).is_some() {
+            return Err("Duplicate partial signature");
+        }
+
+        let share = *self.state.public_keys.get(&index).ok_or("Missing public key")?;
+
+        Ok(IndividualPublicKey { index, share })
     }
 
     /// Ensure that this signature aggregator is in a proper state to run the aggregation protocol.
@@ -572,7 +1106,8 @@ impl SignatureAggregator<Initial> {
     ///
     /// If the `Hashmap` contains a key for `0`, this indicates that
     /// the aggregator did not have \(( t' \)) partial signers
-    /// s.t. \(( t \le t' \le n \)).
+    /// s.t. \(( t \le t' \le n \)), or included a signer whose index is
+    /// not in \(( 1..=n \)).
     pub fn finalize(mut self) -> Result<SignatureAggregator<Finalized>, HashMap<u32, &'static str>> {
         let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
         let remaining_signers = self.get_remaining_signers();
@@ -592,6 +1127,16 @@ impl SignatureAggregator<Initial> {
         // Ensure that our new state is ordered and deduplicated.
         self.state.signers = self.get_signers().clone();
 
+        if self.state.signers.len() < self.state.parameters.t as usize {
+            misbehaving_participants.insert(0, "Not enough signers to meet the threshold");
+        }
+
+        for signer in self.state.signers.iter() {
+            if signer.participant_index == 0 || signer.participant_index > self.state.parameters.n {
+                misbehaving_participants.insert(0, "Signer index out of range");
+            }
+        }
+
         for signer in self.state.signers.iter() {
             if self.state.public_keys.get(&signer.participant_index).is_none() {
                 // XXX These should be Vec<&'static str> for full error reporting
@@ -603,14 +1148,25 @@ impl SignatureAggregator<Initial> {
             return Err(misbehaving_participants);
         }
 
-        let message_hash = compute_message_hash(&self.aggregator.context, &self.aggregator.message);
+        let message_hash = self.aggregator.message_hash;
+        let hashing_mode = self.aggregator.hashing_mode;
 
-        Ok(SignatureAggregator { state: self.state, aggregator: Finalized { message_hash } })
+        Ok(SignatureAggregator { state: self.state, aggregator: Finalized { message_hash, hashing_mode } })
     }
 }
 
 #[cfg(feature = "std")]
 impl SignatureAggregator<Finalized> {
+    /// The digest which was signed.
+    pub fn message_hash(&self) -> &[u8; 32] {
+        &self.aggregator.message_hash
+    }
+
+    /// How the [`SignatureAggregator::message_hash`] was obtained.
+    pub fn hashing_mode(&self) -> HashingMode {
+        self.aggregator.hashing_mode
+    }
+
     /// Aggregate a set of previously-collected partial signatures.
     ///
     /// # Returns
@@ -618,9 +1174,21 @@ impl SignatureAggregator<Finalized> {
     /// A Result whose Ok() value is a [`ThresholdSignature`], otherwise a
     /// `Hashmap<u32, &'static str>` containing the participant indices of the misbehaving
     /// signers and a description of their misbehaviour.
+    ///
+    /// If the `Hashmap` contains a key for `0`, this indicates that every
+    /// partial signature was valid, but the aggregated signature would be
+    /// rejected by [`ThresholdSignature::verify`], or that the aggregator was
+    /// set up for an adaptor signature.  To also check it against the
+    /// EthSchnorr contract, use
+    /// [`SignatureAggregator::aggregate_for_ethschnorr`] instead.
     pub fn aggregate(&self) -> Result<ThresholdSignature, HashMap<u32, &'static str>> {
         let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
-        
+
+        if self.state.adaptor.is_some() {
+            misbehaving_participants.insert(0, "Aggregator is set up for an adaptor signature");
+            return Err(misbehaving_participants);
+        }
+
         let (_, Rs) = compute_binding_factors_and_group_commitment(&self.aggregator.message_hash, &self.state.signers);
         let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
         let Raff = R.to_affine();
@@ -650,14 +1218,11 @@ impl SignatureAggregator<Finalized> {
             // Again, this unwrap() cannot fail, because of the checks in finalize().
             let Y_i = self.state.public_keys.get(&signer.participant_index).unwrap();
 
-            let check = AffinePoint::GENERATOR * partial_sig;
-
             // Again, this unwrap() cannot fail, because we check the
             // participant indexes against the expected ones in finalize().
             let R_i = Rs.get(&signer.participant_index).unwrap();
 
-            // [DIFFERENT_TO_PAPER] c * lambda is positive in the paper
-            if check == (*Y_i * (-c * lambda)) + R_i {
+            if partial_signature_is_valid(partial_sig, Y_i, R_i, &c, &lambda) {
                 z += partial_sig;
             } else {
                 // XXX We don't really need the error string anymore, since there's only one failure mode.
@@ -665,9 +1230,89 @@ impl SignatureAggregator<Finalized> {
             }
         }
 
+        if ! misbehaving_participants.is_empty() {
+            return Err(misbehaving_participants);
+        }
+
+        let signature = ThresholdSignature { z, R: Raff };
+
+        // Every partial signature was valid, so the signature should be too,
+        // unless the aggregator (participant 0) made a mistake.  In any case,
+        // check it exactly as a verifier will.
+        if Raff == AffinePoint::IDENTITY {
+            misbehaving_participants.insert(0, "Group commitment is the identity");
+        } else if signature.verify(&self.state.group_key, &self.aggregator.message_hash).is_err() {
+            misbehaving_participants.insert(0, "Aggregated signature does not verify");
+        }
+
         match ! misbehaving_participants.is_empty() {
             true => Err(misbehaving_participants),
-            false => Ok(ThresholdSignature {z, R: Raff}),
+            false => Ok(signature),
+        }
+    }
+}
+
+/// Check that a signer's partial signature \\(z\_i\\) is consistent with their
+/// public key share \\(Y\_i\\) and their commitment \\(R\_i\\), i.e. that
+/// \\(z\_i G + c \lambda\_i Y\_i = R\_i\\).
+#[cfg(feature = "std")]
+pub(crate) fn partial_signature_is_valid(
+    z: &Scalar,
+    Y_i: &AffinePoint,
+    R_i: &AffinePoint,
+    challenge: &Scalar,
+    lambda: &Scalar,
+) -> bool
+{
+    // [DIFFERENT_TO_PAPER] c * lambda is positive in the paper
+    let check = ProjectivePoint::lincomb(&ProjectivePoint::GENERATOR, z, &(*Y_i).into(), &(*challenge * lambda));
+
+    check.to_affine().to_bytes() == R_i.to_bytes()
+}
+
+#[cfg(feature = "std")]
+impl PartialThresholdSignature {
+    /// Verify this [`PartialThresholdSignature`] independently of any
+    /// [`SignatureAggregator`].
+    ///
+    /// This allows anyone who sees a partial signature, e.g. a relay or another
+    /// signer, to check it and attribute it to its signer.
+    ///
+    /// # Inputs
+    ///
+    /// * The public [`GroupKey`] for this group of signing participants,
+    /// * The `message_hash` which was signed,
+    /// * The list of all the participating [`Signer`]s, exactly as it was
+    ///   passed to [`SecretKey::sign`], i.e. as returned by
+    ///   [`SignatureAggregator::get_signers`], and
+    /// * The [`IndividualPublicKey`] of the signer who made this partial signature.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the partial signature
+    /// is valid, otherwise a string describing why it is not.
+    pub fn verify(
+        &self,
+        group_key: &GroupKey,
+        message_hash: &[u8; 32],
+        signers: &[Signer],
+        public_key: &IndividualPublicKey,
+    ) -> Result<(), &'static str>
+    {
+        if public_key.index != self.index {
+            return Err("Public key does not belong to the signer of this partial signature");
+        }
+
+        let (_, Rs) = compute_binding_factors_and_group_commitment(message_hash, signers);
+        let R_i = Rs.get(&self.index).ok_or("Partial signature is not from one of the signers")?;
+        let R: ProjectivePoint = Rs.values().fold(ProjectivePoint::IDENTITY, |acc, x| acc + x);
+        let c = compute_challenge(message_hash, group_key, &R.to_affine());
+        let all_participant_indices: Vec<u32> = signers.iter().map(|x| x.participant_index).collect();
+        let lambda = calculate_lagrange_coefficients(&self.index, &all_participant_indices)?;
+
+        match partial_signature_is_valid(&self.z, &public_key.share, R_i, &c, &lambda) {
+            true => Ok(()),
+            false => Err("Incorrect partial signature"),
         }
     }
 }
@@ -692,11 +1337,152 @@ impl ThresholdSignature {
     }
 }
 
+/// Generate a random, 128-bit coefficient for a random linear combination in
+/// batch verification.
+#[cfg(feature = "std")]
+pub(crate) fn random_batch_coefficient(csprng: &mut (impl CryptoRng + Rng)) -> Scalar {
+    let mut bytes = [0u8; 32];
+
+    csprng.fill_bytes(&mut bytes[16..]);
+
+    // A 128-bit integer is always less than the group order.
+    Scalar::from_repr(FieldBytes::from(bytes)).unwrap()
+}
+
+/// Compute \\(\sum\_i s\_i P\_i\\) with Pippenger's bucket method, where every
+/// scalar \\(s\_i\\) is known to be less than \\(2^{\text{bits}}\\).
+#[cfg(feature = "std")]
+pub(crate) fn multiscalar_mul(scalars: &[Scalar], points: &[AffinePoint], bits: usize) -> ProjectivePoint {
+    debug_assert_eq!(scalars.len(), points.len());
+
+    // For only a few points, the bookkeeping isn't worth it.
+    if points.len() < 8 {
+        return scalars.iter().zip(points.iter()).fold(ProjectivePoint::IDENTITY, |acc, (s, P)| acc + (*P * s));
+    }
+
+    // The window size is roughly log_2(n) - 2, which minimises
+    // (bits / c) * (n + 2^{c+1}) additions.
+    let log_n = (usize::BITS - points.len().leading_zeros()) as usize;
+    let c = log_n.saturating_sub(2).clamp(2, 12);
+    let windows = bits.div_ceil(c);
+
+    // Little-endian scalars make it easier to index their bits.
+    let little_endian: Vec<[u8; 32]> = scalars.iter().map(|s| {
+        let mut bytes: [u8; 32] = s.to_bytes().into();
+        bytes.reverse();
+        bytes
+    }).collect();
+    let digit = |bytes: &[u8; 32], window: usize| -> usize {
+        let mut d = 0usize;
+
+        for bit in (window * c..core::cmp::min((window + 1) * c, 256)).rev() {
+            d = (d << 1) | ((bytes[bit / 8] >> (bit % 8)) & 1) as usize;
+        }
+        d
+    };
+
+    let mut result = ProjectivePoint::IDENTITY;
+    let mut buckets: Vec<ProjectivePoint> = Vec::with_capacity((1 << c) - 1);
+
+    for window in (0..windows).rev() {
+        for _ in 0..c {
+            result = result.double();
+        }
+
+        buckets.clear();
+        buckets.resize((1 << c) - 1, ProjectivePoint::IDENTITY);
+
+        for (bytes, P) in little_endian.iter().zip(points.iter()) {
+            let d = digit(bytes, window);
+
+            if d != 0 {
+                buckets[d - 1] += P;
+            }
+        }
+
+        // Sum the buckets, weighting each by its digit, with a running sum.
+        let mut running = ProjectivePoint::IDENTITY;
+        let mut sum = ProjectivePoint::IDENTITY;
+
+        for bucket in buckets.iter().rev() {
+            running += bucket;
+            sum += running;
+        }
+        result += sum;
+    }
+    result
+}
+
+/// Verify many [`ThresholdSignature`]s at once.
+///
+/// Rather than checking \\(R\_i = c\_i Y\_i + z\_i G\\) for each signature
+/// separately, this picks random 128-bit \\(a\_i\\) and checks the single
+/// combined equation
+/// \\(\sum\_i a\_i R\_i = \sum\_Y (\sum\_{i : Y\_i = Y} a\_i c\_i) Y + (\sum\_i a\_i z\_i) G\\)
+/// with one multiscalar multiplication, which is considerably faster when
+/// there are many signatures, particularly when they share a [`GroupKey`].
+///
+/// # Inputs
+///
+/// * A cryptographically secure random number generator, and
+/// * The `(signature, message_hash, group_key)` triples to verify.
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is an empty tuple if every signature is valid.
+/// Otherwise, each signature is then checked individually, and the `Err`
+/// value contains the positions in `signatures` of those which are invalid.
+#[cfg(feature = "std")]
+pub fn verify_batch(
+    mut csprng: impl CryptoRng + Rng,
+    signatures: &[(&ThresholdSignature, &[u8; 32], &GroupKey)],
+) -> Result<(), Vec<usize>>
+{
+    let mut coefficients: Vec<Scalar> = Vec::with_capacity(signatures.len());
+    let mut Rs: Vec<AffinePoint> = Vec::with_capacity(signatures.len());
+    let mut key_coefficients: HashMap<CompressedPoint, (AffinePoint, Scalar)> = HashMap::new();
+    let mut generator_coefficient = Scalar::ZERO;
+
+    for (signature, message_hash, group_key) in signatures.iter() {
+        let a = random_batch_coefficient(&mut csprng);
+        let c = compute_challenge(message_hash, group_key, &signature.R);
+
+        coefficients.push(a);
+        Rs.push(signature.R);
+
+        key_coefficients.entry(group_key.to_bytes()).or_insert((group_key.0, Scalar::ZERO)).1 += a * c;
+        generator_coefficient += a * signature.z;
+    }
+
+    let mut scalars: Vec<Scalar> = Vec::with_capacity(key_coefficients.len() + 1);
+    let mut points: Vec<AffinePoint> = Vec::with_capacity(key_coefficients.len() + 1);
+
+    for (key, coefficient) in key_coefficients.values() {
+        scalars.push(-coefficient);
+        points.push(*key);
+    }
+    scalars.push(-generator_coefficient);
+    points.push(AffinePoint::GENERATOR);
+
+    let check = multiscalar_mul(&coefficients, &Rs, 128) + multiscalar_mul(&scalars, &points, 256);
+
+    if check == ProjectivePoint::IDENTITY {
+        return Ok(());
+    }
+
+    Err(signatures.iter()
+        .enumerate()
+        .filter(|(_, (signature, message_hash, group_key))| signature.verify(group_key, message_hash).is_err())
+        .map(|(i, _)| i)
+        .collect())
+}
+
 #[cfg(feature = "std")]
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use crate::keygen::dealt_group;
     use crate::keygen::Participant;
     use crate::keygen::{DistributedKeyGeneration, RoundOne};
     use crate::precomputation::generate_commitment_share_lists;
@@ -731,14 +1517,14 @@ mod test {
 
         let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
 
         let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
 
-        aggregator.include_partial_signature(p1_partial);
+        aggregator.include_partial_signature(p1_partial).unwrap();
 
         let aggregator = aggregator.finalize().unwrap();
         let signing_result = aggregator.aggregate();
@@ -775,14 +1561,14 @@ mod test {
 
         let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
 
         let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
 
-        aggregator.include_partial_signature(p1_partial);
+        aggregator.include_partial_signature(p1_partial).unwrap();
 
         let aggregator = aggregator.finalize().unwrap();
         let threshold_signature = aggregator.aggregate().unwrap();
@@ -827,14 +1613,14 @@ mod test {
 
         let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
 
         let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
 
-        aggregator.include_partial_signature(p1_partial);
+        aggregator.include_partial_signature(p1_partial).unwrap();
 
         let aggregator = aggregator.finalize().unwrap();
         let threshold_signature = aggregator.aggregate().unwrap();
@@ -988,8 +1774,8 @@ mod test {
 
         let mut aggregator = SignatureAggregator::new(params, group_key.clone(), context.to_vec(), message.to_vec());
 
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into());
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
         let message_hash = compute_message_hash(&context[..], &message[..]);
@@ -997,8 +1783,8 @@ mod test {
         let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
         let p2_partial = p2_sk.sign(&message_hash, &group_key, &mut p2_secret_comshares, 0, signers).unwrap();
 
-        aggregator.include_partial_signature(p1_partial);
-        aggregator.include_partial_signature(p2_partial);
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p2_partial).unwrap();
 
         let aggregator = aggregator.finalize().unwrap();
         let signing_result = aggregator.aggregate();
@@ -1013,6 +1799,385 @@ mod test {
         assert!(verification_result.is_ok());
     }
 
+    #[test]
+    fn partial_signature_verification() {
+        let params = Parameters { n: 3, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let p1_sk = participants[0].secret_key();
+        let p2_sk = participants[1].secret_key();
+        let p1_pk: IndividualPublicKey = (&p1_sk).into();
+        let p2_pk: IndividualPublicKey = (&p2_sk).into();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
+
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into()).unwrap();
+
+        let signers = aggregator.get_signers().clone();
+        let message_hash = compute_message_hash(&context[..], &message[..]);
+
+        let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, &signers).unwrap();
+        let p2_partial = p2_sk.sign(&message_hash, &group_key, &mut p2_secret_comshares, 0, &signers).unwrap();
+
+        assert!(p1_partial.verify(&group_key, &message_hash, &signers, &p1_pk).is_ok());
+        assert!(p2_partial.verify(&group_key, &message_hash, &signers, &p2_pk).is_ok());
+
+        // The wrong public key, message, or signer set must be rejected.
+        assert!(p1_partial.verify(&group_key, &message_hash, &signers, &p2_pk).is_err());
+        assert!(p1_partial.verify(&group_key, &[0u8; 32], &signers, &p1_pk).is_err());
+        assert!(p1_partial.verify(&group_key, &message_hash, &signers[..1], &p1_pk).is_err());
+        assert!(p2_partial.verify(&group_key, &message_hash, &signers[..1], &p2_pk).is_err());
+
+        // As must a tampered partial signature.
+        let forged = PartialThresholdSignature { index: 1, z: p1_partial.z + Scalar::ONE };
+
+        assert_eq!(forged.verify(&group_key, &message_hash, &signers, &p1_pk), Err("Incorrect partial signature"));
+
+        // Whereas the aggregator should agree with the standalone checks.
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p2_partial).unwrap();
+
+        let aggregator = aggregator.finalize().unwrap();
+
+        assert!(aggregator.aggregate().is_ok());
+    }
+
+    /// Have a dealt 2-out-of-3 group make `count` signatures on different messages.
+    fn dealer_signatures(count: usize) -> (GroupKey, Vec<(ThresholdSignature, [u8; 32])>) {
+        let params = Parameters { n: 3, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let p1_sk = participants[0].secret_key();
+        let p3_sk = participants[2].secret_key();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let mut signatures = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let message = format!("Bridge transfer number {}", i).into_bytes();
+            let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+            let (p3_public_comshares, mut p3_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 3, 1);
+
+            let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.clone());
+
+            aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+            aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into()).unwrap();
+
+            let signers = aggregator.get_signers();
+            let message_hash = compute_message_hash(&context[..], &message[..]);
+
+            let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
+            let p3_partial = p3_sk.sign(&message_hash, &group_key, &mut p3_secret_comshares, 0, signers).unwrap();
+
+            aggregator.include_partial_signature(p1_partial).unwrap();
+            aggregator.include_partial_signature(p3_partial).unwrap();
+
+            let aggregator = aggregator.finalize().unwrap();
+
+            signatures.push((aggregator.aggregate().unwrap(), message_hash));
+        }
+        (group_key, signatures)
+    }
+
+    #[test]
+    fn multiscalar_mul_matches_naive() {
+        for n in [0usize, 1, 7, 8, 33, 100] {
+            let scalars: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut OsRng)).collect();
+            let points: Vec<AffinePoint> = (0..n).map(|_| (ProjectivePoint::GENERATOR * Scalar::random(&mut OsRng)).to_affine()).collect();
+            let naive = scalars.iter().zip(points.iter()).fold(ProjectivePoint::IDENTITY, |acc, (s, P)| acc + (*P * s));
+
+            assert_eq!(multiscalar_mul(&scalars, &points, 256), naive);
+
+            let short: Vec<Scalar> = (0..n).map(|_| random_batch_coefficient(&mut OsRng)).collect();
+            let naive = short.iter().zip(points.iter()).fold(ProjectivePoint::IDENTITY, |acc, (s, P)| acc + (*P * s));
+
+            assert_eq!(multiscalar_mul(&short, &points, 128), naive);
+        }
+    }
+
+    #[test]
+    fn batch_verification() {
+        let (group_key_1, signatures_1) = dealer_signatures(6);
+        let (group_key_2, signatures_2) = dealer_signatures(4);
+
+        let mut batch: Vec<(&ThresholdSignature, &[u8; 32], &GroupKey)> = Vec::new();
+
+        for (signature, message_hash) in signatures_1.iter() {
+            batch.push((signature, message_hash, &group_key_1));
+        }
+        for (signature, message_hash) in signatures_2.iter() {
+            batch.push((signature, message_hash, &group_key_2));
+        }
+
+        assert!(verify_batch(&mut OsRng, &[]).is_ok());
+        assert!(verify_batch(&mut OsRng, &batch).is_ok());
+
+        // Tamper with one signature, and use the wrong key for another.
+        let forged = ThresholdSignature { R: signatures_1[2].0.R, z: signatures_1[2].0.z + Scalar::ONE };
+
+        batch[2].0 = &forged;
+        batch[7].2 = &group_key_1;
+
+        assert_eq!(verify_batch(&mut OsRng, &batch), Err(vec![2, 7]));
+    }
+
+    #[test]
+    fn aggregator_from_public_key_package() {
+        let params = Parameters { n: 3, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let p1_sk = participants[0].secret_key();
+        let p2_sk = participants[1].secret_key();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        // An aggregator without a package can't look up anyone's keys.
+        let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
+
+        assert!(aggregator.try_include_signer(1, p1_public_comshares.commitments[0]).is_err());
+
+        let mut aggregator = SignatureAggregator::from_public_key_package(package.clone(), context.to_vec(), message.to_vec());
+
+        assert_eq!(aggregator.try_include_signer(0, p1_public_comshares.commitments[0]),
+                   Err("Participant index out of range"));
+        assert_eq!(aggregator.try_include_signer(4, p1_public_comshares.commitments[0]),
+                   Err("Participant index out of range"));
+        assert!(aggregator.try_include_signer(1, p1_public_comshares.commitments[0]).is_ok());
+        assert_eq!(aggregator.try_include_signer(1, p1_public_comshares.commitments[0]),
+                   Err("Signer was already included"));
+
+        // Nor can the package be bypassed by supplying some other key.
+        let p3_public_key = participants[2].public_key.clone();
+        let mut rogue_key = p3_public_key.clone();
+
+        rogue_key.share = participants[0].public_key.share;
+
+        assert_eq!(aggregator.include_signer(3, p2_public_comshares.commitments[0], rogue_key),
+                   Err("Public key does not match the public key package"));
+        assert_eq!(aggregator.include_signer(2, p2_public_comshares.commitments[0], p3_public_key),
+                   Err("Public key does not belong to this participant"));
+
+        let signers = aggregator.get_signers().clone();
+        let message_hash = compute_message_hash(&context[..], &message[..]);
+        let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, &signers).unwrap();
+
+        aggregator.include_partial_signature(p1_partial).unwrap();
+
+        // One signer is below the threshold.
+        let errors = aggregator.finalize().unwrap_err();
+
+        assert_eq!(errors.get(&0), Some(&"Not enough signers to meet the threshold"));
+
+        // Whereas two signers suffice.
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let mut aggregator = SignatureAggregator::from_public_key_package(package, context.to_vec(), message.to_vec());
+
+        aggregator.try_include_signer(1, p1_public_comshares.commitments[0]).unwrap();
+        aggregator.try_include_signer(2, p2_public_comshares.commitments[0]).unwrap();
+
+        let signers = aggregator.get_signers().clone();
+        let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, &signers).unwrap();
+        let p2_partial = p2_sk.sign(&message_hash, &group_key, &mut p2_secret_comshares, 0, &signers).unwrap();
+
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p2_partial).unwrap();
+
+        let aggregator = aggregator.finalize().unwrap();
+        let threshold_signature = aggregator.aggregate().unwrap();
+
+        assert!(threshold_signature.verify(&group_key, &message_hash).is_ok());
+    }
+
+    #[test]
+    fn include_partial_signature_verifies_eagerly() {
+        let params = Parameters { n: 3, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let p1_sk = participants[0].secret_key();
+        let p2_sk = participants[1].secret_key();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+        let (p3_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 3, 1);
+
+        let mut aggregator = SignatureAggregator::from_public_key_package(package, context.to_vec(), message.to_vec());
+
+        aggregator.try_include_signer(1, p1_public_comshares.commitments[0]).unwrap();
+        aggregator.try_include_signer(2, p2_public_comshares.commitments[0]).unwrap();
+
+        let signers = aggregator.get_signers().clone();
+        let message_hash = compute_message_hash(&context[..], &message[..]);
+
+        let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, &signers).unwrap();
+        let p2_partial = p2_sk.sign(&message_hash, &group_key, &mut p2_secret_comshares, 0, &signers).unwrap();
+
+        let unexpected = PartialThresholdSignature { index: 3, z: Scalar::random(&mut OsRng) };
+        let forged = PartialThresholdSignature { index: 1, z: p1_partial.z + Scalar::ONE };
+        let duplicate = PartialThresholdSignature { index: 1, z: p1_partial.z };
+
+        assert_eq!(aggregator.include_partial_signature(unexpected), Err("Partial signature is from an unexpected signer"));
+        assert_eq!(aggregator.include_partial_signature(forged), Err("Incorrect partial signature"));
+        assert_eq!(aggregator.include_partial_signature(p1_partial), Ok(()));
+        assert_eq!(aggregator.include_partial_signature(duplicate), Err("Duplicate partial signature"));
+
+        // The set of signers is fixed once partial signatures start arriving.
+        assert!(aggregator.try_include_signer(3, p3_public_comshares.commitments[0]).is_err());
+        assert_eq!(aggregator.include_signer(3, p3_public_comshares.commitments[0], participants[2].public_key.clone()),
+                   Err("Cannot include signers after partial signatures"));
+
+        // Only participant 2 is still outstanding.
+        let remaining: Vec<u32> = aggregator.get_remaining_signers().iter().map(|s| s.participant_index).collect();
+
+        assert_eq!(remaining, vec![2]);
+        assert_eq!(aggregator.include_partial_signature(p2_partial), Ok(()));
+        assert!(aggregator.finalize().unwrap().aggregate().is_ok());
+    }
+
+    #[test]
+    fn aggregator_refuses_reused_commitments() {
+        let params = Parameters { n: 3, t: 2 };
+        let (_, package) = dealt_group(params);
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let (p1_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 1, 2);
+        let (p2_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+        let (p3_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 3, 1);
+        let mut registry = CommitmentRegistry::new();
+
+        let mut aggregator = SignatureAggregator::from_public_key_package(package.clone(), context.to_vec(), message.to_vec());
+
+        aggregator.try_include_signer(1, p1_public_comshares.commitments[0]).unwrap();
+        aggregator.try_include_signer(2, p2_public_comshares.commitments[0]).unwrap();
+
+        assert!(aggregator.consume_commitments(&mut registry).is_ok());
+
+        // In the next session, participant 2 re-advertises their old commitment.
+        let mut aggregator = SignatureAggregator::from_public_key_package(package.clone(), context.to_vec(), message.to_vec());
+
+        aggregator.try_include_signer(1, p1_public_comshares.commitments[1]).unwrap();
+        aggregator.try_include_signer(2, p2_public_comshares.commitments[0]).unwrap();
+
+        let misbehaving_participants = aggregator.consume_commitments(&mut registry).unwrap_err();
+
+        assert_eq!(misbehaving_participants.len(), 1);
+        assert_eq!(misbehaving_participants.get(&2), Some(&"Nonce commitment was already used"));
+        assert_eq!(aggregator.get_signers().len(), 2);
+        assert!(!registry.is_used(1, &p1_public_comshares.commitments[1]));
+
+        // Participant 2 may be removed, and replaced by participant 3.
+        aggregator.remove_signer(2).unwrap();
+        aggregator.try_include_signer(3, p3_public_comshares.commitments[0]).unwrap();
+
+        assert!(aggregator.consume_commitments(&mut registry).is_ok());
+
+        // Consuming again doesn't accuse anyone of reusing what we just consumed.
+        assert!(aggregator.consume_commitments(&mut registry).is_ok());
+
+        let signers: Vec<u32> = aggregator.get_signers().iter().map(|s| s.participant_index).collect();
+
+        assert_eq!(signers, vec![1, 3]);
+        assert!(registry.is_used(1, &p1_public_comshares.commitments[1]));
+        assert!(registry.is_used(3, &p3_public_comshares.commitments[0]));
+
+        // A signer listed twice in one session with the same commitment is
+        // refused, and nothing is recorded for anyone.
+        let (p1_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p3_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 3, 1);
+        let mut aggregator = SignatureAggregator::from_public_key_package(package.clone(), context.to_vec(), message.to_vec());
+
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], package.public_key(1).unwrap().clone()).unwrap();
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], package.public_key(1).unwrap().clone()).unwrap();
+        aggregator.try_include_signer(3, p3_public_comshares.commitments[0]).unwrap();
+
+        let misbehaving_participants = aggregator.consume_commitments(&mut registry).unwrap_err();
+
+        assert_eq!(misbehaving_participants.get(&1), Some(&"Nonce commitment was already used"));
+        assert!(!registry.is_used(1, &p1_public_comshares.commitments[0]));
+        assert!(!registry.is_used(3, &p3_public_comshares.commitments[0]));
+    }
+
+    #[test]
+    fn signing_prehashed_message() {
+        let params = Parameters { n: 3, t: 2 };
+        let (participants, package) = dealt_group(params);
+        let group_key = *package.group_key();
+
+        let p1_sk = participants[0].secret_key();
+        let p2_sk = participants[1].secret_key();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
+
+        assert_eq!(aggregator.hashing_mode(), HashingMode::ContextAndMessage);
+        assert_eq!(aggregator.message_hash(), &compute_message_hash(&context[..], &message[..]));
+
+        // Signers are sent the mode along with the message, and recompute the digest.
+        let mode: HashingMode = bincode::deserialize(&bincode::serialize(&aggregator.hashing_mode()).unwrap()).unwrap();
+
+        assert_eq!(mode.message_hash(&context[..], &message[..]), Ok(*aggregator.message_hash()));
+
+        // A digest computed elsewhere is signed as is.
+        let message_hash: [u8; 32] = Keccak256::digest(&message[..]).into();
+        let (p1_public_comshares, _) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+
+        let mut aggregator = SignatureAggregator::from_public_key_package_prehashed(package, message_hash);
+
+        assert_eq!(aggregator.hashing_mode(), HashingMode::Prehashed);
+        assert_eq!(aggregator.message_hash(), &message_hash);
+        assert!(aggregator.message().is_empty());
+        assert!(aggregator.hashing_mode().message_hash(aggregator.context(), aggregator.message()).is_err());
+
+        aggregator.try_include_signer(1, p1_public_comshares.commitments[0]).unwrap();
+        aggregator.try_include_signer(2, p2_public_comshares.commitments[0]).unwrap();
+
+        let signers = aggregator.get_signers().clone();
+        let digest = *aggregator.message_hash();
+
+        // A signer who hashed the message differently is caught.
+        let confused_hash = compute_message_hash(&context[..], &message[..]);
+        let confused = p2_sk.sign(&confused_hash, &group_key, &mut p2_secret_comshares, 0, &signers);
+
+        assert_eq!(aggregator.include_partial_signature(confused.unwrap()), Err("Incorrect partial signature"));
+
+        // Since participant 2's nonce was used, everyone has to start over.
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public_comshares, mut p2_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+        let mut aggregator = SignatureAggregator::new_prehashed(params, group_key, digest);
+
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into()).unwrap();
+
+        let signers = aggregator.get_signers().clone();
+        let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, &signers).unwrap();
+        let p2_partial = p2_sk.sign(&message_hash, &group_key, &mut p2_secret_comshares, 0, &signers).unwrap();
+
+        aggregator.include_partial_signature(p1_partial).unwrap();
+        aggregator.include_partial_signature(p2_partial).unwrap();
+
+        let aggregator = aggregator.finalize().unwrap();
+
+        assert_eq!(aggregator.hashing_mode(), HashingMode::Prehashed);
+        assert!(aggregator.aggregate().unwrap().verify(&group_key, &message_hash).is_ok());
+    }
+
     #[test]
     fn aggregator_get_signers() {
         let params = Parameters { n: 3, t: 2 };
@@ -1027,9 +2192,9 @@ mod test {
         let p1_sk = SecretKey{ index: 1, key: Scalar::random(&mut OsRng) };
         let p2_sk = SecretKey{ index: 2, key: Scalar::random(&mut OsRng) };
 
-        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into());
-        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into());
+        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into()).unwrap();
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(2, p2_public_comshares.commitments[0], (&p2_sk).into()).unwrap();
 
         let signers = aggregator.get_signers();
 
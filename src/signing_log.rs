@@ -0,0 +1,446 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! A signer's append-only log of the partial signatures they have issued.
+//!
+//! Each [`LogEntry`] commits to the one before it, so that the log forms a
+//! hash chain which can be checked with [`SigningLog::audit`].  Signing through
+//! [`SecretKey::sign_and_log`] consults the log first, and refuses to:
+//!
+//! * sign a different message for a bridge nonce which was already signed for,
+//! * sign a message again for a different set of signers, unless the message
+//!   was explicitly allowed with [`SigningLog::allow_resigning`],
+//! * sign with a nonce commitment which was already used, even after the
+//!   signer's [`SecretCommitmentShareList`] was restored from a backup.
+//!
+//! Permissions to sign again are recorded in the hash chain too, so that they
+//! can't be added behind the signer's back either.
+
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::Scalar;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use sha3::Digest;
+use sha3::Keccak256;
+
+use crate::keygen::GroupKey;
+use crate::keygen::SecretKey;
+use crate::precomputation::SecretCommitmentShareList;
+use crate::signature::PartialThresholdSignature;
+use crate::signature::Signer;
+
+/// What happened to a message, as recorded by a [`LogEntry`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum LogEvent {
+    /// A [`PartialThresholdSignature`] was issued on the message.
+    Signed {
+        /// The aggregator's identifier for the signing session.
+        session_id: Vec<u8>,
+        /// The bridge nonce of the message, if it has one.
+        nonce: Option<u64>,
+        /// The sorted participant indices of the signers.
+        signers: Vec<u32>,
+        /// Our published commitment share which was used.
+        commitment: (AffinePoint, AffinePoint),
+        /// The partial signature which was issued.
+        z: Scalar,
+    },
+    /// The message may be signed again by different sets of signers, as
+    /// allowed with [`SigningLog::allow_resigning`].
+    ResigningAllowed,
+}
+
+/// A record of one [`PartialThresholdSignature`] issued by a signer, or of a
+/// permission to sign a message again.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// The hash of the previous entry, or all zeroes for the first one.
+    pub previous: [u8; 32],
+    /// The message hash which this entry is about.
+    pub message_hash: [u8; 32],
+    /// What happened to the message.
+    pub event: LogEvent,
+    /// The hash of this entry, which the next one commits to.
+    pub hash: [u8; 32],
+}
+
+impl LogEntry {
+    /// Compute the hash of this entry from all its other fields.
+    fn compute_hash(&self) -> [u8; 32] {
+        let contents = (&self.previous, &self.message_hash, &self.event);
+        let mut h = Keccak256::default();
+
+        h.update(b"FROST-SIGNING-LOG");
+        h.update(bincode::serialize(&contents).expect("serialisable log entry contents"));
+        h.finalize().into()
+    }
+}
+
+/// A hash-chained log of every [`PartialThresholdSignature`] issued by a
+/// signer.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SigningLog {
+    /// The entries of the log, oldest first.
+    entries: Vec<LogEntry>,
+}
+
+impl SigningLog {
+    /// Create a new, empty [`SigningLog`].
+    pub fn new() -> SigningLog {
+        SigningLog::default()
+    }
+
+    /// Get the entries of this log, oldest first.
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    /// Get the hash of the latest entry, or all zeroes if the log is empty.
+    ///
+    /// Publishing the head from time to time, e.g. to the other signers, stops
+    /// the log from being silently rewritten afterwards.
+    pub fn head(&self) -> [u8; 32] {
+        self.entries.last().map_or([0u8; 32], |entry| entry.hash)
+    }
+
+    /// Allow a `message_hash` to be signed again by different sets of signers,
+    /// e.g. when retrying a session in which some signers went offline.
+    ///
+    /// The permission is appended to the log as a [`LogEvent::ResigningAllowed`]
+    /// entry.
+    pub fn allow_resigning(&mut self, message_hash: [u8; 32]) {
+        self.append(message_hash, LogEvent::ResigningAllowed);
+    }
+
+    /// Check whether a `message_hash` may be signed again by different sets of
+    /// signers.
+    fn is_resignable(&self, message_hash: &[u8; 32]) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.message_hash == *message_hash && entry.event == LogEvent::ResigningAllowed
+        })
+    }
+
+    /// Check whether signing a `message_hash` with the given bridge `nonce`,
+    /// `signers`, and our `commitment` would conflict with this log.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if there is no conflict,
+    /// otherwise a string describing it.
+    pub fn check(
+        &self,
+        message_hash: &[u8; 32],
+        nonce: Option<u64>,
+        signers: &[u32],
+        commitment: &(AffinePoint, AffinePoint),
+    ) -> Result<(), &'static str>
+    {
+        let resignable = self.is_resignable(message_hash);
+
+        for entry in self.entries.iter() {
+            let (entry_nonce, entry_signers, entry_commitment) = match &entry.event {
+                LogEvent::Signed { nonce, signers, commitment, .. } => (nonce, signers, commitment),
+                LogEvent::ResigningAllowed => continue,
+            };
+
+            if nonce.is_some() && *entry_nonce == nonce && entry.message_hash != *message_hash {
+                return Err("Conflicting message for this bridge nonce");
+            }
+            if entry.message_hash == *message_hash && entry_signers != signers && !resignable {
+                return Err("Message was already signed by a different signer set");
+            }
+            // Reusing either nonce alone is as bad as reusing both.
+            if entry_commitment.0 == commitment.0 || entry_commitment.1 == commitment.1 {
+                return Err("Nonce commitment was already used");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify that every entry of this log commits to the previous one, and
+    /// that its hash matches its contents.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the [`SigningLog::head`], otherwise the
+    /// position of the first bad entry and a string describing what is wrong
+    /// with it.
+    pub fn audit(&self) -> Result<[u8; 32], (usize, &'static str)> {
+        let mut previous = [0u8; 32];
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            if entry.previous != previous {
+                return Err((i, "Entry does not follow the previous entry"));
+            }
+            if entry.hash != entry.compute_hash() {
+                return Err((i, "Entry hash does not match its contents"));
+            }
+            previous = entry.hash;
+        }
+
+        Ok(previous)
+    }
+
+    /// Append an entry for an `event` concerning a `message_hash` to this log.
+    fn append(&mut self, message_hash: [u8; 32], event: LogEvent) {
+        let mut entry = LogEntry {
+            previous: self.head(),
+            message_hash,
+            event,
+            hash: [0u8; 32],
+        };
+
+        entry.hash = entry.compute_hash();
+        self.entries.push(entry);
+    }
+
+    /// Serialise this log, e.g. to be persisted to disk after each signature.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("a serialisable log")
+    }
+
+    /// Deserialise a log persisted with [`SigningLog::to_bytes`], if it passes
+    /// its [`SigningLog::audit`].
+    pub fn from_bytes(bytes: &[u8]) -> Option<SigningLog> {
+        let log: SigningLog = bincode::deserialize(bytes).ok()?;
+
+        log.audit().ok()?;
+        Some(log)
+    }
+}
+
+impl SecretKey {
+    /// Compute this signer's [`PartialThresholdSignature`] as with
+    /// [`SecretKey::sign`], if it does not conflict with our [`SigningLog`],
+    /// and record it there.
+    ///
+    /// # Inputs
+    ///
+    /// * Our signing `log`,
+    /// * The aggregator's `session_id` for this signing session,
+    /// * The bridge `nonce` of the message, if it has one,
+    /// * The remaining inputs are as for [`SecretKey::sign`].
+    ///
+    /// # Returns
+    ///
+    /// A Result whose `Ok` value contains a [`PartialThresholdSignature`], which
+    /// should be sent to the aggregator.  Otherwise, its `Err` value contains
+    /// a string describing the conflict, or the error which occurred.
+    #[allow(clippy::too_many_arguments)]
+    pub fn sign_and_log(
+        &self,
+        log: &mut SigningLog,
+        session_id: &[u8],
+        nonce: Option<u64>,
+        message_hash: &[u8; 32],
+        group_key: &GroupKey,
+        my_secret_commitment_share_list: &mut SecretCommitmentShareList,
+        my_commitment_share_index: usize,
+        signers: &[Signer],
+    ) -> Result<PartialThresholdSignature, &'static str>
+    {
+        let me = signers.iter().find(|s| s.participant_index == self.index).ok_or("We are not a signer")?;
+        let commitment = my_secret_commitment_share_list.commitments.get(my_commitment_share_index)
+            .ok_or("Commitment share index out of bounds")?
+            .publish();
+
+        // Check and log the nonces which will actually be used, rather than
+        // whatever the aggregator listed for us.
+        if commitment != me.published_commitment_share {
+            return Err("Commitment share does not match the signers");
+        }
+
+        let mut indices: Vec<u32> = signers.iter().map(|s| s.participant_index).collect();
+
+        indices.sort_unstable();
+        log.check(message_hash, nonce, &indices, &commitment)?;
+
+        let partial_signature = self.sign(message_hash, group_key, my_secret_commitment_share_list,
+                                          my_commitment_share_index, signers)?;
+
+        log.append(*message_hash, LogEvent::Signed {
+            session_id: session_id.to_vec(),
+            nonce,
+            signers: indices,
+            commitment,
+            z: partial_signature.z,
+        });
+
+        Ok(partial_signature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::keygen::DealtParticipant;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::compute_message_hash;
+    use crate::signature::SignatureAggregator;
+
+    use rand::rngs::OsRng;
+
+    /// Ask the `participants` to sign a `message` for the bridge `nonce`, with
+    /// fresh commitments, and return the first one's answer.
+    fn request_signature(
+        participants: &[&DealtParticipant],
+        group_key: &GroupKey,
+        log: &mut SigningLog,
+        nonce: Option<u64>,
+        message: &[u8],
+    ) -> Result<PartialThresholdSignature, &'static str>
+    {
+        let params = Parameters { n: 3, t: participants.len() as u32 };
+        let mut aggregator = SignatureAggregator::new(params, *group_key, b"BRIDGE".to_vec(), message.to_vec());
+        let mut secret_comshares = Vec::new();
+
+        for p in participants.iter() {
+            let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, p.public_key.index, 1);
+
+            aggregator.include_signer(p.public_key.index, public_comshares.commitments[0], p.public_key.clone()).unwrap();
+            secret_comshares.push(secret);
+        }
+
+        let signers = aggregator.get_signers().clone();
+        let sk = participants[0].secret_key();
+
+        sk.sign_and_log(log, b"session", nonce, &compute_message_hash(b"BRIDGE", message), group_key,
+                        &mut secret_comshares[0], 0, &signers)
+    }
+
+    #[test]
+    fn refuses_equivocation() {
+        let (participants, package) = dealt_group(Parameters { n: 3, t: 2 });
+        let group_key = *package.group_key();
+        let (p1, p2, p3) = (&participants[0], &participants[1], &participants[2]);
+        let mut log = SigningLog::new();
+
+        assert!(request_signature(&[p1, p2], &group_key, &mut log, Some(7), b"withdraw 100").is_ok());
+        // Retrying with the same signers is fine.
+        assert!(request_signature(&[p1, p2], &group_key, &mut log, Some(7), b"withdraw 100").is_ok());
+        assert_eq!(request_signature(&[p1, p2], &group_key, &mut log, Some(7), b"withdraw 999").unwrap_err(),
+                   "Conflicting message for this bridge nonce");
+        assert_eq!(request_signature(&[p1, p3], &group_key, &mut log, Some(7), b"withdraw 100").unwrap_err(),
+                   "Message was already signed by a different signer set");
+        assert!(request_signature(&[p1, p3], &group_key, &mut log, Some(8), b"withdraw 200").is_ok());
+
+        log.allow_resigning(compute_message_hash(b"BRIDGE", b"withdraw 100"));
+        assert!(request_signature(&[p1, p3], &group_key, &mut log, Some(7), b"withdraw 100").is_ok());
+
+        // Refused requests are not logged, but the permission is.
+        assert_eq!(log.entries().len(), 5);
+        assert_eq!(log.entries()[3].event, LogEvent::ResigningAllowed);
+        assert_eq!(log.audit(), Ok(log.head()));
+
+        // A permission can't be slipped into the log afterwards.
+        let mut tampered = log.clone();
+        let message_hash = compute_message_hash(b"BRIDGE", b"withdraw 200");
+
+        tampered.entries.insert(0, LogEntry {
+            previous: [0u8; 32],
+            message_hash,
+            event: LogEvent::ResigningAllowed,
+            hash: [0u8; 32],
+        });
+        tampered.entries[0].hash = tampered.entries[0].compute_hash();
+
+        assert!(tampered.is_resignable(&message_hash));
+        assert_eq!(tampered.audit(), Err((1, "Entry does not follow the previous entry")));
+        assert!(SigningLog::from_bytes(&tampered.to_bytes()).is_none());
+    }
+
+    #[test]
+    fn refuses_reused_commitments() {
+        let (participants, package) = dealt_group(Parameters { n: 3, t: 2 });
+        let group_key = *package.group_key();
+        let message_hash = compute_message_hash(b"BRIDGE", b"withdraw 100");
+        let mut log = SigningLog::new();
+
+        let (p1_public, p1_secret) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p2_public, _) = generate_commitment_share_lists(&mut OsRng, 2, 1);
+        let mut signers = vec![
+            Signer { participant_index: 1, published_commitment_share: p1_public.commitments[0] },
+            Signer { participant_index: 2, published_commitment_share: p2_public.commitments[0] },
+        ];
+        let sk = participants[0].secret_key();
+
+        // Sign, then restore our secret commitments from a backup.
+        let backup = bincode::serialize(&p1_secret).unwrap();
+        let restore = || -> SecretCommitmentShareList { bincode::deserialize(&backup).unwrap() };
+
+        sk.sign_and_log(&mut log, b"session", None, &message_hash, &group_key, &mut restore(), 0, &signers).unwrap();
+        assert_eq!(sk.sign_and_log(&mut log, b"session", None, &message_hash, &group_key,
+                                   &mut restore(), 0, &signers).unwrap_err(),
+                   "Nonce commitment was already used");
+
+        // Nor can either of the nonces be reused on its own.
+        let (p1_other, _) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let used = p1_public.commitments[0];
+        let other = p1_other.commitments[0];
+
+        assert_eq!(log.check(&message_hash, None, &[1, 2], &(used.0, other.1)), Err("Nonce commitment was already used"));
+        assert_eq!(log.check(&message_hash, None, &[1, 2], &(other.0, used.1)), Err("Nonce commitment was already used"));
+        assert!(log.check(&message_hash, None, &[1, 2], &other).is_ok());
+
+        // An aggregator can't get the restored nonces used again by listing a
+        // fresh commitment for us in their place.
+        let mut substituted = signers.clone();
+
+        substituted[0].published_commitment_share = other;
+
+        assert_eq!(sk.sign_and_log(&mut log, b"session", None, &message_hash, &group_key,
+                                   &mut restore(), 0, &substituted).unwrap_err(),
+                   "Commitment share does not match the signers");
+        assert_eq!(log.entries().len(), 1);
+
+        signers.remove(0);
+        assert_eq!(sk.sign_and_log(&mut log, b"session", None, &message_hash, &group_key,
+                                   &mut restore(), 0, &signers).unwrap_err(),
+                   "We are not a signer");
+    }
+
+    #[test]
+    fn audit_detects_tampering() {
+        let (participants, package) = dealt_group(Parameters { n: 3, t: 2 });
+        let group_key = *package.group_key();
+        let (p1, p2) = (&participants[0], &participants[1]);
+        let mut log = SigningLog::new();
+
+        assert_eq!(log.audit(), Ok([0u8; 32]));
+
+        for nonce in 0..3 {
+            request_signature(&[p1, p2], &group_key, &mut log, Some(nonce), &nonce.to_be_bytes()).unwrap();
+        }
+
+        let restored = SigningLog::from_bytes(&log.to_bytes()).unwrap();
+
+        assert_eq!(restored.head(), log.head());
+        assert_eq!(restored.audit(), Ok(log.head()));
+
+        let mut tampered = log.clone();
+
+        tampered.entries[1].message_hash = [0u8; 32];
+        assert_eq!(tampered.audit(), Err((1, "Entry hash does not match its contents")));
+        assert!(SigningLog::from_bytes(&tampered.to_bytes()).is_none());
+
+        let mut truncated = log.clone();
+
+        truncated.entries.remove(1);
+        assert_eq!(truncated.audit(), Err((1, "Entry does not follow the previous entry")));
+    }
+}
@@ -0,0 +1,273 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! [BIP341] Taproot tweaking, for outputs controlled by the threshold group.
+//!
+//! The [`GroupKey`] \\(Y\\) is used as the Taproot internal key \\(P\\), which
+//! is \\(Y\\) in its even-Y form, and the output key is \\(Q = P + tG\\) with
+//! the tweak \\(t = \mathcal{H}\_{\mathtt{TapTweak}}(P\_x || r)\\), where
+//! \\(r\\) is the Merkle root of the script tree, if there is one.
+//!
+//! Since the tweak depends only upon public data, every participant computes
+//! the same [`TaprootOutput`], and tweaks their [`SecretKey`] \\(x\_i\\) to
+//! \\(\pm x\_i + t\\), negating it if \\(Y\\) has an odd y-coordinate, so that
+//! the tweaked shares are shares of the secret key for \\(Q\\).  Signing with
+//! the tweaked shares using [`SecretKey::sign_bip340`], with
+//! [`TaprootOutput::output_key`] as the group key, then gives a BIP340
+//! signature for a key-path spend of the output.  The parity of \\(Q\\) is
+//! dealt with by the BIP340 signing mode itself.
+//!
+//! [BIP341]: https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki
+
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::FieldBytes;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::elliptic_curve::AffineXCoordinate;
+use k256::elliptic_curve::PrimeField;
+
+use crate::bip340::has_even_y;
+use crate::bip340::tagged_hash;
+use crate::keygen::GroupKey;
+use crate::keygen::IndividualPublicKey;
+use crate::keygen::PublicKeyPackage;
+use crate::keygen::SecretKey;
+
+/// A Taproot output whose internal key is a [`GroupKey`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TaprootOutput {
+    /// The internal key \\(P\\), which is the group key in its even-Y form.
+    pub internal_key: GroupKey,
+    /// Whether the group key had to be negated to give the internal key.
+    pub internal_key_negated: bool,
+    /// The Merkle root of the script tree, or `None` for a key-path-only output.
+    pub merkle_root: Option<[u8; 32]>,
+    /// The tweak \\(t\\).
+    pub tweak: Scalar,
+    /// The output key \\(Q = P + tG\\), which need not have an even y-coordinate.
+    pub output_key: GroupKey,
+}
+
+impl TaprootOutput {
+    /// The 32-byte x-only output key, as committed to in the output.
+    pub fn output_key_bytes(&self) -> [u8; 32] {
+        self.output_key.to_bip340_bytes()
+    }
+
+    /// The parity of the y-coordinate of the output key, `0` if it is even
+    /// and `1` if it is odd, as needed in the control block of a script-path
+    /// spend.
+    pub fn output_key_parity(&self) -> u8 {
+        match has_even_y(&self.output_key.0) {
+            true => 0,
+            false => 1,
+        }
+    }
+
+    /// The segwit version 1 `scriptPubKey` of the output,
+    /// `OP_1 OP_PUSHBYTES_32` followed by the x-only output key.
+    pub fn script_pubkey(&self) -> [u8; 34] {
+        let mut script = [0u8; 34];
+
+        script[0] = 0x51;
+        script[1] = 0x20;
+        script[2..].copy_from_slice(&self.output_key_bytes());
+        script
+    }
+}
+
+impl GroupKey {
+    /// Use this key as the internal key of a Taproot output, committing to the
+    /// script tree with the given `merkle_root`, if any.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the [`TaprootOutput`], otherwise a string
+    /// describing the error, which happens with negligible probability.
+    pub fn taproot_output(&self, merkle_root: Option<&[u8; 32]>) -> Result<TaprootOutput, &'static str> {
+        let internal_key_negated = !has_even_y(&self.0);
+        let internal_key = match internal_key_negated {
+            true => -self.0,
+            false => self.0,
+        };
+        let internal_key_x = internal_key.x();
+        let hash = match merkle_root {
+            Some(root) => tagged_hash(b"TapTweak", &[&internal_key_x[..], &root[..]]),
+            None => tagged_hash(b"TapTweak", &[&internal_key_x[..]]),
+        };
+        let tweak: Scalar = Option::from(Scalar::from_repr(FieldBytes::from(hash)))
+            .ok_or("Invalid Taproot tweak")?;
+        let output_key = (ProjectivePoint::from(internal_key) + (ProjectivePoint::GENERATOR * tweak)).to_affine();
+
+        if output_key == AffinePoint::IDENTITY {
+            return Err("Invalid Taproot tweak");
+        }
+
+        Ok(TaprootOutput {
+            internal_key: GroupKey(internal_key),
+            internal_key_negated,
+            merkle_root: merkle_root.copied(),
+            tweak,
+            output_key: GroupKey(output_key),
+        })
+    }
+}
+
+impl SecretKey {
+    /// Tweak this secret key share for a [`TaprootOutput`] of the group key,
+    /// so that it is a share of the secret key for the output key.
+    pub fn taproot_tweak(&self, output: &TaprootOutput) -> SecretKey {
+        let key = match output.internal_key_negated {
+            true => -self.key,
+            false => self.key,
+        };
+
+        SecretKey { index: self.index, key: key + output.tweak }
+    }
+}
+
+impl IndividualPublicKey {
+    /// Tweak this public key share for a [`TaprootOutput`] of the group key,
+    /// consistently with [`SecretKey::taproot_tweak`].
+    pub fn taproot_tweak(&self, output: &TaprootOutput) -> IndividualPublicKey {
+        let sign = match output.internal_key_negated {
+            true => -Scalar::ONE,
+            false => Scalar::ONE,
+        };
+        let share = (ProjectivePoint::from(self.share) * sign) + (ProjectivePoint::GENERATOR * output.tweak);
+
+        IndividualPublicKey { index: self.index, share: share.to_affine() }
+    }
+}
+
+impl PublicKeyPackage {
+    /// Derive the public key material for a [`TaprootOutput`] of the group key
+    /// committing to the given `merkle_root`, e.g. to construct a
+    /// [`SignatureAggregator`](crate::signature::SignatureAggregator) for a
+    /// key-path spend.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is the tweaked package and the
+    /// [`TaprootOutput`], otherwise a string describing the error.
+    pub fn taproot_tweak(&self, merkle_root: Option<&[u8; 32]>) -> Result<(PublicKeyPackage, TaprootOutput), &'static str> {
+        let output = self.group_key().taproot_output(merkle_root)?;
+        let public_keys: Vec<IndividualPublicKey> = self.public_keys().iter().map(|key| key.taproot_tweak(&output)).collect();
+        let package = PublicKeyPackage::new(*self.parameters(), output.output_key, public_keys)?;
+
+        Ok((package, output))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+    use crate::precomputation::generate_commitment_share_lists;
+    use crate::signature::SignatureAggregator;
+
+    use rand::rngs::OsRng;
+
+    use secp256k1::Message;
+    use secp256k1::Secp256k1;
+    use secp256k1::XOnlyPublicKey;
+    use secp256k1::schnorr::Signature;
+
+    fn bytes32(s: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+
+        bytes.copy_from_slice(&hex::decode(s).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn bip341_test_vectors() {
+        // The first two scriptPubKey test vectors from BIP341.
+        let internal_key = GroupKey::from_bip340_bytes(bytes32("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d")).unwrap();
+        let output = internal_key.taproot_output(None).unwrap();
+
+        assert_eq!(output.tweak.to_bytes()[..], bytes32("b86e7be8f39bab32a6f2c0443abbc210f0edac0e2c53d501b36b64437d9c6c70")[..]);
+        assert_eq!(output.output_key_bytes(), bytes32("53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"));
+        assert!(!output.internal_key_negated);
+
+        let internal_key = GroupKey::from_bip340_bytes(bytes32("187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27")).unwrap();
+        let merkle_root = bytes32("5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21");
+        let output = internal_key.taproot_output(Some(&merkle_root)).unwrap();
+
+        assert_eq!(output.tweak.to_bytes()[..], bytes32("cbd8679ba636c1110ea247542cfbd964131a6be84f873f7f3b62a777528ed001")[..]);
+        assert_eq!(output.output_key_bytes(), bytes32("147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3"));
+        assert_eq!(output.script_pubkey()[..2], [0x51, 0x20]);
+
+        // The negation of a key has the same internal key, and so the same output.
+        assert_eq!(GroupKey(-internal_key.0).taproot_output(Some(&merkle_root)).unwrap().output_key, output.output_key);
+    }
+
+    #[test]
+    fn key_path_spend_with_tweaked_shares() {
+        let params = Parameters { n: 5, t: 3 };
+        let secp = Secp256k1::verification_only();
+        let merkle_root = [7u8; 32];
+
+        // Run several times, so that both parities of the group key and of the
+        // output key are (very likely to be) exercised.
+        for i in 0..8u8 {
+            let (participants, package) = dealt_group(params);
+            let root = match i % 2 {
+                0 => None,
+                _ => Some(&merkle_root),
+            };
+            let (tweaked_package, output) = package.taproot_tweak(root).unwrap();
+
+            // The output key must agree with libsecp256k1's.
+            let mut expected = XOnlyPublicKey::from_slice(&output.internal_key.to_bip340_bytes()).unwrap();
+            let parity = expected.tweak_add_assign(&secp, &output.tweak.to_bytes()).unwrap();
+
+            assert_eq!(expected.serialize(), output.output_key_bytes());
+            assert_eq!(parity as u8, output.output_key_parity());
+
+            let mut aggregator = SignatureAggregator::from_public_key_package(tweaked_package, b"CTX".to_vec(), vec![i]);
+            let mut secret_comshares = Vec::new();
+
+            for p in participants[1..4].iter() {
+                let (public_comshares, secret) = generate_commitment_share_lists(&mut OsRng, p.public_key.index, 1);
+
+                aggregator.try_include_signer(p.public_key.index, public_comshares.commitments[0]).unwrap();
+                secret_comshares.push(secret);
+            }
+
+            let signers = aggregator.get_signers().clone();
+            let message_hash = *aggregator.message_hash();
+
+            for (p, secret) in participants[1..4].iter().zip(secret_comshares.iter_mut()) {
+                let sk = p.secret_key();
+                let tweaked_sk = sk.taproot_tweak(&output);
+
+                assert_eq!(tweaked_sk.to_public().share, p.public_key.taproot_tweak(&output).share);
+
+                let partial = tweaked_sk.sign_bip340(&message_hash, &output.output_key, secret, 0, &signers).unwrap();
+
+                aggregator.include_bip340_partial_signature(partial).unwrap();
+            }
+
+            let signature = aggregator.finalize().unwrap().aggregate_bip340().unwrap();
+
+            assert!(signature.verify(&output.output_key, &message_hash).is_ok());
+            assert!(signature.verify(&output.internal_key, &message_hash).is_err());
+
+            let signature = Signature::from_slice(&signature.to_bytes()[..]).unwrap();
+            let message = Message::from_slice(&message_hash).unwrap();
+
+            assert!(secp.verify_schnorr(&signature, &message, &expected).is_ok());
+        }
+    }
+}
@@ -0,0 +1,439 @@
+// -*- mode: rust; -*-
+//
+// This file is part of dalek-frost.
+// Copyright (c) 2020 isis lovecruft
+// See LICENSE for licensing information.
+//
+// Authors:
+// - isis agora lovecruft <isis@patternsinthevoid.net>
+
+//! A threshold verifiable random function, e.g. for a randomness beacon.
+//!
+//! The output of the VRF on an `input` is \\(\Gamma = xH\\), where \\(x\\) is
+//! the group's secret key and \\(H\\) is the input [hashed to the
+//! curve](hash_to_curve), together with a [`NizkOfDiscreteLogEquality`] that
+//! \\(\Gamma\\) and the [`GroupKey`] \\(Y = xG\\) have the same discrete
+//! logarithm.  Since \\(\Gamma\\) is uniquely determined by the key and the
+//! input, no participant, nor the combiner, can bias the [`VrfOutput::randomness`].
+//!
+//! It is computed by \\(t\\) participants in two rounds:
+//!
+//! 1. Each participant publishes a [`VrfCommitment`] to their share
+//!    \\(\Gamma\_i = x\_i H\\) and to a fresh nonce \\(k\_i\\),
+//!    \\(A\_i = k\_i G\\) and \\(B\_i = k\_i H\\), with [`SecretKey::vrf_commit`].
+//! 2. Given everyone's commitments, each participant computes the joint
+//!    \\(\Gamma = \sum\_i \lambda\_i \Gamma\_i\\), \\(A = \sum\_i \lambda\_i A\_i\\),
+//!    \\(B = \sum\_i \lambda\_i B\_i\\) and challenge
+//!    \\(c = \mathcal{H}(Y, H, \Gamma, A, B)\\), and responds with
+//!    \\(s\_i = k\_i - c \cdot x\_i\\), using [`SecretKey::vrf_respond`].
+//!
+//! Each \\((A\_i, B\_i, s\_i)\\) is then a DLEQ proof, under the joint challenge,
+//! that \\(\Gamma\_i\\) is consistent with the participant's
+//! [`IndividualPublicKey`], so [`combine_vrf_responses`] can identify anyone
+//! who cheated, and \\(s = \sum\_i \lambda\_i s\_i\\) completes the proof
+//! \\((A, B, s)\\) for \\(\Gamma\\) under the [`GroupKey`].
+//!
+//! [`IndividualPublicKey`]: crate::keygen::IndividualPublicKey
+
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use k256::AffinePoint;
+use k256::ProjectivePoint;
+use k256::Scalar;
+use k256::Secp256k1;
+use k256::elliptic_curve::Field;
+use k256::elliptic_curve::group::GroupEncoding;
+use k256::elliptic_curve::hash2curve::ExpandMsgXmd;
+use k256::elliptic_curve::hash2curve::GroupDigest;
+
+use rand::CryptoRng;
+use rand::Rng;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use sha2::Sha256;
+use sha3::Digest;
+use sha3::Keccak256;
+
+use zeroize::Zeroize;
+
+use crate::keygen::GroupKey;
+use crate::keygen::PublicKeyPackage;
+use crate::keygen::SecretKey;
+use crate::nizk::NizkOfDiscreteLogEquality;
+use crate::nizk::dleq_challenge;
+use crate::signature::calculate_lagrange_coefficients;
+
+/// The domain separation tag for hashing VRF inputs to the curve, following
+/// the naming convention of RFC 9380.
+pub const VRF_HASH_TO_CURVE_DST: &[u8] = b"FROST-secp256k1-VRF-V01-CS01-with-secp256k1_XMD:SHA-256_SSWU_RO_";
+
+/// The domain separator for the [`NizkOfDiscreteLogEquality`] of a [`VrfOutput`].
+const DLEQ_DOMAIN: &[u8] = b"FROST-VRF";
+
+/// Hash an `input` to a point on the curve with the `secp256k1_XMD:SHA-256_SSWU_RO_`
+/// suite of RFC 9380, under the [`VRF_HASH_TO_CURVE_DST`].
+pub fn hash_to_curve(input: &[u8]) -> AffinePoint {
+    // This cannot fail, since the DST is short enough and the output length fixed.
+    Secp256k1::hash_from_bytes::<ExpandMsgXmd<Sha256>>(&[input], VRF_HASH_TO_CURVE_DST)
+        .expect("valid hash-to-curve parameters")
+        .to_affine()
+}
+
+/// The output of the VRF on some input, with a proof that it is correct.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct VrfOutput {
+    /// The point \\(\Gamma = xH\\).
+    pub gamma: AffinePoint,
+    /// A proof that \\(\Gamma\\) and the [`GroupKey`] have the same discrete logarithm.
+    pub proof: NizkOfDiscreteLogEquality,
+}
+
+impl VrfOutput {
+    /// Verify that this is the VRF output on an `input` for the `group_key`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is an empty tuple if the output is correct,
+    /// otherwise a string describing why it is not.
+    pub fn verify(&self, group_key: &GroupKey, input: &[u8]) -> Result<(), &'static str> {
+        self.proof.verify(DLEQ_DOMAIN, &group_key.0, &hash_to_curve(input), &self.gamma)
+            .or(Err("Invalid VRF output"))
+    }
+
+    /// The 32 bytes of randomness, \\(\mathrm{Keccak256}(\Gamma)\\), derived
+    /// from this output.  This must only be used once the output has been
+    /// verified.
+    pub fn randomness(&self) -> [u8; 32] {
+        let mut h = Keccak256::default();
+
+        h.update(b"FROST-VRF-OUTPUT");
+        h.update(self.gamma.to_bytes());
+
+        h.finalize().into()
+    }
+}
+
+/// A participant's first-round commitment to their share of a VRF output.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct VrfCommitment {
+    /// The participant index of the committer.
+    pub index: u32,
+    /// The share \\(\Gamma\_i = x\_i H\\).
+    pub gamma: AffinePoint,
+    /// The nonce commitment \\(A\_i = k\_i G\\).
+    pub A: AffinePoint,
+    /// The nonce commitment \\(B\_i = k\_i H\\).
+    pub B: AffinePoint,
+}
+
+/// The secret nonce for a participant's [`VrfCommitment`], which must be used
+/// for exactly one [`SecretKey::vrf_respond`].
+#[derive(Debug, Zeroize)]
+#[zeroize(drop)]
+pub struct VrfNonce {
+    /// The nonce \\(k\_i\\).
+    k: Scalar,
+    /// The input hashed to the curve, \\(H\\).
+    #[zeroize(skip)]
+    input_point: AffinePoint,
+    /// The commitment which was published for this nonce.
+    #[zeroize(skip)]
+    commitment: VrfCommitment,
+}
+
+/// A participant's second-round response, \\(s\_i = k\_i - c \cdot x\_i\\).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct VrfResponse {
+    /// The participant index of the responder.
+    pub index: u32,
+    /// The response \\(s\_i\\).
+    pub s: Scalar,
+}
+
+/// The joint values of a VRF computation, which every participant and the
+/// combiner compute from all the [`VrfCommitment`]s.
+struct JointCommitment {
+    /// Each participant's Lagrange coefficient \\(\lambda\_i\\).
+    lambdas: HashMap<u32, Scalar>,
+    /// The output \\(\Gamma\\).
+    gamma: AffinePoint,
+    /// The joint nonce commitment \\(A\\).
+    A: AffinePoint,
+    /// The joint nonce commitment \\(B\\).
+    B: AffinePoint,
+    /// The challenge \\(c\\).
+    c: Scalar,
+}
+
+impl JointCommitment {
+    /// Interpolate the joint output and nonce commitments, and compute the challenge.
+    fn new(
+        group_key: &GroupKey,
+        input_point: &AffinePoint,
+        commitments: &[VrfCommitment],
+    ) -> Result<JointCommitment, &'static str>
+    {
+        let indices: Vec<u32> = commitments.iter().map(|c| c.index).collect();
+        let mut lambdas = HashMap::with_capacity(commitments.len());
+        let mut gamma = ProjectivePoint::IDENTITY;
+        let mut A = ProjectivePoint::IDENTITY;
+        let mut B = ProjectivePoint::IDENTITY;
+
+        if indices.contains(&0) {
+            return Err("Participant index out of range");
+        }
+        if indices.iter().enumerate().any(|(i, index)| indices[..i].contains(index)) {
+            return Err("Duplicate commitments");
+        }
+
+        for commitment in commitments.iter() {
+            let lambda = calculate_lagrange_coefficients(&commitment.index, &indices)?;
+
+            gamma += commitment.gamma * lambda;
+            A += commitment.A * lambda;
+            B += commitment.B * lambda;
+            lambdas.insert(commitment.index, lambda);
+        }
+
+        let (gamma, A, B) = (gamma.to_affine(), A.to_affine(), B.to_affine());
+        let c = dleq_challenge(DLEQ_DOMAIN, &group_key.0, input_point, &gamma, &A, &B);
+
+        Ok(JointCommitment { lambdas, gamma, A, B, c })
+    }
+}
+
+impl SecretKey {
+    /// Commit to our share of the VRF output on an `input`, in the first
+    /// round of the threshold VRF.
+    ///
+    /// # Returns
+    ///
+    /// Our secret [`VrfNonce`], which must be kept for
+    /// [`SecretKey::vrf_respond`], and our [`VrfCommitment`], which should be
+    /// sent to the combiner.
+    pub fn vrf_commit(&self, input: &[u8], mut csprng: impl Rng + CryptoRng) -> (VrfNonce, VrfCommitment) {
+        let input_point = hash_to_curve(input);
+        let k = Scalar::random(&mut csprng);
+        let commitment = VrfCommitment {
+            index: self.index,
+            gamma: (input_point * self.key).to_affine(),
+            A: (AffinePoint::GENERATOR * k).to_affine(),
+            B: (input_point * k).to_affine(),
+        };
+
+        (VrfNonce { k, input_point, commitment }, commitment)
+    }
+
+    /// Respond to everyone's [`VrfCommitment`]s, as given by the combiner, in
+    /// the second round of the threshold VRF.  The `nonce` is consumed, so
+    /// that it can never be used twice.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` whose `Ok` value is our [`VrfResponse`], which should be
+    /// sent to the combiner, otherwise a string describing the error.
+    pub fn vrf_respond(
+        &self,
+        nonce: VrfNonce,
+        group_key: &GroupKey,
+        commitments: &[VrfCommitment],
+    ) -> Result<VrfResponse, &'static str>
+    {
+        if nonce.commitment.index != self.index {
+            return Err("Nonce does not belong to us");
+        }
+        if !commitments.contains(&nonce.commitment) {
+            return Err("Our commitment is missing or was altered");
+        }
+
+        let joint = JointCommitment::new(group_key, &nonce.input_point, commitments)?;
+
+        Ok(VrfResponse { index: self.index, s: nonce.k - (joint.c * self.key) })
+    }
+}
+
+/// Combine the participants' [`VrfCommitment`]s and [`VrfResponse`]s for an
+/// `input` into the [`VrfOutput`].
+///
+/// # Returns
+///
+/// A `Result` whose `Ok` value is the verified [`VrfOutput`], otherwise a
+/// `HashMap<u32, &'static str>` containing the participant indices of the
+/// misbehaving participants and a description of their misbehaviour.  A key
+/// of `0` indicates that there were too few commitments, or that they could
+/// not be combined.
+pub fn combine_vrf_responses(
+    public_key_package: &PublicKeyPackage,
+    input: &[u8],
+    commitments: &[VrfCommitment],
+    responses: &[VrfResponse],
+) -> Result<VrfOutput, HashMap<u32, &'static str>>
+{
+    let mut misbehaving_participants: HashMap<u32, &'static str> = HashMap::new();
+
+    if commitments.len() < public_key_package.parameters().t as usize {
+        misbehaving_participants.insert(0, "Not enough commitments");
+        return Err(misbehaving_participants);
+    }
+
+    let group_key = public_key_package.group_key();
+    let input_point = hash_to_curve(input);
+    let joint = match JointCommitment::new(group_key, &input_point, commitments) {
+        Ok(joint) => joint,
+        Err(error) => {
+            misbehaving_participants.insert(0, error);
+            return Err(misbehaving_participants);
+        },
+    };
+    let mut s = Scalar::ZERO;
+
+    for commitment in commitments.iter() {
+        let public_key = match public_key_package.public_key(commitment.index) {
+            Some(public_key) => public_key,
+            None => {
+                misbehaving_participants.insert(commitment.index, "Participant index out of range");
+                continue;
+            },
+        };
+        let response = match responses.iter().find(|r| r.index == commitment.index) {
+            Some(response) => response,
+            None => {
+                misbehaving_participants.insert(commitment.index, "Missing response");
+                continue;
+            },
+        };
+
+        // Check s_i G + c Y_i = A_i and s_i H + c Gamma_i = B_i.
+        let A = (AffinePoint::GENERATOR * response.s) + (public_key.share * joint.c);
+        let B = (input_point * response.s) + (commitment.gamma * joint.c);
+
+        if A.to_affine() != commitment.A || B.to_affine() != commitment.B {
+            misbehaving_participants.insert(commitment.index, "Incorrect VRF share");
+            continue;
+        }
+        // This unwrap() cannot fail, since there's a coefficient for every commitment.
+        s += response.s * joint.lambdas.get(&commitment.index).unwrap();
+    }
+
+    if ! misbehaving_participants.is_empty() {
+        return Err(misbehaving_participants);
+    }
+
+    let output = VrfOutput {
+        gamma: joint.gamma,
+        proof: NizkOfDiscreteLogEquality { A: joint.A, B: joint.B, s },
+    };
+
+    if let Err(error) = output.verify(group_key, input) {
+        misbehaving_participants.insert(0, error);
+        return Err(misbehaving_participants);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::keygen::dealt_group;
+    use crate::parameters::Parameters;
+
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+    use rand::rngs::OsRng;
+
+    fn evaluate(
+        secret_keys: &[&SecretKey],
+        package: &PublicKeyPackage,
+        input: &[u8],
+    ) -> Result<VrfOutput, HashMap<u32, &'static str>>
+    {
+        let (nonces, commitments): (Vec<VrfNonce>, Vec<VrfCommitment>) =
+            secret_keys.iter().map(|sk| sk.vrf_commit(input, OsRng)).unzip();
+        let responses: Vec<VrfResponse> = secret_keys.iter().zip(nonces)
+            .map(|(sk, nonce)| sk.vrf_respond(nonce, package.group_key(), &commitments).unwrap())
+            .collect();
+
+        combine_vrf_responses(package, input, &commitments, &responses)
+    }
+
+    #[test]
+    fn hash_to_curve_test_vector() {
+        // RFC 9380, Appendix J.8.1, with msg = "".
+        let point = Secp256k1::hash_from_bytes::<ExpandMsgXmd<Sha256>>(
+            &[b""], b"QUUX-V01-CS02-with-secp256k1_XMD:SHA-256_SSWU_RO_").unwrap().to_affine();
+
+        assert_eq!(hex::encode(point.to_encoded_point(false).as_bytes()),
+                   "04c1cae290e291aee617ebaef1be6d73861479c48b841eaba9b7b5852ddfeb1346\
+                    64fa678e07ae116126f08b022a94af6de15985c996c3a91b64c406a960e51067");
+    }
+
+    #[test]
+    fn threshold_vrf_is_unique() {
+        let (participants, package) = dealt_group(Parameters { n: 11, t: 7 });
+        let secret_keys: Vec<SecretKey> = participants.iter().map(|p| p.secret_key()).collect();
+        let first: Vec<&SecretKey> = secret_keys[..7].iter().collect();
+        let last: Vec<&SecretKey> = secret_keys[4..].iter().rev().collect();
+
+        let output = evaluate(&first, &package, b"epoch 42").unwrap();
+        let again = evaluate(&last, &package, b"epoch 42").unwrap();
+
+        assert!(output.verify(package.group_key(), b"epoch 42").is_ok());
+        assert!(again.verify(package.group_key(), b"epoch 42").is_ok());
+        assert!(output.verify(package.group_key(), b"epoch 43").is_err());
+
+        // Different signers, and different nonces, give the same randomness.
+        assert_eq!(output.gamma, again.gamma);
+        assert_eq!(output.randomness(), again.randomness());
+        assert_ne!(output.randomness(), evaluate(&first, &package, b"epoch 43").unwrap().randomness());
+
+        let restored: VrfOutput = bincode::deserialize(&bincode::serialize(&output).unwrap()).unwrap();
+
+        assert_eq!(restored, output);
+    }
+
+    #[test]
+    fn cheating_participants_are_identified() {
+        let (participants, package) = dealt_group(Parameters { n: 11, t: 7 });
+        let secret_keys: Vec<SecretKey> = participants.iter().map(|p| p.secret_key()).collect();
+        let input = b"fee lottery";
+        let (nonces, mut commitments): (Vec<VrfNonce>, Vec<VrfCommitment>) =
+            secret_keys[..8].iter().map(|sk| sk.vrf_commit(input, OsRng)).unzip();
+
+        // Participant 3 commits to a share of a different output.
+        commitments[2].gamma = (ProjectivePoint::from(commitments[2].gamma) + ProjectivePoint::GENERATOR).to_affine();
+
+        let mut responses = Vec::new();
+
+        for (sk, nonce) in secret_keys[..8].iter().zip(nonces) {
+            match sk.vrf_respond(nonce, package.group_key(), &commitments) {
+                Ok(response) => responses.push(response),
+                Err(error) => assert_eq!((sk.index, error), (3, "Our commitment is missing or was altered")),
+            }
+        }
+
+        // So the honest participant 3 refused, and a cheater responds for them.
+        responses.push(VrfResponse { index: 3, s: Scalar::random(&mut OsRng) });
+        responses[0].s += Scalar::ONE;
+
+        let misbehaving = combine_vrf_responses(&package, input, &commitments, &responses).unwrap_err();
+
+        assert_eq!(misbehaving.len(), 2);
+        assert_eq!(misbehaving.get(&1), Some(&"Incorrect VRF share"));
+        assert_eq!(misbehaving.get(&3), Some(&"Incorrect VRF share"));
+
+        let misbehaving = combine_vrf_responses(&package, input, &commitments[..6], &responses).unwrap_err();
+
+        assert_eq!(misbehaving.get(&0), Some(&"Not enough commitments"));
+
+        commitments[7] = commitments[6];
+
+        let misbehaving = combine_vrf_responses(&package, input, &commitments, &responses).unwrap_err();
+
+        assert_eq!(misbehaving.get(&0), Some(&"Duplicate commitments"));
+    }
+}
@@ -118,9 +118,9 @@ fn signing_and_verification_3_out_of_5() {
 
     let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-    aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-    aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into());
-    aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into());
+    aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+    aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into()).unwrap();
+    aggregator.include_signer(4, p4_public_comshares.commitments[0], (&p4_sk).into()).unwrap();
 
     let signers = aggregator.get_signers();
     let message_hash = compute_message_hash(&context[..], &message[..]);
@@ -129,9 +129,9 @@ fn signing_and_verification_3_out_of_5() {
     let p3_partial = p3_sk.sign(&message_hash, &group_key, &mut p3_secret_comshares, 0, signers).unwrap();
     let p4_partial = p4_sk.sign(&message_hash, &group_key, &mut p4_secret_comshares, 0, signers).unwrap();
 
-    aggregator.include_partial_signature(p1_partial);
-    aggregator.include_partial_signature(p3_partial);
-    aggregator.include_partial_signature(p4_partial);
+    aggregator.include_partial_signature(p1_partial).unwrap();
+    aggregator.include_partial_signature(p3_partial).unwrap();
+    aggregator.include_partial_signature(p4_partial).unwrap();
 
     let aggregator = aggregator.finalize().unwrap();
     let threshold_signature = aggregator.aggregate().unwrap();
@@ -196,8 +196,8 @@ fn signing_and_verification_with_schnorr_bip340_2_out_of_3() {
 
     let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
 
-    aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into());
-    aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into());
+    aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+    aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into()).unwrap();
 
     let signers = aggregator.get_signers();
     let message_hash = compute_message_hash(&context[..], &message[..]);
@@ -205,8 +205,8 @@ fn signing_and_verification_with_schnorr_bip340_2_out_of_3() {
     let p1_partial = p1_sk.sign(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
     let p3_partial = p3_sk.sign(&message_hash, &group_key, &mut p3_secret_comshares, 0, signers).unwrap();
 
-    aggregator.include_partial_signature(p1_partial);
-    aggregator.include_partial_signature(p3_partial);
+    aggregator.include_partial_signature(p1_partial).unwrap();
+    aggregator.include_partial_signature(p3_partial).unwrap();
 
     let aggregator = aggregator.finalize().unwrap();
     let threshold_signature = aggregator.aggregate().unwrap();
@@ -240,3 +240,89 @@ fn signing_and_verification_with_schnorr_bip340_2_out_of_3() {
         assert!(false);
     }
 }
+
+#[cfg(feature = "std")]
+#[test]
+fn signing_and_verification_bip340_mode_2_out_of_3() {
+    use secp256k1::{Secp256k1, schnorr::Signature, Message, XOnlyPublicKey};
+
+    let params = Parameters { n: 3, t: 2 };
+
+    // Run several times, so that both parities of the group key and of the
+    // group commitment are (very likely to be) exercised.
+    for _ in 0..8 {
+        let (p1, p1coeffs) = Participant::new(&params, 1);
+        let (p2, p2coeffs) = Participant::new(&params, 2);
+        let (p3, p3coeffs) = Participant::new(&params, 3);
+
+        let mut p1_other_participants: Vec<Participant> = vec!(p2.clone(), p3.clone());
+        let p1_state = DistributedKeyGeneration::<_>::new(&params,
+                                                          &p1.index,
+                                                          &p1coeffs,
+                                                          &mut p1_other_participants).unwrap();
+        let p1_their_secret_shares = p1_state.their_secret_shares().unwrap();
+
+        let mut p2_other_participants: Vec<Participant> = vec!(p1.clone(), p3.clone());
+        let p2_state = DistributedKeyGeneration::<>::new(&params,
+                                                         &p2.index,
+                                                         &p2coeffs,
+                                                         &mut p2_other_participants).unwrap();
+        let p2_their_secret_shares = p2_state.their_secret_shares().unwrap();
+
+        let mut p3_other_participants: Vec<Participant> = vec!(p1.clone(), p2.clone());
+        let p3_state = DistributedKeyGeneration::<_>::new(&params,
+                                                          &p3.index,
+                                                          &p3coeffs,
+                                                          &mut p3_other_participants).unwrap();
+        let p3_their_secret_shares = p3_state.their_secret_shares().unwrap();
+
+        let p1_my_secret_shares = vec!(p2_their_secret_shares[0].clone(), // XXX FIXME indexing
+                                       p3_their_secret_shares[0].clone());
+
+        let p2_my_secret_shares = vec!(p1_their_secret_shares[0].clone(),
+                                       p3_their_secret_shares[1].clone());
+
+        let p3_my_secret_shares = vec!(p1_their_secret_shares[1].clone(),
+                                       p2_their_secret_shares[1].clone());
+
+        let p1_state = p1_state.to_round_two(p1_my_secret_shares).unwrap();
+        let p2_state = p2_state.to_round_two(p2_my_secret_shares).unwrap();
+        let p3_state = p3_state.to_round_two(p3_my_secret_shares).unwrap();
+
+        let (group_key, p1_sk) = p1_state.finish(&p1.public_key().unwrap()).unwrap();
+        let (_, _p2_sk) = p2_state.finish(&p2.public_key().unwrap()).unwrap();
+        let (_, p3_sk) = p3_state.finish(&p3.public_key().unwrap()).unwrap();
+
+        let context = b"CONTEXT STRING STOLEN FROM DALEK TEST SUITE";
+        let message = b"This is a test of the tsunami alert system. This is only a test.";
+        let (p1_public_comshares, mut p1_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 1, 1);
+        let (p3_public_comshares, mut p3_secret_comshares) = generate_commitment_share_lists(&mut OsRng, 3, 1);
+
+        let mut aggregator = SignatureAggregator::new(params, group_key, context.to_vec(), message.to_vec());
+
+        aggregator.include_signer(1, p1_public_comshares.commitments[0], (&p1_sk).into()).unwrap();
+        aggregator.include_signer(3, p3_public_comshares.commitments[0], (&p3_sk).into()).unwrap();
+
+        let signers = aggregator.get_signers();
+        let message_hash = compute_message_hash(&context[..], &message[..]);
+
+        let p1_partial = p1_sk.sign_bip340(&message_hash, &group_key, &mut p1_secret_comshares, 0, signers).unwrap();
+        let p3_partial = p3_sk.sign_bip340(&message_hash, &group_key, &mut p3_secret_comshares, 0, signers).unwrap();
+
+        aggregator.include_bip340_partial_signature(p1_partial).unwrap();
+        aggregator.include_bip340_partial_signature(p3_partial).unwrap();
+
+        let aggregator = aggregator.finalize().unwrap();
+        let bip340_signature = aggregator.aggregate_bip340().unwrap();
+
+        assert!(bip340_signature.verify(&group_key, &message_hash).is_ok());
+
+        let signature = Signature::from_slice(&bip340_signature.to_bytes()[..]).unwrap();
+        let message = Message::from_slice(&message_hash).unwrap();
+        let pk = XOnlyPublicKey::from_slice(&group_key.to_bip340_bytes()[..]).unwrap();
+
+        let schnorr = Secp256k1::verification_only();
+
+        assert!(schnorr.verify_schnorr(&signature, &message, &pk).is_ok());
+    }
+}